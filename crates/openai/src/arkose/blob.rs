@@ -1,13 +1,13 @@
-use super::Type;
+use super::registry::Blob;
 use crate::with_context;
 use serde::Deserialize;
 
 /// Get arkose blob payload
-pub async fn get_blob(typed: Type, identifier: Option<String>) -> anyhow::Result<Option<String>> {
-    match (typed, identifier) {
-        (Type::GPT4, Some(identifier)) => {
+pub async fn get_blob(blob: Blob, identifier: Option<String>) -> anyhow::Result<Option<String>> {
+    match (blob, identifier) {
+        (Blob::Dx, Some(identifier)) => {
             #[derive(Deserialize)]
-            struct Blob {
+            struct Dx {
                 data: String,
             }
             let resp = with_context!(arkose_client)
@@ -16,11 +16,11 @@ pub async fn get_blob(typed: Type, identifier: Option<String>) -> anyhow::Result
                 .send()
                 .await?
                 .error_for_status()?
-                .json::<Blob>()
+                .json::<Dx>()
                 .await?;
             Ok(Some(resp.data))
         }
-        (Type::SignUp, Some(identifier)) => Ok(Some(identifier)),
+        (Blob::Identifier, Some(identifier)) => Ok(Some(identifier)),
         _ => Ok(None),
     }
 }
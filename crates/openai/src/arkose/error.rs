@@ -16,6 +16,8 @@ pub enum ArkoseError {
     NoSolverAvailable,
     #[error("Solver task error: {0}")]
     SolverTaskError(String),
+    #[error("Solver {0} skipped, {1} limit reached ({2})")]
+    SolverLimitReached(String, &'static str, u64),
     #[error("Error creating arkose session error ({0:?})")]
     CreateSessionError(anyhow::Error),
     #[error("Invalid funcaptcha error")]
@@ -55,6 +57,10 @@ pub enum ArkoseError {
     FuncaptchaNotSolvedError(String),
     #[error("Unknown game type ({0})")]
     UnknownGameType(u32),
+    #[error("Unknown api breaker ({0})")]
+    UnknownApiBreaker(String),
+    #[error("Unknown funcaptcha variant ({0})")]
+    UnknownVariant(String),
     #[error("Unknown challenge type key: ({0})")]
     UnknownChallengeTypeKey(String),
     #[error("Unknow challenge")]
@@ -0,0 +1,527 @@
+use super::murmur::murmurhash3_x64_128;
+use base64::engine::general_purpose;
+use base64::Engine;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde_json::{json, Value};
+
+const MAC_FONTS: &str = "Andale Mono,Arial,Arial Black,Arial Hebrew,Arial Narrow,Arial Rounded MT Bold,Arial Unicode MS,Comic Sans MS,Courier,Courier New,Geneva,Georgia,Helvetica,Helvetica Neue,Impact,LUCIDA GRANDE,Microsoft Sans Serif,Monaco,Palatino,Tahoma,Times,Times New Roman,Trebuchet MS,Verdana,Wingdings,Wingdings 2,Wingdings 3";
+const WINDOWS_FONTS: &str = "Arial,Arial Black,Arial Narrow,Calibri,Cambria,Cambria Math,Comic Sans MS,Consolas,Courier,Courier New,Georgia,Helvetica,Impact,Lucida Console,Lucida Sans Unicode,Microsoft Sans Serif,MS Gothic,MS PGothic,MS Sans Serif,MS Serif,Palatino Linotype,Segoe Print,Segoe Script,Segoe UI,Segoe UI Light,Segoe UI Semibold,Segoe UI Symbol,Tahoma,Times,Times New Roman,Trebuchet MS,Verdana,Wingdings";
+const LINUX_FONTS: &str = "Arial,Courier,Courier New,Helvetica,Times,Times New Roman";
+
+const OPENGL_EXTENSIONS: &str = "ANGLE_instanced_arrays;EXT_blend_minmax;EXT_color_buffer_half_float;EXT_disjoint_timer_query;EXT_float_blend;EXT_frag_depth;EXT_shader_texture_lod;EXT_texture_compression_rgtc;EXT_texture_filter_anisotropic;EXT_sRGB;KHR_parallel_shader_compile;OES_element_index_uint;OES_fbo_render_mipmap;OES_standard_derivatives;OES_texture_float;OES_texture_float_linear;OES_texture_half_float;OES_texture_half_float_linear;OES_vertex_array_object;WEBGL_color_buffer_float;WEBGL_compressed_texture_s3tc;WEBGL_compressed_texture_s3tc_srgb;WEBGL_debug_renderer_info;WEBGL_debug_shaders;WEBGL_depth_texture;WEBGL_draw_buffers;WEBGL_lose_context;WEBGL_multi_draw";
+const D3D11_EXTENSIONS: &str = "ANGLE_instanced_arrays;EXT_blend_minmax;EXT_color_buffer_half_float;EXT_disjoint_timer_query;EXT_float_blend;EXT_frag_depth;EXT_shader_texture_lod;EXT_texture_compression_bptc;EXT_texture_compression_rgtc;EXT_texture_filter_anisotropic;EXT_sRGB;KHR_parallel_shader_compile;OES_element_index_uint;OES_fbo_render_mipmap;OES_standard_derivatives;OES_texture_float;OES_texture_float_linear;OES_texture_half_float;OES_texture_half_float_linear;OES_vertex_array_object;WEBGL_color_buffer_float;WEBGL_compressed_texture_s3tc;WEBGL_compressed_texture_s3tc_srgb;WEBGL_debug_renderer_info;WEBGL_debug_shaders;WEBGL_depth_texture;WEBGL_draw_buffers;WEBGL_lose_context;WEBGL_multi_draw";
+
+const PLUGINS: &str =
+    "Chrome PDF Viewer,Chromium PDF Viewer,Microsoft Edge PDF Viewer,PDF Viewer,WebKit built-in PDF";
+
+struct Gpu {
+    vendor: &'static str,
+    renderer: &'static str,
+    extensions: &'static str,
+    point_size_range: &'static str,
+    max_params: &'static str,
+}
+
+struct Os {
+    /// `navigator.platform`
+    platform: &'static str,
+    /// `sec-ch-ua-platform`
+    ch_platform: &'static str,
+    /// Screen height taken by the menu bar or taskbar
+    taskbar: u32,
+    pixel_ratios: &'static [&'static str],
+    screens: &'static [(u32, u32)],
+    gpus: &'static [Gpu],
+    fonts: &'static str,
+}
+
+struct Language {
+    language: &'static str,
+    languages: &'static str,
+    accept_language: &'static str,
+    /// `Date.getTimezoneOffset()` of the regions using the language
+    timezone_offsets: &'static [i32],
+}
+
+static MACOS: Os = Os {
+    platform: "MacIntel",
+    ch_platform: "macOS",
+    taskbar: 25,
+    pixel_ratios: &["2", "2", "1"],
+    screens: &[
+        (1440, 900),
+        (1512, 982),
+        (1680, 1050),
+        (1728, 1117),
+        (1920, 1080),
+        (2560, 1440),
+    ],
+    gpus: &[
+        Gpu {
+            vendor: "Google Inc. (Apple)",
+            renderer: "ANGLE (Apple, Apple M1, OpenGL 4.1)",
+            extensions: OPENGL_EXTENSIONS,
+            point_size_range: "[1, 511]",
+            max_params: "16,32,16384,1024,16384,16,16384,30,16,16,1024",
+        },
+        Gpu {
+            vendor: "Google Inc. (Apple)",
+            renderer: "ANGLE (Apple, Apple M2, OpenGL 4.1)",
+            extensions: OPENGL_EXTENSIONS,
+            point_size_range: "[1, 511]",
+            max_params: "16,32,16384,1024,16384,16,16384,30,16,16,1024",
+        },
+        Gpu {
+            vendor: "Google Inc. (Intel Inc.)",
+            renderer: "ANGLE (Intel Inc., Intel(R) UHD Graphics 630, OpenGL 4.1)",
+            extensions: OPENGL_EXTENSIONS,
+            point_size_range: "[1, 255.875]",
+            max_params: "16,32,16384,1024,16384,16,16384,15,16,16,1024",
+        },
+        Gpu {
+            vendor: "Google Inc. (ATI Technologies Inc.)",
+            renderer:
+                "ANGLE (ATI Technologies Inc., AMD Radeon Pro 5500M OpenGL Engine, OpenGL 4.1)",
+            extensions: OPENGL_EXTENSIONS,
+            point_size_range: "[1, 511]",
+            max_params: "16,32,16384,1024,16384,16,16384,30,16,16,1024",
+        },
+    ],
+    fonts: MAC_FONTS,
+};
+
+static WINDOWS: Os = Os {
+    platform: "Win32",
+    ch_platform: "Windows",
+    taskbar: 40,
+    pixel_ratios: &["1", "1", "1.25", "1.5"],
+    screens: &[
+        (1366, 768),
+        (1536, 864),
+        (1600, 900),
+        (1920, 1080),
+        (2560, 1440),
+    ],
+    gpus: &[
+        Gpu {
+            vendor: "Google Inc. (NVIDIA)",
+            renderer: "ANGLE (NVIDIA, NVIDIA GeForce RTX 3060 Direct3D11 vs_5_0 ps_5_0, D3D11)",
+            extensions: D3D11_EXTENSIONS,
+            point_size_range: "[1, 1024]",
+            max_params: "16,32,16384,1024,16384,16,16384,30,16,16,4095",
+        },
+        Gpu {
+            vendor: "Google Inc. (NVIDIA)",
+            renderer:
+                "ANGLE (NVIDIA, NVIDIA GeForce GTX 1660 SUPER Direct3D11 vs_5_0 ps_5_0, D3D11)",
+            extensions: D3D11_EXTENSIONS,
+            point_size_range: "[1, 1024]",
+            max_params: "16,32,16384,1024,16384,16,16384,30,16,16,4095",
+        },
+        Gpu {
+            vendor: "Google Inc. (Intel)",
+            renderer: "ANGLE (Intel, Intel(R) UHD Graphics 620 Direct3D11 vs_5_0 ps_5_0, D3D11)",
+            extensions: D3D11_EXTENSIONS,
+            point_size_range: "[1, 1024]",
+            max_params: "16,32,16384,1024,16384,16,16384,30,16,16,4095",
+        },
+        Gpu {
+            vendor: "Google Inc. (AMD)",
+            renderer: "ANGLE (AMD, AMD Radeon RX 6600 Direct3D11 vs_5_0 ps_5_0, D3D11)",
+            extensions: D3D11_EXTENSIONS,
+            point_size_range: "[1, 1024]",
+            max_params: "16,32,16384,1024,16384,16,16384,30,16,16,4095",
+        },
+    ],
+    fonts: WINDOWS_FONTS,
+};
+
+static LINUX: Os = Os {
+    platform: "Linux x86_64",
+    ch_platform: "Linux",
+    taskbar: 27,
+    pixel_ratios: &["1"],
+    screens: &[(1366, 768), (1920, 1080), (2560, 1440)],
+    gpus: &[
+        Gpu {
+            vendor: "Google Inc. (Intel)",
+            renderer: "ANGLE (Intel, Mesa Intel(R) UHD Graphics 630 (CFL GT2), OpenGL 4.6)",
+            extensions: OPENGL_EXTENSIONS,
+            point_size_range: "[1, 2047]",
+            max_params: "16,32,16384,1024,16384,16,16384,32,16,16,1024",
+        },
+        Gpu {
+            vendor: "Google Inc. (NVIDIA Corporation)",
+            renderer: "ANGLE (NVIDIA Corporation, NVIDIA GeForce GTX 1080/PCIe/SSE2, OpenGL 4.5.0)",
+            extensions: OPENGL_EXTENSIONS,
+            point_size_range: "[1, 2047]",
+            max_params: "16,32,16384,1024,32768,16,32768,31,16,16,1024",
+        },
+    ],
+    fonts: LINUX_FONTS,
+};
+
+static LANGUAGES: &[Language] = &[
+    Language {
+        language: "en-US",
+        languages: "en-US,en",
+        accept_language: "en-US,en;q=0.9",
+        timezone_offsets: &[240, 300, 360, 420, 480],
+    },
+    Language {
+        language: "en-GB",
+        languages: "en-GB,en",
+        accept_language: "en-GB,en;q=0.9",
+        timezone_offsets: &[0, -60],
+    },
+    Language {
+        language: "zh-CN",
+        languages: "zh-CN,zh,en",
+        accept_language: "zh-CN,zh;q=0.9,en;q=0.8",
+        timezone_offsets: &[-480],
+    },
+    Language {
+        language: "de-DE",
+        languages: "de-DE,de,en",
+        accept_language: "de-DE,de;q=0.9,en;q=0.8",
+        timezone_offsets: &[-60, -120],
+    },
+    Language {
+        language: "ja-JP",
+        languages: "ja-JP,ja,en",
+        accept_language: "ja-JP,ja;q=0.9,en;q=0.8",
+        timezone_offsets: &[-540],
+    },
+];
+
+/// Browser fingerprint used to build the Arkose `bda` payload.
+///
+/// The profile is derived from the key (identity or egress), the same key always gets the same
+/// profile. The operating system follows the user agent, everything else is picked from tables
+/// of the operating system so the fields stay consistent with each other.
+pub struct Fingerprint {
+    os: &'static Os,
+    gpu: &'static Gpu,
+    language: &'static Language,
+    screen: (u32, u32),
+    window: (u32, u32),
+    pixel_ratio: &'static str,
+    timezone_offset: i32,
+    hardware_concurrency: u32,
+    device_memory: u32,
+    canvas: i32,
+    audio: f64,
+    downlink: f64,
+    rtt: u32,
+    dark_mode: bool,
+    dnt: &'static str,
+    history_length: u32,
+    /// Chromium brand and major version, none for other browsers
+    brand: Option<(&'static str, String)>,
+}
+
+impl Fingerprint {
+    pub fn new(key: &str, user_agent: &str) -> Self {
+        let (seed, _) = murmurhash3_x64_128(key.as_bytes(), 0);
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let os = if user_agent.contains("Macintosh") {
+            &MACOS
+        } else if user_agent.contains("Windows") {
+            &WINDOWS
+        } else if user_agent.contains("Linux") || user_agent.contains("X11") {
+            &LINUX
+        } else {
+            [&MACOS, &WINDOWS, &LINUX]
+                .choose(&mut rng)
+                .copied()
+                .unwrap_or(&MACOS)
+        };
+
+        let gpu = os.gpus.choose(&mut rng).unwrap_or(&os.gpus[0]);
+        let language = LANGUAGES.choose(&mut rng).unwrap_or(&LANGUAGES[0]);
+        let screen = *os.screens.choose(&mut rng).unwrap_or(&os.screens[0]);
+        let window = (
+            rng.gen_range(screen.0 * 7 / 10..=screen.0),
+            rng.gen_range(screen.1 * 7 / 10..=screen.1 - os.taskbar),
+        );
+
+        Self {
+            os,
+            gpu,
+            language,
+            screen,
+            window,
+            pixel_ratio: *os.pixel_ratios.choose(&mut rng).unwrap_or(&"1"),
+            timezone_offset: *language.timezone_offsets.choose(&mut rng).unwrap_or(&0),
+            hardware_concurrency: *[4, 8, 8, 10, 12, 16].choose(&mut rng).unwrap_or(&8),
+            device_memory: *[4, 8, 8].choose(&mut rng).unwrap_or(&8),
+            canvas: rng.gen(),
+            audio: 124.04347 + rng.gen_range(0.0..0.00001),
+            downlink: (rng.gen_range(1.0..10.0_f64) * 100.0).round() / 100.0,
+            rtt: rng.gen_range(1..=6) * 50,
+            dark_mode: rng.gen(),
+            dnt: *["unknown", "1"].choose(&mut rng).unwrap_or(&"unknown"),
+            history_length: rng.gen_range(1..=15),
+            brand: chromium_version(user_agent).map(|version| {
+                let brand = if user_agent.contains("Edg/") {
+                    "Microsoft Edge"
+                } else {
+                    "Google Chrome"
+                };
+                (brand, version.to_owned())
+            }),
+        }
+    }
+
+    /// `Accept-Language` header matching `navigator.languages`
+    pub fn accept_language(&self) -> &'static str {
+        self.language.accept_language
+    }
+
+    /// `sec-ch-ua` header, none for browsers without client hints
+    pub fn sec_ch_ua(&self) -> Option<String> {
+        self.brand.as_ref().map(|(brand, version)| {
+            format!("\"Not_A Brand\";v=\"8\", \"Chromium\";v=\"{version}\", \"{brand}\";v=\"{version}\"")
+        })
+    }
+
+    /// `sec-ch-ua-platform` header
+    pub fn sec_ch_ua_platform(&self) -> String {
+        format!("\"{}\"", self.os.ch_platform)
+    }
+
+    /// Fill the fingerprint into the bda template and recompute the hashes derived from it,
+    /// the page specific fields (origins, location) of the template are kept
+    pub fn bda(&self, template: &str, timestamp: u64) -> anyhow::Result<String> {
+        let mut entries = serde_json::from_str::<Vec<Value>>(template)?;
+
+        let fe = self.fe();
+        set(
+            &mut entries,
+            "n",
+            json!(general_purpose::STANDARD.encode(timestamp.to_string())),
+        );
+        // `f` also covers raw canvas data the payload does not carry, the template value is kept
+        set(&mut entries, "ife_hash", json!(ife_hash(&fe)));
+        set(&mut entries, "fe", json!(fe));
+        set(
+            &mut entries,
+            "jsbd",
+            json!(format!(
+                r#"{{"HL":{},"NCE":true,"DT":"","NWD":"false","DOTO":1,"DMTO":1}}"#,
+                self.history_length
+            )),
+        );
+
+        if let Some(enhanced_fp) = entries
+            .iter_mut()
+            .find(|entry| entry["key"] == "enhanced_fp")
+            .and_then(|entry| entry["value"].as_array_mut())
+        {
+            self.enhanced_fp(enhanced_fp);
+        }
+
+        Ok(serde_json::to_string(&entries)?)
+    }
+
+    fn fe(&self) -> Vec<String> {
+        let (width, height) = self.screen;
+        vec![
+            format!("DNT:{}", self.dnt),
+            format!("L:{}", self.language.language),
+            "D:24".to_owned(),
+            format!("PR:{}", self.pixel_ratio),
+            format!("S:{width},{height}"),
+            format!("AS:{width},{}", height - self.os.taskbar),
+            format!("TO:{}", self.timezone_offset),
+            "SS:true".to_owned(),
+            "LS:true".to_owned(),
+            "IDB:true".to_owned(),
+            "B:false".to_owned(),
+            "ODB:true".to_owned(),
+            "CPUC:unknown".to_owned(),
+            format!("PK:{}", self.os.platform),
+            format!("CFP:{}", self.canvas),
+            "FR:false".to_owned(),
+            "FOS:false".to_owned(),
+            "FB:false".to_owned(),
+            format!("JSF:{}", self.os.fonts),
+            format!("P:{PLUGINS}"),
+            "T:0,false,false".to_owned(),
+            format!("H:{}", self.hardware_concurrency),
+            "SWF:false".to_owned(),
+        ]
+    }
+
+    fn enhanced_fp(&self, entries: &mut [Value]) {
+        let gpu = self.gpu;
+        set(entries, "webgl_extensions", json!(gpu.extensions));
+        set(
+            entries,
+            "webgl_extensions_hash",
+            json!(x64hash128(gpu.extensions, 0)),
+        );
+        set(
+            entries,
+            "webgl_version",
+            json!("WebGL 1.0 (OpenGL ES 2.0 Chromium)"),
+        );
+        set(
+            entries,
+            "webgl_shading_language_version",
+            json!("WebGL GLSL ES 1.0 (OpenGL ES GLSL ES 1.0 Chromium)"),
+        );
+        set(
+            entries,
+            "webgl_aliased_point_size_range",
+            json!(gpu.point_size_range),
+        );
+        set(entries, "webgl_max_params", json!(gpu.max_params));
+        set(entries, "webgl_unmasked_vendor", json!(gpu.vendor));
+        set(entries, "webgl_unmasked_renderer", json!(gpu.renderer));
+        // `webgl_hash_webgl` covers raw WebGL data the payload does not carry, the template
+        // value is kept
+
+        match &self.brand {
+            Some((brand, _)) => {
+                set(
+                    entries,
+                    "user_agent_data_brands",
+                    json!(format!("Not_A Brand,Chromium,{brand}")),
+                );
+                set(entries, "user_agent_data_mobile", json!(false));
+            }
+            None => {
+                set(entries, "user_agent_data_brands", Value::Null);
+                set(entries, "user_agent_data_mobile", Value::Null);
+            }
+        }
+        set(
+            entries,
+            "navigator_connection_downlink",
+            json!(self.downlink),
+        );
+        set(entries, "network_info_rtt", json!(self.rtt));
+        set(
+            entries,
+            "navigator_device_memory",
+            json!(self.device_memory),
+        );
+        set(
+            entries,
+            "navigator_languages",
+            json!(self.language.languages),
+        );
+        set(entries, "window_outer_width", json!(self.window.0));
+        set(entries, "window_outer_height", json!(self.window.1));
+        set(entries, "media_query_dark_mode", json!(self.dark_mode));
+        set(entries, "audio_fingerprint", json!(self.audio.to_string()));
+    }
+}
+
+/// Set the value of the entry with the key, entries missing from the template are left out
+fn set(entries: &mut [Value], key: &str, value: Value) {
+    if let Some(entry) = entries.iter_mut().find(|entry| entry["key"] == key) {
+        entry["value"] = value;
+    }
+}
+
+/// `ife_hash` digest of the `fe` entries, same as the browser
+fn ife_hash(fe: &[String]) -> String {
+    x64hash128(&fe.join(", "), 38)
+}
+
+/// Hex digest compatible with the `x64hash128` of the Arkose client
+fn x64hash128(s: &str, seed: u64) -> String {
+    let (h1, h2) = murmurhash3_x64_128(s.as_bytes(), seed);
+    format!("{h1:016x}{h2:016x}")
+}
+
+fn chromium_version(user_agent: &str) -> Option<&str> {
+    let (_, version) = user_agent.split_once("Chrome/")?;
+    version.split('.').next().filter(|v| !v.is_empty())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const USER_AGENT: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
+
+    /// Browser recorded templates
+    const TEMPLATES: [&str; 4] = [
+        super::super::GPT3_BX,
+        super::super::GPT4_BX,
+        super::super::AUTH_BX,
+        super::super::PLATFORM_BX,
+    ];
+
+    fn entries(bda: &str) -> Vec<Value> {
+        serde_json::from_str::<Vec<Value>>(bda).unwrap()
+    }
+
+    fn value<'a>(entries: &'a [Value], key: &str) -> &'a Value {
+        &entries.iter().find(|entry| entry["key"] == key).unwrap()["value"]
+    }
+
+    fn fe(entries: &[Value]) -> Vec<String> {
+        serde_json::from_value(value(entries, "fe").clone()).unwrap()
+    }
+
+    #[test]
+    fn test_same_key_same_bda() {
+        let bda = |key: &str| {
+            Fingerprint::new(key, USER_AGENT)
+                .bda(super::super::GPT4_BX, 1700000000)
+                .unwrap()
+        };
+        assert_eq!(bda("user@example.com"), bda("user@example.com"));
+        assert_ne!(bda("user@example.com"), bda("2001:db8::1"));
+    }
+
+    #[test]
+    fn test_hashes_match_browser() {
+        for template in TEMPLATES {
+            let entries = entries(template);
+            let enhanced_fp = value(&entries, "enhanced_fp").as_array().unwrap();
+            let extensions = value(enhanced_fp, "webgl_extensions").as_str().unwrap();
+
+            assert_eq!(json!(ife_hash(&fe(&entries))), *value(&entries, "ife_hash"));
+            assert_eq!(
+                json!(x64hash128(extensions, 0)),
+                *value(enhanced_fp, "webgl_extensions_hash")
+            );
+        }
+    }
+
+    #[test]
+    fn test_bda_fields() {
+        let bda = Fingerprint::new("user@example.com", USER_AGENT)
+            .bda(super::super::GPT4_BX, 1700000000)
+            .unwrap();
+        let entries = entries(&bda);
+        let enhanced_fp = value(&entries, "enhanced_fp").as_array().unwrap();
+        let fe = fe(&entries);
+
+        assert!(fe.contains(&"PK:MacIntel".to_owned()));
+        assert_eq!(json!(ife_hash(&fe)), *value(&entries, "ife_hash"));
+
+        // Hashes over raw canvas and WebGL data keep the recorded browser values
+        let template = self::entries(super::super::GPT4_BX);
+        assert_eq!(*value(&entries, "f"), *value(&template, "f"));
+        assert_eq!(
+            *value(enhanced_fp, "webgl_hash_webgl"),
+            *value(
+                value(&template, "enhanced_fp").as_array().unwrap(),
+                "webgl_hash_webgl"
+            )
+        );
+        assert_eq!(
+            *value(&entries, "n"),
+            json!(general_purpose::STANDARD.encode("1700000000"))
+        );
+    }
+}
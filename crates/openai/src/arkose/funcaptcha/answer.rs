@@ -0,0 +1,107 @@
+use super::model::FunCaptcha;
+use crate::context::database;
+use crate::{debug, warn};
+use native_db::*;
+use native_model::{native_model, Model};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+
+static DATABASE_BUILDER: OnceLock<DatabaseBuilder> = OnceLock::new();
+static DATABASE: OnceLock<Option<Database<'static>>> = OnceLock::new();
+
+/// Verified answer of a funcaptcha image
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[native_model(id = 3, version = 1)]
+#[native_db]
+struct Answer {
+    /// `{game_variant}|{image sha256}`
+    #[primary_key]
+    key: String,
+    answer: i32,
+    /// Times the answer was accepted
+    solved: u64,
+}
+
+fn database() -> Option<&'static Database<'static>> {
+    DATABASE
+        .get_or_init(|| {
+            database::open(
+                &DATABASE_BUILDER,
+                |builder| builder.define::<Answer>().expect("define table failed"),
+                "funcaptcha.db",
+            )
+        })
+        .as_ref()
+}
+
+/// Answer key of the image, repeated images of the same game variant share the key
+pub(crate) fn key(fun: &FunCaptcha) -> String {
+    let hash = Sha256::digest(fun.image.as_bytes());
+    format!("{}|{:x}", fun.game_variant, hash)
+}
+
+/// Get the stored answers of the keys
+pub(crate) fn get(keys: &[String]) -> Vec<Option<i32>> {
+    let r = match database().map(|db| db.r_transaction()) {
+        Some(Ok(r)) => r,
+        _ => return vec![None; keys.len()],
+    };
+
+    keys.iter()
+        .map(|key| {
+            r.get()
+                .primary::<Answer>(key.clone())
+                .ok()
+                .flatten()
+                .map(|answer| answer.answer)
+        })
+        .collect()
+}
+
+/// Store the answers of a verified solved challenge
+pub(crate) fn insert(answers: &[(String, i32)]) {
+    if let Some(db) = database() {
+        if let Some(err) = write(db, answers).err() {
+            warn!("Failed to store funcaptcha answers: {err}")
+        }
+    }
+}
+
+/// Remove the answers of a rejected challenge
+pub(crate) fn remove(keys: &[String]) {
+    if let Some(db) = database() {
+        if let Some(err) = delete(db, keys).err() {
+            warn!("Failed to remove funcaptcha answers: {err}")
+        }
+    }
+}
+
+fn write(db: &Database<'static>, answers: &[(String, i32)]) -> anyhow::Result<()> {
+    let rw = db.rw_transaction()?;
+    for (key, answer) in answers {
+        let solved = match rw.get().primary::<Answer>(key.clone())? {
+            Some(old) if old.answer.eq(answer) => old.solved,
+            _ => 0,
+        };
+        rw.insert(Answer {
+            key: key.to_owned(),
+            answer: *answer,
+            solved: solved + 1,
+        })?;
+    }
+    rw.commit()?;
+    Ok(())
+}
+
+fn delete(db: &Database<'static>, keys: &[String]) -> anyhow::Result<()> {
+    let rw = db.rw_transaction()?;
+    for key in keys {
+        if let Some(answer) = rw.get().primary::<Answer>(key.clone())? {
+            debug!("Remove rejected funcaptcha answer: {key}");
+            rw.remove(answer)?;
+        }
+    }
+    rw.commit()?;
+    Ok(())
+}
@@ -1,3 +1,9 @@
+//! Encode funcaptcha answers with the `api_breaker` of the challenge. The transforms,
+//! `method_*` included, follow the `apiBreakers` table of the noahcoolboy/funcaptcha JS
+//! client, which mirrors Arkose's game core. `snapshots/api_breaker.js` keeps that table in
+//! JS, the replay tests check the guesses against it.
+
+use rand::rngs::mock::StepRng;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
@@ -6,9 +12,22 @@ use crate::arkose::{error::ArkoseError, funcaptcha::get_time_stamp};
 
 use super::model::ApiBreaker;
 
+fn handle_v1_game3_api_breaker(key: &str, c: Loc) -> anyhow::Result<serde_json::Value> {
+    let answer = match key {
+        "" | "default" => json!(c),
+        "method_1" => json!({ "x": c.y, "y": c.x }),
+        "method_2" => json!({ "x": c.x, "y": (c.y + c.x) * c.x }),
+        "method_3" => json!({ "a": c.x, "b": c.y }),
+        "method_4" => json!([c.x, c.y]),
+        "method_5" => json!([c.y.sqrt(), c.x.sqrt()]),
+        _ => anyhow::bail!(ArkoseError::UnknownApiBreaker(format!("v1 {key}"))),
+    };
+    Ok(answer)
+}
+
 /// Rust does not have dynamic types, and there may be errors in calculations.
-fn handle_v2_game3_api_breaker_value(key: &str, c: Loc) -> Loc {
-    return match key {
+fn handle_v2_game3_api_breaker_value(key: &str, c: Loc) -> anyhow::Result<Loc> {
+    let loc = match key {
         "alpha" => Loc {
             x: c.x,
             y: (c.y + c.x) * c.x,
@@ -45,31 +64,51 @@ fn handle_v2_game3_api_breaker_value(key: &str, c: Loc) -> Loc {
             px: c.px,
             py: c.py,
         },
-        _ => c,
+        "method_1" => c,
+        "method_2" => Loc {
+            x: c.y,
+            y: (c.y + c.x) * c.y,
+            px: c.px,
+            py: c.py,
+        },
+        "method_3" => Loc {
+            x: c.x.sqrt(),
+            y: c.y.sqrt(),
+            px: c.px,
+            py: c.py,
+        },
+        _ => anyhow::bail!(ArkoseError::UnknownApiBreaker(format!("v2 value {key}"))),
     };
+    Ok(loc)
 }
 
-fn handle_v2_game4_api_breaker_value(key: &str, answer: i32) -> i32 {
-    return match key {
-        "alpha" => {
-            let y_value_str = answer.to_string();
-            let combined_str = y_value_str + &1.to_string();
-            let combined_int = combined_str.parse::<i32>().unwrap();
-            combined_int - 2
-        }
+/// Keeps the JS number semantics, `zeta` may turn the index into a fraction
+fn handle_v2_game4_api_breaker_value(key: &str, answer: f64) -> anyhow::Result<f64> {
+    let answer = match key {
+        "alpha" => format!("{answer}1").parse::<f64>()? - 2.0,
         "beta" => -answer,
-        "delta" => 7 * answer,
-        "gamma" => 3 * (3 - answer),
-        "epsilon" => 2 * answer,
+        "delta" => 7.0 * answer,
+        "gamma" => 3.0 * (3.0 - answer),
+        "epsilon" => 2.0 * answer,
         "zeta" => {
-            if answer != 0 {
-                100 / answer
+            if answer != 0.0 {
+                100.0 / answer
             } else {
                 answer
             }
         }
-        _ => answer,
+        _ => anyhow::bail!(ArkoseError::UnknownApiBreaker(format!("v2 value {key}"))),
     };
+    Ok(answer)
+}
+
+/// JSON number as JS prints it, integral values have no fraction
+fn number(value: f64) -> serde_json::Value {
+    if value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+        json!(value as i64)
+    } else {
+        json!(value)
+    }
 }
 
 /// Rust does not have dynamic types, and there may be errors in calculations.
@@ -80,62 +119,65 @@ fn handle_v2_game3_api_breaker_key(key: &str, c: Loc) -> anyhow::Result<serde_js
         "delta" => json!([1, c.x, 2, c.y, 3, c.px, 4, c.py]),
         "epsilon" => json!({ "x": c.x, "y": c.y, "px": c.px, "py": c.py }),
         "zeta" => json!([c.x, [c.y, [c.px, [c.py]]]]),
-        "gamma" | _ => json!(vec![
+        "gamma" => json!(vec![
             c.x.to_string(),
             c.y.to_string(),
             c.px.to_string(),
             c.py.to_string()
         ]
         .join(" ")),
+        "method_1" => json!({ "a": c.x, "b": c.y, "px": c.px, "py": c.py }),
+        "method_2" => json!([c.x, c.y]),
+        "method_3" => json!([c.y, c.x]),
+        _ => anyhow::bail!(ArkoseError::UnknownApiBreaker(format!("v2 key {key}"))),
     };
     Ok(answer)
 }
 
-fn handle_v2_game4_api_breaker_key(key: &str, answer: i32) -> anyhow::Result<serde_json::Value> {
+fn handle_v2_game4_api_breaker_key(
+    key: &str,
+    answer: f64,
+    rng: &mut impl Rng,
+) -> anyhow::Result<serde_json::Value> {
     let answer = match key {
-        "alpha" => json!([
-            rand::thread_rng().gen_range(0..100),
-            answer,
-            rand::thread_rng().gen_range(0..100)
-        ]),
+        "alpha" => json!([rng.gen_range(0..100), number(answer), rng.gen_range(0..100)]),
         "beta" => json!({
-            "size": 50 - answer,
-            "id": answer,
-            "limit": 10 * answer,
+            "size": number(50.0 - answer),
+            "id": number(answer),
+            "limit": number(10.0 * answer),
             "req_timestamp": get_time_stamp()?,
         }),
-        "delta" => json!({ "index": answer }),
+        "delta" => json!({ "index": number(answer) }),
         "epsilon" => {
-            let mut arr: Vec<i32> = Vec::new();
-            let len = rand::thread_rng().gen_range(0..5) + 1;
-            let rand = rand::thread_rng().gen_range(0..len);
+            let mut arr = Vec::new();
+            let len = rng.gen_range(0..5) + 1;
+            let rand = rng.gen_range(0..len);
             for i in 0..len {
                 if i == rand {
-                    arr.push(answer);
+                    arr.push(number(answer));
                 } else {
-                    arr.push(rand::thread_rng().gen_range(0..10));
+                    arr.push(json!(rng.gen_range(0..10)));
                 }
             }
-            arr.push(rand);
+            arr.push(json!(rand));
             json!(arr)
         }
         "zeta" => {
-            let array_len = rand::thread_rng().gen_range(0..5) + 1;
-            let mut vec = vec![0; array_len];
-            vec.push(answer);
+            let array_len = rng.gen_range(0..5) + 1;
+            let mut vec = vec![json!(0); array_len];
+            vec.push(number(answer));
             json!(vec)
         }
-        "gamma" | _ => json!(answer),
+        "gamma" => number(answer),
+        _ => anyhow::bail!(ArkoseError::UnknownApiBreaker(format!("v2 key {key}"))),
     };
 
     Ok(answer)
 }
 
-fn tile_to_loc(tile: i32) -> Loc {
-    let x_click =
-        (tile % 3) * 100 + (tile % 3) * 3 + 3 + 10 + (rand::random::<f64>() * 80.0) as i32;
-    let y_click =
-        (tile / 3) * 100 + (tile / 3) * 3 + 3 + 10 + (rand::random::<f64>() * 80.0) as i32;
+fn tile_to_loc(tile: i32, rng: &mut impl Rng) -> Loc {
+    let x_click = (tile % 3) * 100 + (tile % 3) * 3 + 3 + 10 + (rng.gen::<f64>() * 80.0) as i32;
+    let y_click = (tile / 3) * 100 + (tile / 3) * 3 + 3 + 10 + (rng.gen::<f64>() * 80.0) as i32;
     Loc {
         x: x_click as f64,
         y: y_click as f64,
@@ -152,35 +194,47 @@ struct Loc {
     py: f64,
 }
 
+/// Encode the answer as the guess payload, the random parts (click offset, padding)
+/// come from the rng
 pub(super) fn hanlde_answer(
     v2: bool,
     game_type: u32,
     api_breaker: &ApiBreaker,
-    mut answer: i32,
+    answer: i32,
+    rng: &mut impl Rng,
 ) -> anyhow::Result<serde_json::Value> {
     if !v2 && game_type == 3 {
-        let loc = tile_to_loc(answer);
-        return Ok(json!(loc));
+        let loc = tile_to_loc(answer, rng);
+        return handle_v1_game3_api_breaker(&api_breaker.key, loc);
     }
 
     if !v2 && game_type == 4 {
-        return Ok(json!({ "index": answer }));
+        return match api_breaker.key.as_str() {
+            "" | "default" => Ok(json!({ "index": answer })),
+            key => anyhow::bail!(ArkoseError::UnknownApiBreaker(format!("v1 {key}"))),
+        };
     }
 
     if v2 && game_type == 3 {
-        let mut loc = tile_to_loc(answer);
+        let mut loc = tile_to_loc(answer, rng);
         for v in &api_breaker.value {
-            loc = handle_v2_game3_api_breaker_value(&v, loc)
+            loc = handle_v2_game3_api_breaker_value(&v, loc)?
         }
         return handle_v2_game3_api_breaker_key(&api_breaker.key, loc);
     }
 
     if v2 && game_type == 4 {
+        let mut answer = answer as f64;
         for v in &api_breaker.value {
-            answer = handle_v2_game4_api_breaker_value(&v, answer)
+            answer = handle_v2_game4_api_breaker_value(&v, answer)?
         }
-        return handle_v2_game4_api_breaker_key(&api_breaker.key, answer);
+        return handle_v2_game4_api_breaker_key(&api_breaker.key, answer, rng);
     }
 
     anyhow::bail!(ArkoseError::UnknownGameType(game_type))
 }
+
+/// Check the game type and api breaker are known by encoding a sample answer
+pub(super) fn check(v2: bool, game_type: u32, api_breaker: &ApiBreaker) -> anyhow::Result<()> {
+    hanlde_answer(v2, game_type, api_breaker, 0, &mut StepRng::new(0, 0)).map(|_| ())
+}
@@ -0,0 +1,253 @@
+use crate::warn;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Label of a saved funcaptcha image, written next to the image as `{session}_{index}.json`
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Sidecar {
+    pub instructions: String,
+    pub game_variant: String,
+    /// Submitted answer index, the first tile of a multi-tile round
+    pub answer: i32,
+    /// Every tile of a multi-tile round
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tiles: Vec<i32>,
+    /// Solver that answered the image, `store` for stored answers
+    pub solver: String,
+    /// Whether the challenge session was accepted
+    pub accepted: bool,
+    /// Unix timestamp
+    pub time: i64,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Format {
+    Csv,
+    Jsonl,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "jsonl" => Ok(Self::Jsonl),
+            _ => anyhow::bail!("Only support `csv` / `jsonl` format"),
+        }
+    }
+}
+
+/// Exported sample, the image path is relative to the output directory
+#[derive(Serialize)]
+struct Record {
+    image: String,
+    #[serde(flatten)]
+    sidecar: Sidecar,
+}
+
+/// Result of the dataset export
+#[derive(Debug, Default)]
+pub struct Summary {
+    /// Exported samples
+    pub exported: usize,
+    /// Images skipped for a malformed label
+    pub skipped: usize,
+}
+
+/// Export the saved images and labels as `images/{game_variant}/*.png` plus `labels.{csv,jsonl}`
+pub fn export(
+    dir: &Path,
+    out: &Path,
+    format: Format,
+    accepted_only: bool,
+) -> anyhow::Result<Summary> {
+    let images_dir = out.join("images");
+    fs::create_dir_all(&images_dir)?;
+
+    let mut records = Vec::new();
+    let mut skipped = 0;
+    for variant_dir in read_dir_sorted(dir)? {
+        if !variant_dir.is_dir() {
+            continue;
+        }
+
+        for image in read_dir_sorted(&variant_dir)? {
+            if image.extension().map(|ext| ext != "png").unwrap_or(true) {
+                continue;
+            }
+
+            let sidecar = match read_sidecar(&image, &variant_dir) {
+                Ok(Some(sidecar)) => sidecar,
+                Ok(None) => continue,
+                Err(err) => {
+                    warn!(
+                        "Skip image with malformed label: {}: {err}",
+                        image.display()
+                    );
+                    skipped += 1;
+                    continue;
+                }
+            };
+
+            if accepted_only && !sidecar.accepted {
+                continue;
+            }
+
+            let (variant, filename) = match (variant_dir.file_name(), image.file_name()) {
+                (Some(variant), Some(filename)) => (variant, filename),
+                _ => continue,
+            };
+            let target_dir = images_dir.join(variant);
+            fs::create_dir_all(&target_dir)?;
+            fs::copy(&image, target_dir.join(filename))?;
+
+            records.push(Record {
+                image: format!(
+                    "images/{}/{}",
+                    variant.to_string_lossy(),
+                    filename.to_string_lossy()
+                ),
+                sidecar,
+            });
+        }
+    }
+
+    match format {
+        Format::Csv => write_csv(&out.join("labels.csv"), &records)?,
+        Format::Jsonl => write_jsonl(&out.join("labels.jsonl"), &records)?,
+    }
+
+    Ok(Summary {
+        exported: records.len(),
+        skipped,
+    })
+}
+
+fn read_dir_sorted(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut paths = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .collect::<Vec<PathBuf>>();
+    paths.sort();
+    Ok(paths)
+}
+
+/// Read the label of the image, images saved before the sidecar only have the answer in `.txt`
+fn read_sidecar(image: &Path, variant_dir: &Path) -> anyhow::Result<Option<Sidecar>> {
+    let json = image.with_extension("json");
+    if json.is_file() {
+        return Ok(Some(serde_json::from_slice::<Sidecar>(&fs::read(json)?)?));
+    }
+
+    let txt = image.with_extension("txt");
+    if txt.is_file() {
+        let answer = match fs::read_to_string(txt)?.trim().parse::<i32>() {
+            Ok(answer) => answer,
+            Err(_) => return Ok(None),
+        };
+        return Ok(Some(Sidecar {
+            game_variant: variant_dir
+                .file_name()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            answer,
+            // Images were only saved for accepted sessions
+            accepted: true,
+            ..Default::default()
+        }));
+    }
+
+    Ok(None)
+}
+
+fn write_jsonl(path: &Path, records: &[Record]) -> anyhow::Result<()> {
+    let mut file = fs::File::create(path)?;
+    for record in records {
+        serde_json::to_writer(&mut file, record)?;
+        file.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+fn write_csv(path: &Path, records: &[Record]) -> anyhow::Result<()> {
+    let mut file = fs::File::create(path)?;
+    writeln!(
+        file,
+        "image,game_variant,instructions,answer,tiles,solver,accepted,time"
+    )?;
+    for record in records {
+        // Tiles of a multi-tile round are space separated, empty for a single answer
+        let tiles = record
+            .sidecar
+            .tiles
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<String>>()
+            .join(" ");
+        writeln!(
+            file,
+            "{},{},{},{},{},{},{},{}",
+            csv_field(&record.image),
+            csv_field(&record.sidecar.game_variant),
+            csv_field(&record.sidecar.instructions),
+            record.sidecar.answer,
+            tiles,
+            csv_field(&record.sidecar.solver),
+            record.sidecar.accepted,
+            record.sidecar.time
+        )?;
+    }
+    Ok(())
+}
+
+/// Quote the field if it contains a separator, quote or line break
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_write_csv_tiles() {
+        let record = |image: &str, answer: i32, tiles: Vec<i32>| Record {
+            image: image.to_owned(),
+            sidecar: Sidecar {
+                game_variant: "tile".to_owned(),
+                instructions: "Pick the dice".to_owned(),
+                answer,
+                tiles,
+                solver: "generic".to_owned(),
+                accepted: true,
+                time: 1,
+            },
+        };
+        let path = std::env::temp_dir().join(format!("ninja-dataset-{}.csv", crate::uuid::uuid()));
+
+        write_csv(
+            &path,
+            &[
+                record("a.jpg", 2, Vec::new()),
+                record("b.jpg", 0, vec![0, 3, 5]),
+            ],
+        )
+        .unwrap();
+        let csv = fs::read_to_string(&path).unwrap();
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(
+            csv,
+            "image,game_variant,instructions,answer,tiles,solver,accepted,time\n\
+             a.jpg,tile,Pick the dice,2,,generic,true,1\n\
+             b.jpg,tile,Pick the dice,0,0 3 5,generic,true,1\n"
+        );
+    }
+}
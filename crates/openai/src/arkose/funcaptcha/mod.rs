@@ -1,6 +1,11 @@
+pub(crate) mod answer;
 mod breaker;
+pub mod dataset;
 pub mod model;
 pub mod solver;
+pub(crate) mod spend;
+#[cfg(all(test, feature = "serve"))]
+mod tests;
 
 use self::model::{Challenge, ConciseChallenge, FunCaptcha, RequestChallenge, TGuess};
 use super::{crypto, ArkoseSolverContext};
@@ -16,6 +21,7 @@ use std::sync::Arc;
 
 type FunResult<T, E = super::error::ArkoseError> = Result<T, E>;
 
+#[tracing::instrument(name = "funcaptcha.start_challenge", skip_all)]
 pub async fn start_challenge(ctx: &ArkoseSolverContext) -> FunResult<Session> {
     let value = ctx.arkose_token.value();
     let fields: Vec<&str> = value.split('|').collect();
@@ -66,7 +72,9 @@ pub async fn start_challenge(ctx: &ArkoseSolverContext) -> FunResult<Session> {
         challenge: None,
         game_type: 0,
         headers,
-        tguess_endpoint: with_context!(arkose_solver_tguess_endpoint),
+        tguess_endpoint: with_context!(reloadable)
+            .arkose_solver_tguess_endpoint()
+            .map(ToOwned::to_owned),
         client: ctx.client.clone(),
     };
 
@@ -109,7 +117,7 @@ pub struct Session {
     challenge: Option<Challenge>,
     funcaptcha: Option<Vec<FunCaptcha>>,
     game_type: u32,
-    tguess_endpoint: Option<&'static str>,
+    tguess_endpoint: Option<String>,
     client: reqwest::Client,
 }
 
@@ -176,6 +184,7 @@ impl Session {
     }
 
     #[inline]
+    #[tracing::instrument(name = "funcaptcha.request_challenge", skip_all)]
     async fn request_challenge(&mut self) -> FunResult<ConciseChallenge> {
         // Global Callback
         self.global_callback().await?;
@@ -258,6 +267,22 @@ impl Session {
             )
         };
 
+        // Unknown variants can not be answered, fail before solving
+        let c_ui = &challenge.game_data.custom_gui;
+        if let Some(err) = breaker::check(
+            c_ui.api_breaker_v2_enabled != 0,
+            self.game_type,
+            &c_ui.api_breaker,
+        )
+        .err()
+        {
+            warn!("Unsupported funcaptcha variant: {err}");
+            return Err(ArkoseError::UnknownVariant(format!(
+                "game type {}, variant {game_variant}, breaker {:?}",
+                self.game_type, c_ui.api_breaker
+            )));
+        }
+
         // Remove html tags
         let remove_html_tags = |input: &str| {
             let re = regex::Regex::new(r"<[^>]*>").expect("invalid regex");
@@ -281,9 +306,12 @@ impl Session {
         Ok(concise_challenge)
     }
 
+    #[tracing::instrument(name = "funcaptcha.tguess", skip_all)]
     async fn tguess(&self, guess: Vec<String>, session_token: &str) -> FunResult<Option<String>> {
         if let Some(ref c) = self.challenge {
-            if let (Some(dapib_url), Some(tguess_endpoint)) = (&c.dapib_url, self.tguess_endpoint) {
+            if let (Some(dapib_url), Some(tguess_endpoint)) =
+                (&c.dapib_url, self.tguess_endpoint.as_deref())
+            {
                 let resp = self
                     .client
                     .post(tguess_endpoint)
@@ -310,7 +338,21 @@ impl Session {
         Ok(None)
     }
 
-    pub async fn submit_answer(&self, answers: &[i32]) -> FunResult<()> {
+    /// Tile selection (game type 3) rounds may take several tiles
+    pub fn multiple_answers(&self) -> bool {
+        self.game_type == 3
+    }
+
+    /// Submit the answers, one entry per round
+    #[tracing::instrument(name = "funcaptcha.submit_answer", skip_all)]
+    pub async fn submit_answer(&self, answers: &[Vec<i32>]) -> FunResult<()> {
+        let guess = self.guess(answers, &mut rand::thread_rng())?;
+        self.submit_waves(&guess).await
+    }
+
+    /// Encode the answers with the api breaker of the challenge,
+    /// a round with several tiles is encoded as an array
+    fn guess(&self, answers: &[Vec<i32>], rng: &mut impl rand::Rng) -> FunResult<Vec<String>> {
         let c_ui = &self
             .challenge
             .as_ref()
@@ -320,18 +362,54 @@ impl Session {
 
         let mut answer_index = Vec::with_capacity(answers.len());
 
-        for answer in answers {
-            let answer = breaker::hanlde_answer(
-                c_ui.api_breaker_v2_enabled != 0,
-                self.game_type,
-                &c_ui.api_breaker,
-                *answer,
-            )?
-            .to_string();
-            answer_index.push(answer)
+        for round in answers {
+            let mut tiles = Vec::with_capacity(round.len());
+            for answer in round {
+                tiles.push(breaker::hanlde_answer(
+                    c_ui.api_breaker_v2_enabled != 0,
+                    self.game_type,
+                    &c_ui.api_breaker,
+                    *answer,
+                    rng,
+                )?);
+            }
+
+            let answer = match tiles.len() {
+                1 => tiles.remove(0),
+                _ => serde_json::Value::Array(tiles),
+            };
+            answer_index.push(answer.to_string())
         }
 
-        let answer = answer_index.join(",");
+        Ok(answer_index)
+    }
+
+    /// Submit the guess, a multi-wave challenge is answered one more round per wave
+    async fn submit_waves(&self, guess: &[String]) -> FunResult<()> {
+        let waves = self
+            .challenge
+            .as_ref()
+            .map(|c| c.game_data.waves)
+            .unwrap_or_default();
+
+        if waves > 1 {
+            for round in 1..=guess.len() {
+                if self.submit_guess(&guess[..round]).await? {
+                    return Ok(());
+                }
+            }
+        } else if self.submit_guess(guess).await? {
+            return Ok(());
+        }
+
+        Err(ArkoseError::FuncaptchaNotSolvedError(
+            "not answered".to_owned(),
+        ))
+    }
+
+    /// Submit the guess, `false` when the challenge waits for more waves
+    async fn submit_guess(&self, guess: &[String]) -> FunResult<bool> {
+        let answer = guess.join(",");
 
         let submit = SubmitChallenge {
             session_token: &self.session_token,
@@ -341,7 +419,7 @@ impl Session {
                 .as_ref()
                 .ok_or_else(|| ArkoseError::UnknownChallenge)?
                 .challenge_id,
-            tguess: self.tguess(answer_index, &self.session_token).await?,
+            tguess: self.tguess(guess.to_vec(), &self.session_token).await?,
             guess: crypto::encrypt(&format!("[{answer}]"), &self.session_token)?,
             render_type: "canvas",
             analytics_tier: 40,
@@ -388,13 +466,17 @@ impl Session {
             return Err(ArkoseError::FuncaptchaSubmitError(error));
         }
 
-        if !resp.solved {
-            return Err(ArkoseError::FuncaptchaNotSolvedError(
-                resp.incorrect_guess.unwrap_or_default(),
-            ));
+        if resp.solved {
+            return Ok(true);
         }
 
-        Ok(())
+        if resp.response.as_deref() == Some("not answered") {
+            return Ok(false);
+        }
+
+        Err(ArkoseError::FuncaptchaNotSolvedError(
+            resp.incorrect_guess.unwrap_or_default(),
+        ))
     }
 
     async fn download_image_to_base64(&self, urls: &Vec<String>) -> FunResult<Vec<String>> {
@@ -419,19 +501,24 @@ impl Session {
         self.funcaptcha.as_ref()
     }
 
+    /// Save the images with their label sidecar, grouped by game variant
     pub async fn save_funcaptcha_to_dir(
         self,
         dir: impl AsRef<Path>,
-        guess: Vec<i32>,
+        guess: Vec<Vec<i32>>,
+        solvers: Vec<String>,
+        accepted: bool,
     ) -> FunResult<()> {
         if let Some(funcaptcha) = self.funcaptcha {
-            if guess.len() != funcaptcha.len() {
+            if guess.len() != funcaptcha.len() || solvers.len() != funcaptcha.len() {
                 warn!("Guess length != funcaptcha length");
                 return Ok(());
             }
 
-            for (index, fun) in funcaptcha.into_iter().enumerate() {
-                let game_variant_dir = dir.as_ref().join(fun.game_variant);
+            let time = now_duration()?.as_secs() as i64;
+
+            for (index, (fun, solver)) in funcaptcha.into_iter().zip(solvers).enumerate() {
+                let game_variant_dir = dir.as_ref().join(&fun.game_variant);
                 if !game_variant_dir.exists() {
                     if let Some(err) = tokio::fs::create_dir(&game_variant_dir).await.err() {
                         tracing::warn!(
@@ -447,9 +534,9 @@ impl Session {
                 // Write image to file
                 let image_path =
                     game_variant_dir.join(format!("{}_{index}.png", self.session_token));
-                // Write image guess to file
-                let image_guess_path =
-                    game_variant_dir.join(format!("{}_{index}.txt", self.session_token));
+                // Write image label to file
+                let sidecar_path =
+                    game_variant_dir.join(format!("{}_{index}.json", self.session_token));
 
                 if let Some(err) = tokio::fs::write(&image_path, image).await.err() {
                     tracing::warn!(
@@ -458,13 +545,26 @@ impl Session {
                     );
                 }
 
-                if let Some(err) = tokio::fs::write(&image_guess_path, guess[index].to_string())
+                let sidecar = dataset::Sidecar {
+                    instructions: fun.instructions,
+                    game_variant: fun.game_variant,
+                    answer: guess[index].first().copied().unwrap_or_default(),
+                    tiles: match guess[index].len() {
+                        1 => Vec::new(),
+                        _ => guess[index].clone(),
+                    },
+                    solver,
+                    accepted,
+                    time,
+                };
+
+                if let Some(err) = tokio::fs::write(&sidecar_path, serde_json::to_vec(&sidecar)?)
                     .await
                     .err()
                 {
                     tracing::warn!(
-                        "Failed to write image guess to file: {}, error: {err}",
-                        image_guess_path.display()
+                        "Failed to write image label to file: {}, error: {err}",
+                        sidecar_path.display()
                     );
                 }
             }
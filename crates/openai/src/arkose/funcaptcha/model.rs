@@ -32,6 +32,8 @@ pub(super) struct GameData {
     pub game_type: i32,
     pub game_variant: String,
     pub instruction_string: String,
+    /// Rounds answered one by one, a single wave takes every answer at once
+    pub waves: i32,
     #[serde(rename = "customGUI")]
     pub custom_gui: CustomGUI,
 }
@@ -46,11 +48,33 @@ pub(super) struct CustomGUI {
 }
 
 #[derive(Debug, Deserialize, Default)]
+#[serde(from = "RawApiBreaker")]
 pub(super) struct ApiBreaker {
     pub key: String,
     pub value: Vec<String>,
 }
 
+/// The v1 breaker is only the key, the v2 breaker also has the value transforms
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawApiBreaker {
+    V1(String),
+    V2 {
+        key: String,
+        #[serde(default)]
+        value: Vec<String>,
+    },
+}
+
+impl From<RawApiBreaker> for ApiBreaker {
+    fn from(value: RawApiBreaker) -> Self {
+        match value {
+            RawApiBreaker::V1(key) => ApiBreaker { key, value: vec![] },
+            RawApiBreaker::V2 { key, value } => ApiBreaker { key, value },
+        }
+    }
+}
+
 #[derive(Default)]
 #[allow(dead_code)]
 pub(super) struct ConciseChallenge {
@@ -0,0 +1,145 @@
+// Reference encoding of the funcaptcha `api_breaker`, kept apart from `breaker.rs` so the
+// expected `guess` of every snapshot is not the output of the code under test.
+//
+// The tables are transcribed from the `apiBreakers` table of the noahcoolboy/funcaptcha
+// JS client, which mirrors Arkose's game core, and keep its JS number semantics.
+// The random parts use the lower bound, as the zero `StepRng` of the replay test does.
+//
+//   node api_breaker.js          check the `guess` of every snapshot
+//   node api_breaker.js --write  rewrite the `guess` of every snapshot
+
+const fs = require("fs");
+const path = require("path");
+
+const random = () => 0;
+const randint = (min, max) => Math.floor(random() * (max - min)) + min;
+
+const apiBreakers = {
+  v1: {
+    3: {
+      default: (c) => c,
+      method_1: (c) => ({ x: c.y, y: c.x }),
+      method_2: (c) => ({ x: c.x, y: (c.y + c.x) * c.x }),
+      method_3: (c) => ({ a: c.x, b: c.y }),
+      method_4: (c) => [c.x, c.y],
+      method_5: (c) => [c.y, c.x].map((v) => Math.sqrt(v)),
+    },
+    4: {
+      default: (c) => ({ index: c }),
+    },
+  },
+  v2: {
+    3: {
+      value: {
+        alpha: (c) => ({ x: c.x, y: (c.y + c.x) * c.x, px: c.px, py: c.py }),
+        beta: (c) => ({ x: c.y, y: c.x, px: c.py, py: c.px }),
+        gamma: (c) => ({ x: c.y + 1, y: -c.x, px: c.px, py: c.py }),
+        delta: (c) => ({ x: c.y + 0.25, y: c.x + 0.5, px: c.px, py: c.py }),
+        epsilon: (c) => ({ x: c.x * 0.5, y: c.y * 5, px: c.px, py: c.py }),
+        zeta: (c) => ({ x: c.x + 1, y: c.y + 2, px: c.px, py: c.py }),
+        method_1: (c) => ({ x: c.x, y: c.y, px: c.px, py: c.py }),
+        method_2: (c) => ({ x: c.y, y: (c.y + c.x) * c.y, px: c.px, py: c.py }),
+        method_3: (c) => ({ x: Math.sqrt(c.x), y: Math.sqrt(c.y), px: c.px, py: c.py }),
+      },
+      key: {
+        alpha: (c) => [c.y, c.px, c.py, c.x],
+        beta: (c) => ({ x: c.x, y: c.y, px: c.px, py: c.py }),
+        gamma: (c) => [c.x, c.y, c.px, c.py].join(" "),
+        delta: (c) => [1, c.x, 2, c.y, 3, c.px, 4, c.py],
+        epsilon: (c) => ({ x: c.x, y: c.y, px: c.px, py: c.py }),
+        zeta: (c) => [c.x, [c.y, [c.px, [c.py]]]],
+        method_1: (c) => ({ a: c.x, b: c.y, px: c.px, py: c.py }),
+        method_2: (c) => [c.x, c.y],
+        method_3: (c) => [c.y, c.x],
+      },
+    },
+    4: {
+      value: {
+        alpha: (c) => String(c) + 1 - 2,
+        beta: (c) => -c,
+        gamma: (c) => 3 * (3 - c),
+        delta: (c) => 7 * c,
+        epsilon: (c) => 2 * c,
+        zeta: (c) => (c ? 100 / c : c),
+      },
+      key: {
+        alpha: (c) => [randint(0, 100), c, randint(0, 100)],
+        beta: (c) => ({ size: 50 - c, id: c, limit: 10 * c, req_timestamp: "*" }),
+        gamma: (c) => c,
+        delta: (c) => ({ index: c }),
+        epsilon: (c) => {
+          const arr = [];
+          const len = randint(0, 5) + 1;
+          const rand = randint(0, len);
+          for (let i = 0; i < len; i++) {
+            arr.push(i === rand ? c : randint(0, 10));
+          }
+          arr.push(rand);
+          return arr;
+        },
+        zeta: (c) => Array(randint(0, 5) + 1).fill(0).concat(c),
+      },
+    },
+  },
+};
+
+function tileToLoc(tile) {
+  const x = (tile % 3) * 100 + (tile % 3) * 3 + 3 + 10 + Math.floor(random() * 80);
+  const y = Math.floor(tile / 3) * 100 + Math.floor(tile / 3) * 3 + 3 + 10 + Math.floor(random() * 80);
+  return { x, y, px: x / 300, py: y / 200 };
+}
+
+function encode(gameData, tile) {
+  const gameType = gameData.gameType;
+  const gui = gameData.customGUI;
+  // The v1 breaker is only the key
+  const breaker =
+    typeof gui.api_breaker === "string" ? { key: gui.api_breaker, value: [] } : gui.api_breaker || { key: "", value: [] };
+
+  if (!gui.api_breaker_v2_enabled) {
+    const table = apiBreakers.v1[gameType];
+    const method = table[breaker.key || "default"];
+    return method(gameType === 3 ? tileToLoc(tile) : tile);
+  }
+
+  const table = apiBreakers.v2[gameType];
+  let answer = gameType === 3 ? tileToLoc(tile) : tile;
+  for (const value of breaker.value) {
+    answer = table.value[value](answer);
+  }
+  return table.key[breaker.key](answer);
+}
+
+function guess(snapshot) {
+  return snapshot.answers.map((round) => {
+    const tiles = round.map((tile) => encode(snapshot.challenge.game_data, tile));
+    return tiles.length === 1 ? tiles[0] : tiles;
+  });
+}
+
+const write = process.argv.includes("--write");
+let failed = false;
+
+for (const name of fs.readdirSync(__dirname).filter((name) => name.endsWith(".json")).sort()) {
+  const file = path.join(__dirname, name);
+  const snapshot = JSON.parse(fs.readFileSync(file, "utf8"));
+  if (snapshot.error) {
+    continue;
+  }
+
+  const expected = guess(snapshot);
+  if (JSON.stringify(expected) === JSON.stringify(snapshot.guess)) {
+    continue;
+  }
+
+  if (write) {
+    snapshot.guess = expected;
+    fs.writeFileSync(file, JSON.stringify(snapshot, null, 2) + "\n");
+    console.log(`${name}: updated`);
+  } else {
+    failed = true;
+    console.log(`${name}: expected ${JSON.stringify(expected)}, found ${JSON.stringify(snapshot.guess)}`);
+  }
+}
+
+process.exit(failed ? 1 : 0);
@@ -1,9 +1,13 @@
+use std::collections::BTreeMap;
 use std::str::FromStr;
 
 use hyper::header;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use typed_builder::TypedBuilder;
 
+use super::spend;
 use crate::{arkose::error::ArkoseError, with_context};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -12,6 +16,7 @@ pub enum Solver {
     Yescaptcha,
     Capsolver,
     Fcsrv,
+    Generic,
 }
 
 impl Default for Solver {
@@ -28,7 +33,10 @@ impl FromStr for Solver {
             "yescaptcha" => Ok(Self::Yescaptcha),
             "capsolver" => Ok(Self::Capsolver),
             "fcsrv" => Ok(Self::Fcsrv),
-            _ => anyhow::bail!("Only support `yescaptcha` / `capsolver` / `fcsrv` solver"),
+            "generic" => Ok(Self::Generic),
+            _ => anyhow::bail!(
+                "Only support `yescaptcha` / `capsolver` / `fcsrv` / `generic` solver"
+            ),
         }
     }
 }
@@ -39,38 +47,141 @@ impl ToString for Solver {
             Self::Yescaptcha => "yescaptcha".to_string(),
             Self::Capsolver => "capsolver".to_string(),
             Self::Fcsrv => "fcsrv".to_string(),
+            Self::Generic => "generic".to_string(),
         }
     }
 }
 
+#[trait_variant::make(CaptchaSolver: Send)]
+pub trait LocalCaptchaSolver {
+    /// Submit the funcaptcha images, return the answer indexes
+    async fn submit_task(&self, task: SubmitSolver<'_>) -> anyhow::Result<Vec<i32>>;
+
+    /// Whether the images of a game variant are submitted together,
+    /// otherwise every image is submitted with its instructions
+    fn batch(&self) -> bool;
+}
+
+#[derive(TypedBuilder)]
+pub struct SubmitSolver<'a> {
+    #[builder(setter(into), default)]
+    image: Option<&'a String>,
+    #[builder(setter(into), default)]
+    images: Option<Vec<&'a String>>,
+    question: &'a String,
+}
+
+/// Spend controls of a solver, 0 means unlimited
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SolverBudget {
+    /// Challenges solved at the same time
+    pub concurrency: usize,
+    /// Tasks submitted per minute
+    pub tasks_per_minute: u64,
+    /// Tasks submitted per day (UTC)
+    pub tasks_per_day: u64,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ArkoseSolver {
     pub solver: Solver,
     pub limit: usize,
-    client_key: String,
-    endpoint: String,
+    pub budget: SolverBudget,
+    inner: Solvers,
 }
 
 impl ArkoseSolver {
-    pub fn new(solver: Solver, client_key: String, endpoint: Option<String>, limit: usize) -> Self {
-        let endpoint = match solver {
-            Solver::Yescaptcha => {
-                endpoint.unwrap_or("https://api.yescaptcha.com/createTask".to_string())
-            }
-            Solver::Capsolver => {
-                endpoint.unwrap_or("https://api.capsolver.com/createTask".to_string())
+    pub fn new(
+        solver: Solver,
+        client_key: String,
+        endpoint: Option<String>,
+        limit: usize,
+        budget: SolverBudget,
+        generic: Option<GenericSolverConfig>,
+    ) -> anyhow::Result<Self> {
+        let inner = match solver {
+            Solver::Yescaptcha => Solvers::Yescaptcha(YescaptchaSolver {
+                endpoint: endpoint.unwrap_or("https://api.yescaptcha.com/createTask".to_string()),
+                client_key,
+            }),
+            Solver::Capsolver => Solvers::Capsolver(CapsolverSolver {
+                endpoint: endpoint.unwrap_or("https://api.capsolver.com/createTask".to_string()),
+                client_key,
+            }),
+            Solver::Fcsrv => Solvers::Fcsrv(FcsrvSolver {
+                endpoint: endpoint.unwrap_or("http://127.0.0.1:8000/task".to_string()),
+                client_key,
+            }),
+            Solver::Generic => {
+                let endpoint = endpoint
+                    .ok_or_else(|| anyhow::anyhow!("The generic solver requires an endpoint"))?;
+                let config = generic
+                    .ok_or_else(|| anyhow::anyhow!("The generic solver requires its config"))?;
+                Solvers::Generic(GenericSolver::new(client_key, endpoint, config))
             }
-            Solver::Fcsrv => endpoint.unwrap_or("http://127.0.0.1:8000/task".to_string()),
         };
-        Self {
+
+        Ok(Self {
             solver,
-            client_key,
-            endpoint,
             limit,
+            budget,
+            inner,
+        })
+    }
+
+    /// Query the account balance, only yescaptcha and capsolver have a balance endpoint
+    pub async fn balance(&self) -> anyhow::Result<Option<f64>> {
+        let (endpoint, client_key) = match &self.inner {
+            Solvers::Yescaptcha(solver) => (&solver.endpoint, &solver.client_key),
+            Solvers::Capsolver(solver) => (&solver.endpoint, &solver.client_key),
+            _ => return Ok(None),
+        };
+
+        // The balance endpoint sits next to the createTask endpoint
+        let endpoint = match endpoint.rsplit_once('/') {
+            Some((base, _)) => format!("{base}/getBalance"),
+            None => anyhow::bail!("Invalid solver endpoint: {endpoint}"),
+        };
+        let body = BalanceReq { client_key };
+        let resp = post_task::<BalanceResp>(&endpoint, serde_json::to_string(&body)?, &[]).await?;
+        if let Some(error_description) = resp.error_description {
+            anyhow::bail!(ArkoseError::SolverTaskError(error_description))
+        }
+        Ok(Some(resp.balance))
+    }
+}
+
+impl CaptchaSolver for ArkoseSolver {
+    #[tracing::instrument(name = "funcaptcha.submit_task", skip_all, fields(solver = ?self.solver))]
+    async fn submit_task(&self, task: SubmitSolver<'_>) -> anyhow::Result<Vec<i32>> {
+        // Count the task against the budget before it is charged
+        spend::record(self, 1)?;
+        match &self.inner {
+            Solvers::Yescaptcha(solver) => solver.submit_task(task).await,
+            Solvers::Capsolver(solver) => solver.submit_task(task).await,
+            Solvers::Fcsrv(solver) => solver.submit_task(task).await,
+            Solvers::Generic(solver) => solver.submit_task(task).await,
+        }
+    }
+
+    fn batch(&self) -> bool {
+        match &self.inner {
+            Solvers::Yescaptcha(solver) => solver.batch(),
+            Solvers::Capsolver(solver) => solver.batch(),
+            Solvers::Fcsrv(solver) => solver.batch(),
+            Solvers::Generic(solver) => solver.batch(),
         }
     }
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+enum Solvers {
+    Yescaptcha(YescaptchaSolver),
+    Capsolver(CapsolverSolver),
+    Fcsrv(FcsrvSolver),
+    Generic(GenericSolver),
+}
+
 #[derive(Deserialize, Default, Debug)]
 #[serde(default)]
 struct TaskResp0 {
@@ -111,6 +222,20 @@ struct ReqBody0<'a> {
     app_id: Option<&'static str>,
 }
 
+#[derive(Serialize, Debug)]
+struct BalanceReq<'a> {
+    #[serde(rename = "clientKey")]
+    client_key: &'a str,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+struct BalanceResp {
+    #[serde(rename = "errorDescription")]
+    error_description: Option<String>,
+    balance: f64,
+}
+
 #[derive(Serialize, Debug)]
 struct ReqBody1<'a> {
     api_key: Option<&'a str>,
@@ -130,90 +255,388 @@ struct ReqTask0<'a> {
     question: &'a str,
 }
 
-#[derive(TypedBuilder)]
-pub struct SubmitSolver<'a> {
-    arkose_solver: &'a ArkoseSolver,
-    #[builder(setter(into), default)]
-    image: Option<&'a String>,
-    #[builder(setter(into), default)]
-    images: Option<Vec<&'a String>>,
-    question: &'a String,
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct YescaptchaSolver {
+    client_key: String,
+    endpoint: String,
 }
 
-pub async fn submit_task(submit_task: SubmitSolver<'_>) -> anyhow::Result<Vec<i32>> {
-    let body = match submit_task.arkose_solver.solver {
-        Solver::Yescaptcha => {
-            let body = ReqBody0 {
-                client_key: &submit_task.arkose_solver.client_key,
-                task: ReqTask0 {
-                    type_field: "FunCaptchaClassification",
-                    image: submit_task.image,
-                    images: submit_task.images,
-                    question: &submit_task.question,
-                },
-                soft_id: Some("26299"),
-                app_id: None,
-            };
-            serde_json::to_string(&body)?
+impl CaptchaSolver for YescaptchaSolver {
+    async fn submit_task(&self, task: SubmitSolver<'_>) -> anyhow::Result<Vec<i32>> {
+        let body = ReqBody0 {
+            client_key: &self.client_key,
+            task: ReqTask0 {
+                type_field: "FunCaptchaClassification",
+                image: task.image,
+                images: task.images,
+                question: &task.question,
+            },
+            soft_id: Some("26299"),
+            app_id: None,
+        };
+        let task =
+            post_task::<TaskResp0>(&self.endpoint, serde_json::to_string(&body)?, &[]).await?;
+        // If error
+        if let Some(error_description) = task.error_description {
+            anyhow::bail!(ArkoseError::SolverTaskError(error_description))
         }
-        Solver::Capsolver => {
-            let body = ReqBody0 {
-                client_key: &submit_task.arkose_solver.client_key,
-                task: ReqTask0 {
-                    type_field: "FunCaptchaClassification",
-                    image: submit_task.image,
-                    images: submit_task.images,
-                    question: &submit_task.question,
-                },
-                soft_id: None,
-                app_id: Some("60632CB0-8BE8-41D3-808F-60CC2442F16E"),
-            };
-            serde_json::to_string(&body)?
+        Ok(task.solution.objects)
+    }
+
+    fn batch(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CapsolverSolver {
+    client_key: String,
+    endpoint: String,
+}
+
+impl CaptchaSolver for CapsolverSolver {
+    async fn submit_task(&self, task: SubmitSolver<'_>) -> anyhow::Result<Vec<i32>> {
+        let body = ReqBody0 {
+            client_key: &self.client_key,
+            task: ReqTask0 {
+                type_field: "FunCaptchaClassification",
+                image: task.image,
+                images: task.images,
+                question: &task.question,
+            },
+            soft_id: None,
+            app_id: Some("60632CB0-8BE8-41D3-808F-60CC2442F16E"),
+        };
+        let task =
+            post_task::<TaskResp0>(&self.endpoint, serde_json::to_string(&body)?, &[]).await?;
+        // If error
+        if let Some(error_description) = task.error_description {
+            anyhow::bail!(ArkoseError::SolverTaskError(error_description))
+        }
+        Ok(task.solution.objects)
+    }
+
+    fn batch(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FcsrvSolver {
+    client_key: String,
+    endpoint: String,
+}
+
+impl CaptchaSolver for FcsrvSolver {
+    async fn submit_task(&self, task: SubmitSolver<'_>) -> anyhow::Result<Vec<i32>> {
+        let body = ReqBody1 {
+            api_key: Some(&self.client_key),
+            typed: &task.question,
+            images: task.images,
+        };
+        let task =
+            post_task::<TaskResp1>(&self.endpoint, serde_json::to_string(&body)?, &[]).await?;
+        // If error
+        if let Some(error) = task.error {
+            anyhow::bail!(ArkoseError::SolverTaskError(error))
+        }
+        Ok(task.objects)
+    }
+
+    fn batch(&self) -> bool {
+        true
+    }
+}
+
+/// Generic solver request and response shapes, defined in the config file
+///
+/// ```toml
+/// [arkose_solver_generic]
+/// answer_path = "data.objects"
+/// error_path = "error"
+///
+/// [arkose_solver_generic.headers]
+/// Authorization = "Bearer {client_key}"
+///
+/// [arkose_solver_generic.request]
+/// question = "prefix-{question}"
+/// images = "{images}"
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GenericSolverConfig {
+    /// JSON request body, the `{client_key}`, `{question}`, `{image}` and `{images}`
+    /// string values are substituted. Images are submitted together when `{images}` is used.
+    /// `{client_key}`, `{question}` and `{image}` are also substituted inside longer strings
+    pub request: Value,
+    /// Request headers, placeholders are substituted as in the request strings
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Dot separated path of the answer indexes in the response, Example: `solution.objects`
+    pub answer_path: String,
+    /// Dot separated path of the error message in the response
+    #[serde(default)]
+    pub error_path: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GenericSolver {
+    client_key: String,
+    endpoint: String,
+    config: GenericSolverConfig,
+    batch: bool,
+}
+
+impl GenericSolver {
+    pub fn new(client_key: String, endpoint: String, config: GenericSolverConfig) -> Self {
+        let batch = config.request.to_string().contains("\"{images}\"");
+        Self {
+            client_key,
+            endpoint,
+            config,
+            batch,
+        }
+    }
+
+    /// Substitute the placeholders of the request template
+    fn render(&self, template: &Value, task: &SubmitSolver<'_>) -> Value {
+        match template {
+            Value::String(s) => match s.as_str() {
+                "{client_key}" => Value::from(self.client_key.as_str()),
+                "{question}" => Value::from(task.question.as_str()),
+                "{image}" => serde_json::json!(task.image),
+                "{images}" => serde_json::json!(task.images),
+                _ => Value::from(self.substitute(s, task)),
+            },
+            Value::Array(items) => {
+                Value::Array(items.iter().map(|v| self.render(v, task)).collect())
+            }
+            Value::Object(map) => Value::Object(
+                map.iter()
+                    .map(|(k, v)| (k.clone(), self.render(v, task)))
+                    .collect(),
+            ),
+            _ => template.clone(),
+        }
+    }
+
+    /// Substitute the placeholders inside a string, a missing image is substituted as empty
+    fn substitute(&self, s: &str, task: &SubmitSolver<'_>) -> String {
+        s.replace("{client_key}", &self.client_key)
+            .replace("{question}", task.question)
+            .replace("{image}", task.image.map_or("", String::as_str))
+    }
+
+    /// Substitute the placeholders of the request headers
+    fn render_headers(&self, task: &SubmitSolver<'_>) -> Vec<(String, String)> {
+        self.config
+            .headers
+            .iter()
+            .map(|(name, value)| (name.clone(), self.substitute(value, task)))
+            .collect()
+    }
+}
+
+impl CaptchaSolver for GenericSolver {
+    async fn submit_task(&self, task: SubmitSolver<'_>) -> anyhow::Result<Vec<i32>> {
+        let body = self.render(&self.config.request, &task);
+        let headers = self.render_headers(&task);
+        let resp =
+            post_task::<Value>(&self.endpoint, serde_json::to_string(&body)?, &headers).await?;
+
+        // If error
+        if let Some(error) = self
+            .config
+            .error_path
+            .as_ref()
+            .and_then(|path| lookup(&resp, path))
+        {
+            match error {
+                Value::Null | Value::Bool(false) => {}
+                Value::String(s) if s.is_empty() => {}
+                Value::String(s) => anyhow::bail!(ArkoseError::SolverTaskError(s.to_owned())),
+                _ => anyhow::bail!(ArkoseError::SolverTaskError(error.to_string())),
+            }
         }
-        Solver::Fcsrv => {
-            let body = ReqBody1 {
-                api_key: Some(&submit_task.arkose_solver.client_key),
-                typed: &submit_task.question,
-                images: submit_task.images,
-            };
-            serde_json::to_string(&body)?
+
+        match lookup(&resp, &self.config.answer_path) {
+            Some(answer @ (Value::Array(_) | Value::Number(_))) => answer_indexes(answer),
+            _ => anyhow::bail!(ArkoseError::SolverTaskError(format!(
+                "No answer at `{}`: {resp}",
+                self.config.answer_path
+            ))),
         }
+    }
+
+    fn batch(&self) -> bool {
+        self.batch
+    }
+}
+
+/// Get the value at the dot separated path, array items are indexed by number
+fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .filter(|key| !key.is_empty())
+        .try_fold(value, |value, key| match value {
+            Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+            _ => value.get(key),
+        })
+}
+
+/// Answer indexes of the response, a value that is not an index fails the task
+fn answer_indexes(answer: &Value) -> anyhow::Result<Vec<i32>> {
+    let index = |value: &Value| -> anyhow::Result<i32> {
+        value
+            .as_i64()
+            .and_then(|n| i32::try_from(n).ok())
+            .ok_or_else(|| {
+                ArkoseError::SolverTaskError(format!("Invalid answer index: {value}")).into()
+            })
     };
+    match answer {
+        Value::Array(items) => items.iter().map(index).collect(),
+        value => Ok(vec![index(value)?]),
+    }
+}
 
-    let resp = with_context!(arkose_client)
-        .post(&submit_task.arkose_solver.endpoint)
-        .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+async fn post_task<T: DeserializeOwned>(
+    endpoint: &str,
+    body: String,
+    headers: &[(String, String)],
+) -> anyhow::Result<T> {
+    let req = with_context!(arkose_client)
+        .post(endpoint)
+        .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref());
+    let resp = headers
+        .iter()
+        .fold(req, |req, (name, value)| {
+            req.header(name.as_str(), value.as_str())
+        })
         .body(body)
         .send()
         .await?;
 
     match resp.error_for_status_ref() {
-        Ok(_) => {
-            // Task Respone
-            match submit_task.arkose_solver.solver {
-                Solver::Yescaptcha | Solver::Capsolver => {
-                    let task = resp.json::<TaskResp0>().await?;
-                    // If error
-                    if let Some(error_description) = task.error_description {
-                        anyhow::bail!(ArkoseError::SolverTaskError(error_description))
-                    }
-
-                    Ok(task.solution.objects)
-                }
-                Solver::Fcsrv => {
-                    let task = resp.json::<TaskResp1>().await?;
-                    // If error
-                    if let Some(error) = task.error {
-                        anyhow::bail!(ArkoseError::SolverTaskError(error))
-                    }
-
-                    Ok(task.objects)
-                }
-            }
-        }
+        Ok(_) => Ok(resp.json::<T>().await?),
         Err(_) => {
             let body = resp.text().await?;
             anyhow::bail!(ArkoseError::SolverTaskError(body))
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn solver(request: Value) -> GenericSolver {
+        let config = GenericSolverConfig {
+            request,
+            answer_path: "data.objects".to_owned(),
+            error_path: None,
+            headers: BTreeMap::new(),
+        };
+        GenericSolver::new("key".to_owned(), "http://127.0.0.1".to_owned(), config)
+    }
+
+    #[test]
+    fn test_render() {
+        let (question, image) = ("3d_rollball_objects".to_owned(), "aW1hZ2U=".to_owned());
+        let task = SubmitSolver::builder()
+            .question(&question)
+            .image(&image)
+            .build();
+        let solver = solver(json!({
+            "key": "{client_key}",
+            "task": { "question": "{question}", "body": "{image}", "type": "Funcaptcha" },
+            "tags": ["{question}", 1],
+        }));
+
+        assert!(!solver.batch());
+        assert_eq!(
+            solver.render(&solver.config.request, &task),
+            json!({
+                "key": "key",
+                "task": {
+                    "question": "3d_rollball_objects",
+                    "body": "aW1hZ2U=",
+                    "type": "Funcaptcha",
+                },
+                "tags": ["3d_rollball_objects", 1],
+            })
+        );
+    }
+
+    #[test]
+    fn test_render_images() {
+        let (question, first, second) = ("q".to_owned(), "a".to_owned(), "b".to_owned());
+        let task = SubmitSolver::builder()
+            .question(&question)
+            .images(vec![&first, &second])
+            .build();
+        let solver = solver(json!({ "images": "{images}", "image": "{image}" }));
+
+        assert!(solver.batch());
+        assert_eq!(
+            solver.render(&solver.config.request, &task),
+            json!({ "images": ["a", "b"], "image": null })
+        );
+    }
+
+    #[test]
+    fn test_render_in_string() {
+        let question = "3d_rollball_objects".to_owned();
+        let task = SubmitSolver::builder().question(&question).build();
+        let mut solver = solver(json!({
+            "question": "prefix-{question}",
+            "note": "{question}/{image}/{images}",
+        }));
+        solver.config.headers = BTreeMap::from([
+            ("Authorization".to_owned(), "Bearer {client_key}".to_owned()),
+            ("X-Question".to_owned(), "{question}".to_owned()),
+        ]);
+
+        assert!(!solver.batch());
+        assert_eq!(
+            solver.render(&solver.config.request, &task),
+            json!({
+                "question": "prefix-3d_rollball_objects",
+                "note": "3d_rollball_objects//{images}",
+            })
+        );
+        assert_eq!(
+            solver.render_headers(&task),
+            vec![
+                ("Authorization".to_owned(), "Bearer key".to_owned()),
+                ("X-Question".to_owned(), "3d_rollball_objects".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lookup() {
+        let value = json!({ "data": { "objects": [3, 1], "items": [{ "id": 7 }] } });
+
+        assert_eq!(lookup(&value, "data.objects"), Some(&json!([3, 1])));
+        assert_eq!(lookup(&value, "data.items.0.id"), Some(&json!(7)));
+        assert_eq!(lookup(&value, ".data..objects."), Some(&json!([3, 1])));
+        assert_eq!(lookup(&value, ""), Some(&value));
+        assert_eq!(lookup(&value, "data.items.1"), None);
+        assert_eq!(lookup(&value, "data.missing"), None);
+    }
+
+    #[test]
+    fn test_answer_indexes() {
+        assert_eq!(answer_indexes(&json!([3, 1])).unwrap(), vec![3, 1]);
+        assert_eq!(answer_indexes(&json!(2)).unwrap(), vec![2]);
+
+        for (answer, invalid) in [
+            (json!([3, 1.5]), "1.5"),
+            (json!(["2"]), "\"2\""),
+            (json!(4294967296_i64), "4294967296"),
+        ] {
+            let err = answer_indexes(&answer).unwrap_err();
+            assert!(err.to_string().contains(invalid), "{err}");
+        }
+    }
+}
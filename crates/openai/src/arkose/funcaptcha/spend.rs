@@ -0,0 +1,301 @@
+use super::solver::{ArkoseSolver, SolverBudget};
+use crate::arkose::error::ArkoseError;
+use crate::context::database;
+use crate::{now_duration, warn};
+use native_db::*;
+use native_model::{native_model, Model};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+static DATABASE_BUILDER: OnceLock<DatabaseBuilder> = OnceLock::new();
+static DATABASE: OnceLock<Option<Database<'static>>> = OnceLock::new();
+static COUNTERS: OnceLock<Mutex<HashMap<String, Counter>>> = OnceLock::new();
+static SEMAPHORES: OnceLock<Mutex<HashMap<String, (usize, Arc<Semaphore>)>>> = OnceLock::new();
+
+/// Tasks submitted to a solver in the current window
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[native_model(id = 4, version = 1)]
+#[native_db]
+struct Counter {
+    /// `{solver}|minute` or `{solver}|day`
+    #[primary_key]
+    key: String,
+    /// Window index since the unix epoch
+    window: u64,
+    count: u64,
+}
+
+#[derive(Clone, Copy)]
+enum Window {
+    Minute,
+    Day,
+}
+
+const WINDOWS: [Window; 2] = [Window::Minute, Window::Day];
+
+impl Window {
+    fn key(&self, name: &str) -> String {
+        format!("{name}|{}", self.name())
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Window::Minute => "minute",
+            Window::Day => "day",
+        }
+    }
+
+    fn seconds(&self) -> u64 {
+        match self {
+            Window::Minute => 60,
+            Window::Day => 86400,
+        }
+    }
+
+    fn limit(&self, budget: &SolverBudget) -> u64 {
+        match self {
+            Window::Minute => budget.tasks_per_minute,
+            Window::Day => budget.tasks_per_day,
+        }
+    }
+}
+
+fn database() -> Option<&'static Database<'static>> {
+    DATABASE
+        .get_or_init(|| {
+            database::open(
+                &DATABASE_BUILDER,
+                |builder| builder.define::<Counter>().expect("define table failed"),
+                "solver.db",
+            )
+        })
+        .as_ref()
+}
+
+/// Take a concurrency slot of the solver and check one task fits its budget.
+/// The slot is released when the permit is dropped
+pub(crate) fn acquire(solver: &ArkoseSolver) -> Result<Option<OwnedSemaphorePermit>, ArkoseError> {
+    let name = solver.solver.to_string();
+    let permit = match solver.budget.concurrency {
+        0 => None,
+        concurrency => {
+            let semaphore = semaphore(&name, concurrency);
+            let permit = semaphore.try_acquire_owned().map_err(|_| {
+                ArkoseError::SolverLimitReached(name.clone(), "concurrency", concurrency as u64)
+            })?;
+            Some(permit)
+        }
+    };
+    count(&name, &solver.budget, 1, false)?;
+    Ok(permit)
+}
+
+/// Check the tasks of a whole challenge fit the budget before the first is submitted
+pub(crate) fn check(solver: &ArkoseSolver, tasks: u64) -> Result<(), ArkoseError> {
+    count(&solver.solver.to_string(), &solver.budget, tasks, false)
+}
+
+/// Count the tasks submitted to the solver, rejected when the budget is spent
+pub(crate) fn record(solver: &ArkoseSolver, tasks: u64) -> Result<(), ArkoseError> {
+    count(&solver.solver.to_string(), &solver.budget, tasks, true)
+}
+
+fn semaphore(name: &str, concurrency: usize) -> Arc<Semaphore> {
+    let mut semaphores = lock(SEMAPHORES.get_or_init(Default::default));
+    match semaphores.get(name) {
+        Some((permits, semaphore)) if concurrency.eq(permits) => semaphore.clone(),
+        // New solver or the concurrency is reloaded
+        _ => {
+            let semaphore = Arc::new(Semaphore::new(concurrency));
+            semaphores.insert(name.to_owned(), (concurrency, semaphore.clone()));
+            semaphore
+        }
+    }
+}
+
+/// Check the tasks against the budget, they are only counted when `commit` is set
+fn count(name: &str, budget: &SolverBudget, tasks: u64, commit: bool) -> Result<(), ArkoseError> {
+    if budget.tasks_per_minute == 0 && budget.tasks_per_day == 0 {
+        return Ok(());
+    }
+
+    let now = now_duration()?.as_secs();
+    let mut counters = lock(COUNTERS.get_or_init(Default::default));
+
+    // Counters survive restarts in the database
+    for window in WINDOWS {
+        let key = window.key(name);
+        if !counters.contains_key(&key) {
+            if let Some(counter) = load(&key) {
+                counters.insert(key, counter);
+            }
+        }
+    }
+
+    let updated = spend(&mut counters, name, budget, now, tasks, commit)?;
+    if updated.is_empty() {
+        return Ok(());
+    }
+
+    if let Some(db) = database() {
+        if let Some(err) = write(db, updated).err() {
+            warn!("Failed to store solver spend counters: {err}")
+        }
+    }
+
+    Ok(())
+}
+
+/// Check every window before counting, rejected tasks are not counted.
+/// Returns the updated counters
+fn spend(
+    counters: &mut HashMap<String, Counter>,
+    name: &str,
+    budget: &SolverBudget,
+    now: u64,
+    tasks: u64,
+    commit: bool,
+) -> Result<Vec<Counter>, ArkoseError> {
+    for window in WINDOWS {
+        let key = window.key(name);
+        let index = now / window.seconds();
+        let counter = counters.entry(key.clone()).or_insert_with(|| Counter {
+            key,
+            window: index,
+            count: 0,
+        });
+
+        if counter.window != index {
+            counter.window = index;
+            counter.count = 0;
+        }
+
+        let limit = window.limit(budget);
+        if limit > 0 && counter.count + tasks > limit {
+            return Err(ArkoseError::SolverLimitReached(
+                name.to_owned(),
+                window.name(),
+                limit,
+            ));
+        }
+    }
+
+    if !commit || tasks == 0 {
+        return Ok(vec![]);
+    }
+
+    let updated = WINDOWS
+        .iter()
+        .filter_map(|window| counters.get_mut(&window.key(name)))
+        .map(|counter| {
+            counter.count += tasks;
+            counter.clone()
+        })
+        .collect::<Vec<Counter>>();
+
+    Ok(updated)
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+fn load(key: &str) -> Option<Counter> {
+    let r = database()?.r_transaction().ok()?;
+    let counter = r.get().primary::<Counter>(key.to_owned()).ok().flatten();
+    counter
+}
+
+fn write(db: &Database<'static>, counters: Vec<Counter>) -> anyhow::Result<()> {
+    let rw = db.rw_transaction()?;
+    for counter in counters {
+        rw.insert(counter)?;
+    }
+    rw.commit()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DAY: u64 = 86400;
+
+    fn budget(tasks_per_minute: u64, tasks_per_day: u64) -> SolverBudget {
+        SolverBudget {
+            concurrency: 0,
+            tasks_per_minute,
+            tasks_per_day,
+        }
+    }
+
+    fn submit(
+        counters: &mut HashMap<String, Counter>,
+        budget: &SolverBudget,
+        now: u64,
+        tasks: u64,
+    ) -> bool {
+        spend(counters, "test", budget, now, tasks, true).is_ok()
+    }
+
+    #[test]
+    fn test_limit_boundary() {
+        let budget = budget(3, 0);
+        let mut counters = HashMap::new();
+
+        assert!(submit(&mut counters, &budget, 0, 2));
+        // Exactly reaching the limit is allowed
+        assert!(submit(&mut counters, &budget, 1, 1));
+        assert!(!submit(&mut counters, &budget, 2, 1));
+        assert_eq!(counters["test|minute"].count, 3);
+    }
+
+    #[test]
+    fn test_check_covers_whole_challenge() {
+        let budget = budget(5, 0);
+        let mut counters = HashMap::new();
+        assert!(submit(&mut counters, &budget, 0, 2));
+
+        // A challenge of four images does not fit the three tasks left
+        assert!(spend(&mut counters, "test", &budget, 1, 4, false).is_err());
+        assert!(spend(&mut counters, "test", &budget, 1, 3, false).is_ok());
+        // Checking does not count
+        assert_eq!(counters["test|minute"].count, 2);
+    }
+
+    #[test]
+    fn test_minute_rollover() {
+        let budget = budget(2, 0);
+        let mut counters = HashMap::new();
+
+        assert!(submit(&mut counters, &budget, 60, 2));
+        assert!(!submit(&mut counters, &budget, 119, 1));
+        assert!(submit(&mut counters, &budget, 120, 2));
+        assert_eq!(counters["test|minute"].window, 2);
+    }
+
+    #[test]
+    fn test_day_rollover() {
+        let budget = budget(0, 3);
+        let mut counters = HashMap::new();
+
+        assert!(submit(&mut counters, &budget, DAY - 120, 2));
+        assert!(submit(&mut counters, &budget, DAY - 60, 1));
+        assert!(!submit(&mut counters, &budget, DAY - 1, 1));
+        assert!(submit(&mut counters, &budget, DAY, 3));
+        assert_eq!(counters["test|day"].count, 3);
+    }
+
+    #[test]
+    fn test_rejected_tasks_are_not_counted() {
+        let budget = budget(10, 2);
+        let mut counters = HashMap::new();
+
+        assert!(submit(&mut counters, &budget, 0, 2));
+        // The minute window has room, the day window rejects
+        assert!(!submit(&mut counters, &budget, 1, 1));
+        assert_eq!(counters["test|minute"].count, 2);
+    }
+}
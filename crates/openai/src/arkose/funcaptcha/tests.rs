@@ -0,0 +1,287 @@
+//! Replay the funcaptcha snapshots in `snapshots/` against a local mock Arkose server.
+//!
+//! The snapshots are not recorded sessions, each one pins the `/fc/gfct/` challenge and
+//! `/fc/ca/` answer response shapes of a game type and breaker version. Their expected
+//! `guess` is computed by `snapshots/api_breaker.js`, a JS reference of the breaker tables
+//! kept apart from `breaker.rs`; run it with node after editing a snapshot. Passing does not
+//! prove compatibility with the live service, a recorded session can be added as another
+//! file with its tokens replaced.
+//!
+//! `{origin}` in a snapshot is replaced with the mock server address. `guess` is the expected
+//! breaker payload of every round when the rng always yields zero, `"*"` matches any value.
+//! A multi-wave challenge is `not answered` until every round is submitted, a snapshot with
+//! `error` is expected to be rejected before solving.
+
+use super::*;
+use crate::arkose::crypto;
+use axum::extract::{Query, State};
+use axum::http::HeaderMap;
+use axum::routing::{get, post};
+use axum::{Form, Json, Router};
+use rand::rngs::mock::StepRng;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+const SNAPSHOTS: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/src/arkose/funcaptcha/snapshots"
+);
+
+#[derive(Deserialize)]
+struct Snapshot {
+    session_token: String,
+    sid: String,
+    challenge: Value,
+    game_variant: String,
+    instructions: String,
+    answers: Vec<Vec<i32>>,
+    guess: Vec<Value>,
+    answer_response: Value,
+    error: Option<String>,
+}
+
+/// The `/fc/ca/` request received by the mock server
+struct Submitted {
+    headers: HeaderMap,
+    form: HashMap<String, String>,
+}
+
+#[derive(Clone)]
+struct Mock {
+    snapshot: Arc<Snapshot>,
+    submitted: Arc<Mutex<Vec<Submitted>>>,
+}
+
+fn image(index: usize) -> Vec<u8> {
+    format!("image-{index}").into_bytes()
+}
+
+fn waves(snapshot: &Snapshot) -> u64 {
+    let waves = &snapshot.challenge["game_data"]["waves"];
+    waves.as_u64().unwrap_or(1)
+}
+
+async fn challenge(State(mock): State<Mock>) -> Json<Value> {
+    Json(mock.snapshot.challenge.clone())
+}
+
+async fn answer(
+    State(mock): State<Mock>,
+    headers: HeaderMap,
+    Form(form): Form<HashMap<String, String>>,
+) -> Json<Value> {
+    let snapshot = &mock.snapshot;
+    let guess = crypto::decrypt(form["guess"].as_bytes().to_vec(), &snapshot.session_token)
+        .ok()
+        .and_then(|guess| serde_json::from_str::<Vec<Value>>(&guess).ok())
+        .unwrap_or_default();
+    mock.submitted
+        .lock()
+        .unwrap()
+        .push(Submitted { headers, form });
+
+    if waves(snapshot) > 1 && guess.len() < snapshot.answers.len() {
+        return Json(serde_json::json!({ "response": "not answered" }));
+    }
+    Json(snapshot.answer_response.clone())
+}
+
+async fn challenge_image(Query(query): Query<HashMap<String, String>>) -> Vec<u8> {
+    image(query["challenge"].parse().unwrap())
+}
+
+async fn serve(listener: TcpListener, mock: Mock) {
+    let router = Router::new()
+        .route("/fc/gc/", get(|| async {}))
+        .route("/fc/a/", post(|| async {}))
+        .route("/fc/gfct/", post(challenge))
+        .route("/fc/ca/", post(answer))
+        .route("/rtig/image", get(challenge_image))
+        .with_state(mock);
+
+    axum::Server::from_tcp(listener)
+        .unwrap()
+        .serve(router.into_make_service())
+        .await
+        .unwrap();
+}
+
+/// Compare the value with the expected value, the `"*"` string matches any value
+fn matches(value: &Value, expected: &Value) -> bool {
+    match (value, expected) {
+        (_, Value::String(s)) if s == "*" => true,
+        // JS numbers have no integer type
+        (Value::Number(a), Value::Number(b)) => a.as_f64() == b.as_f64(),
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| matches(a, b))
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len()
+                && b.iter()
+                    .all(|(k, b)| a.get(k).map(|a| matches(a, b)).unwrap_or(false))
+        }
+        _ => value == expected,
+    }
+}
+
+async fn replay(path: &Path) {
+    let name = path.file_stem().unwrap().to_string_lossy().to_string();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let origin = format!("http://{}", listener.local_addr().unwrap());
+    let content = std::fs::read_to_string(path).unwrap();
+    let snapshot = serde_json::from_str::<Snapshot>(&content.replace("{origin}", &origin))
+        .unwrap_or_else(|err| panic!("{name}: {err}"));
+    let snapshot = Arc::new(snapshot);
+
+    let submitted = Arc::new(Mutex::new(Vec::new()));
+    tokio::spawn(serve(
+        listener,
+        Mock {
+            snapshot: snapshot.clone(),
+            submitted: submitted.clone(),
+        },
+    ));
+
+    let mut session = Session {
+        origin: Box::leak(origin.into_boxed_str()),
+        version: Arc::new(ArkoseVersion::default()),
+        sid: snapshot.sid.clone(),
+        session_token: snapshot.session_token.clone(),
+        headers: header::HeaderMap::new(),
+        challenge: None,
+        funcaptcha: None,
+        game_type: 0,
+        tguess_endpoint: None,
+        client: reqwest::Client::new(),
+    };
+
+    // Challenge
+    let result = session.request_challenge().await;
+    if let Some(error) = snapshot.error.as_deref() {
+        let err = result
+            .err()
+            .unwrap_or_else(|| panic!("{name}: not rejected"));
+        assert!(err.to_string().contains(error), "{name}: {err}");
+        return;
+    }
+    let concise_challenge = result.unwrap_or_else(|err| panic!("{name}: {err}"));
+    assert_eq!(
+        concise_challenge.game_variant, snapshot.game_variant,
+        "{name}"
+    );
+    assert_eq!(
+        concise_challenge.instructions, snapshot.instructions,
+        "{name}"
+    );
+    assert_eq!(
+        Some(session.game_type as u64),
+        snapshot.challenge["game_data"]["gameType"].as_u64(),
+        "{name}"
+    );
+
+    let images = session
+        .download_image_to_base64(&concise_challenge.urls)
+        .await
+        .unwrap_or_else(|err| panic!("{name}: {err}"));
+    let expected = (0..snapshot.answers.len())
+        .map(|index| general_purpose::STANDARD.encode(image(index)))
+        .collect::<Vec<String>>();
+    assert_eq!(images, expected, "{name}");
+
+    // Breaker
+    let guess = session
+        .guess(&snapshot.answers, &mut StepRng::new(0, 0))
+        .unwrap_or_else(|err| panic!("{name}: {err}"));
+    let values = guess
+        .iter()
+        .map(|answer| serde_json::from_str::<Value>(answer).unwrap())
+        .collect::<Vec<Value>>();
+    assert!(
+        matches(&Value::Array(values), &Value::Array(snapshot.guess.clone())),
+        "{name}: {guess:?}"
+    );
+
+    // Submit
+    let result = session.submit_waves(&guess).await;
+    assert_eq!(
+        Some(result.is_ok()),
+        snapshot.answer_response["solved"].as_bool(),
+        "{name}: {result:?}"
+    );
+
+    // Every wave submits one more round
+    let mut submitted = std::mem::take(&mut *submitted.lock().unwrap());
+    let expected = if waves(&snapshot) > 1 {
+        snapshot.answers.len()
+    } else {
+        1
+    };
+    assert_eq!(submitted.len(), expected, "{name}");
+    let submitted = submitted
+        .pop()
+        .unwrap_or_else(|| panic!("{name}: no answer submitted"));
+    assert_eq!(
+        submitted.form["session_token"], snapshot.session_token,
+        "{name}"
+    );
+    assert_eq!(submitted.form["sid"], snapshot.sid, "{name}");
+    assert_eq!(
+        Some(submitted.form["game_token"].as_str()),
+        snapshot.challenge["challengeID"].as_str(),
+        "{name}"
+    );
+
+    let guess = crypto::decrypt(
+        submitted.form["guess"].as_bytes().to_vec(),
+        &snapshot.session_token,
+    )
+    .unwrap_or_else(|err| panic!("{name}: {err}"));
+    assert!(
+        matches(
+            &serde_json::from_str::<Value>(&guess).unwrap(),
+            &Value::Array(snapshot.guess.clone())
+        ),
+        "{name}: {guess}"
+    );
+
+    let request_id = submitted
+        .headers
+        .get("X-Requested-ID")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_else(|| panic!("{name}: no X-Requested-ID"));
+    assert!(
+        crypto::decrypt(
+            request_id.as_bytes().to_vec(),
+            &format!("REQUESTED{}ID", snapshot.session_token)
+        )
+        .is_ok(),
+        "{name}"
+    );
+}
+
+#[tokio::test]
+async fn test_replay_snapshots() {
+    let mut paths = std::fs::read_dir(SNAPSHOTS)
+        .unwrap()
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().map(|ext| ext == "json").unwrap_or(false))
+        .collect::<Vec<PathBuf>>();
+    paths.sort();
+    assert!(!paths.is_empty());
+
+    for path in paths {
+        replay(&path).await;
+    }
+}
+
+#[test]
+fn test_crypto_roundtrip() {
+    let data = r#"[{"index":29},{"index":7}]"#;
+    let encrypted = crypto::encrypt(data, "4a3d5d1f8e1b2c9d7.6021154603").unwrap();
+    let decrypted = crypto::decrypt(encrypted.into_bytes(), "4a3d5d1f8e1b2c9d7.6021154603");
+    assert_eq!(decrypted.unwrap(), data);
+}
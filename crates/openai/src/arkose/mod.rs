@@ -1,13 +1,19 @@
 mod blob;
 pub mod crypto;
 mod error;
+pub mod fingerprint;
 pub mod funcaptcha;
 pub mod murmur;
+pub mod pool;
+pub mod registry;
 
 use base64::engine::general_purpose;
 use rand::thread_rng;
 use reqwest::Client;
 use serde::Serialize;
+use std::borrow::Cow;
+use std::net::IpAddr;
+use std::path::PathBuf;
 use std::str::FromStr;
 use typed_builder::TypedBuilder;
 
@@ -15,15 +21,20 @@ use base64::Engine;
 use rand::Rng;
 use regex::Regex;
 use reqwest::Method;
+use reqwest::RequestBuilder;
 use serde::Deserialize;
 use tokio::sync::OnceCell;
 
+use self::fingerprint::Fingerprint;
+use self::funcaptcha::model::FunCaptcha;
 use self::funcaptcha::solver::ArkoseSolver;
-use self::funcaptcha::solver::Solver;
+use self::funcaptcha::solver::CaptchaSolver;
 use self::funcaptcha::solver::SubmitSolver;
 use crate::context::arkose::har;
+use crate::debug;
 use crate::generate_random_string;
 use crate::gpt_model::GPTModel;
+use crate::metrics::{self, ArkoseSource};
 use crate::now_duration;
 use crate::warn;
 use crate::with_context;
@@ -56,62 +67,55 @@ pub enum Type {
 }
 
 impl Type {
+    /// All arkose types
+    pub const ALL: [Type; 5] = [
+        Type::GPT3,
+        Type::GPT4,
+        Type::Auth,
+        Type::SignUp,
+        Type::Platform,
+    ];
+
     /// From public key to type
     pub fn from_pk(pk: &str) -> anyhow::Result<Self> {
-        let typed = match pk {
-            "3D86FBBA-9D22-402A-B512-3420086BA6CC" => Type::GPT3,
-            "35536E1E-65B4-4D96-9D97-6ADB7EFF8147" => Type::GPT4,
-            "0A1D34FC-659D-4E23-B17B-694DCFCF6A6C" => Type::Auth,
-            "0655BC92-82E1-43D9-B32E-9DF9B01AF50C" => Type::SignUp,
-            "23AAD243-4799-4A9E-B01D-1166C5DE02DF" => Type::Platform,
-            _ => anyhow::bail!(ArkoseError::InvalidPublicKey(pk.to_owned())),
-        };
-        Ok(typed)
+        registry::get(pk)
+            .map(|key| key.typed)
+            .ok_or_else(|| ArkoseError::InvalidPublicKey(pk.to_owned()).into())
     }
 
     /// Get the public key
     pub fn pk(&self) -> &'static str {
-        match self {
-            Type::GPT3 => "3D86FBBA-9D22-402A-B512-3420086BA6CC",
-            Type::GPT4 => "35536E1E-65B4-4D96-9D97-6ADB7EFF8147",
-            Type::Auth => "0A1D34FC-659D-4E23-B17B-694DCFCF6A6C",
-            Type::SignUp => "0655BC92-82E1-43D9-B32E-9DF9B01AF50C",
-            Type::Platform => "23AAD243-4799-4A9E-B01D-1166C5DE02DF",
-        }
+        &registry::default_key(*self).pk
     }
 
     /// Get the site
     pub fn site_url(&self) -> &'static str {
-        match self {
-            Type::GPT3 | Type::GPT4 => "https://chat.openai.com",
-            Type::Platform | Type::SignUp => "https://platform.openai.com",
-            Type::Auth => "https://auth0.openai.com",
-        }
+        &registry::default_key(*self).site_url
     }
 
     /// Get site to origin
     pub fn site_host(&self) -> &'static str {
-        match self {
-            Type::GPT3 | Type::GPT4 => "chat.openai.com",
-            Type::Platform | Type::SignUp => "platform.openai.com",
-            Type::Auth => "auth0.openai.com",
-        }
+        registry::default_key(*self).site_host()
     }
 
     /// Get the origin
     pub fn origin_host(&self) -> &'static str {
-        match self {
-            Type::GPT3 | Type::GPT4 | Type::Auth => "tcr9i.openai.com",
-            Type::Platform | Type::SignUp => "openai-api.arkoselabs.com",
-        }
+        registry::default_key(*self).origin_host()
     }
 
     /// Get the origin url
     pub fn origin_url(&self) -> &'static str {
+        &registry::default_key(*self).origin_url
+    }
+
+    /// Get the type name
+    pub fn name(&self) -> &'static str {
         match self {
-            Type::Auth => "https://tcr9i.openai.com",
-            Type::GPT3 | Type::GPT4 => "https://tcr9i.chat.openai.com",
-            Type::Platform | Type::SignUp => "https://openai-api.arkoselabs.com",
+            Type::GPT3 => "gpt3",
+            Type::GPT4 => "gpt4",
+            Type::Auth => "auth",
+            Type::SignUp => "signup",
+            Type::Platform => "platform",
         }
     }
 }
@@ -133,12 +137,26 @@ impl FromStr for Type {
             "gpt3" => Ok(Type::GPT3),
             "gpt4" => Ok(Type::GPT4),
             "auth" => Ok(Type::Auth),
+            "signup" => Ok(Type::SignUp),
             "platform" => Ok(Type::Platform),
             _ => anyhow::bail!(ArkoseError::InvalidPlatformType(s.to_owned())),
         }
     }
 }
 
+impl Serialize for Type {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for Type {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Type::from_str(&s).map_err(serde::de::Error::custom)
+    }
+}
+
 #[derive(TypedBuilder, Clone)]
 pub struct ArkoseContext {
     #[builder(setter(into), default)]
@@ -146,9 +164,31 @@ pub struct ArkoseContext {
     typed: Type,
     #[builder(setter(into), default)]
     identifier: Option<String>,
+    /// Label of the egress client, keeps the fingerprint stable without identifier
+    #[builder(setter(into), default)]
+    egress: Option<String>,
+    /// Address chosen from the IPv6 subnet, the subnet clients share one label
+    #[builder(setter(into), default)]
+    egress_addr: Option<IpAddr>,
+    /// Registered public key, defaults to the key of the type
+    #[builder(setter(into), default)]
+    public_key: Option<&'static registry::PublicKey>,
+    /// Preferred token source
+    #[builder(setter(into), default)]
+    source: Option<TokenSource>,
+    /// HAR file the token was generated from
+    #[builder(default, setter(skip))]
+    har_file: Option<PathBuf>,
     client: Client,
 }
 
+impl ArkoseContext {
+    fn public_key(&self) -> &'static registry::PublicKey {
+        self.public_key
+            .unwrap_or_else(|| registry::default_key(self.typed))
+    }
+}
+
 #[derive(TypedBuilder)]
 pub struct ArkoseSolverContext {
     user_agent: Option<String>,
@@ -157,11 +197,39 @@ pub struct ArkoseSolverContext {
     client: Client,
 }
 
+/// Estimated lifetime of an arkose token (second), ArkoseLabs does not publish it
+pub const TOKEN_LIFETIME: u64 = 120;
+
+/// Where the arkose token came from
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenSource {
+    Har,
+    Bx,
+    Pool,
+    Experiment,
+}
+
+/// How the arkose token was generated, not part of the token payload
+#[derive(Serialize, Clone, Copy, Debug, Default)]
+pub struct TokenMeta {
+    /// Token source, none for tokens given by the client
+    pub source: Option<TokenSource>,
+    /// Taken from the pre-generated pool
+    pub pooled: bool,
+    /// Funcaptcha rounds solved, 0 when the token was issued without challenge
+    pub rounds: usize,
+    /// Unix timestamp of the token generation
+    pub created: u64,
+}
+
 /// curl 'https://tcr9i.openai.com/fc/gt2/public_key/35536E1E-65B4-4D96-9D97-6ADB7EFF8147' --data-raw 'public_key=35536E1E-65B4-4D96-9D97-6ADB7EFF8147'
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ArkoseToken {
     token: String,
     styles: serde_json::Value,
+    #[serde(skip)]
+    meta: TokenMeta,
 }
 
 impl From<&str> for ArkoseToken {
@@ -169,6 +237,7 @@ impl From<&str> for ArkoseToken {
         ArkoseToken {
             token: value.to_owned(),
             styles: serde_json::Value::Null,
+            meta: TokenMeta::default(),
         }
     }
 }
@@ -178,6 +247,7 @@ impl From<String> for ArkoseToken {
         ArkoseToken {
             token: value,
             styles: serde_json::Value::Null,
+            meta: TokenMeta::default(),
         }
     }
 }
@@ -194,6 +264,11 @@ impl ArkoseToken {
         &self.token
     }
 
+    /// Get the token generation meta
+    pub fn meta(&self) -> &TokenMeta {
+        &self.meta
+    }
+
     /// Check if the token is valid
     pub fn success(&self) -> bool {
         self.token.contains("sup=1")
@@ -232,39 +307,56 @@ impl ArkoseToken {
     }
 
     #[inline]
+    #[tracing::instrument(name = "arkose.bx", skip_all, fields(typed = ?ctx.typed))]
     pub async fn new(ctx: &mut ArkoseContext) -> anyhow::Result<Self> {
         let regex = get_or_init_regex().await;
 
-        let (bx, capi_mode) = match ctx.typed {
-            Type::GPT3 => (GPT3_BX, "inline"),
-            Type::GPT4 => (GPT4_BX, "lightbox"),
-            Type::Auth => (AUTH_BX, "lightbox"),
-            Type::SignUp => (PLATFORM_BX, "lightbox"),
-            Type::Platform => (PLATFORM_BX, "lightbox"),
+        let public_key = ctx.public_key();
+        let bx = public_key.template();
+        let capi_mode = match ctx.typed {
+            Type::GPT3 => "inline",
+            _ => "lightbox",
         };
 
         let version = with_context!(arkose_context)
             .version(ctx.typed)
             .ok_or_else(|| ArkoseError::ArkoseVersionNotFound)?;
 
-        let site = ctx.typed.site_url();
-        let pk = ctx.typed.pk();
-
-        let bv = ctx
-            .client
-            .user_agent()
-            .map(|h| h.to_str().ok())
-            .flatten()
-            .unwrap_or("okhttp/4.9.1");
+        let site = public_key.site_url.as_str();
+        let pk = public_key.pk.as_str();
+
+        let bv = match ctx.user_agent.as_deref() {
+            Some(user_agent) => user_agent,
+            None => ctx
+                .client
+                .user_agent()
+                .map(|h| h.to_str().ok())
+                .flatten()
+                .unwrap_or("okhttp/4.9.1"),
+        };
         let bt = now_duration()?.as_secs();
         let bw = bt - (bt % 21600);
-        let bx = regex.replace_all(
-            bx,
-            format!(
-                r#"{{"key":"n","value":"{}"}}"#,
-                general_purpose::STANDARD.encode(bt.to_string())
+
+        // One stable fingerprint per identity, or per egress address without identity
+        let fingerprint = with_context!(arkose_random_fingerprint).then(|| {
+            let key = match (ctx.identifier.as_deref(), ctx.egress_addr) {
+                (Some(identifier), _) => identifier.to_owned(),
+                (None, Some(addr)) => addr.to_string(),
+                (None, None) => ctx.egress.clone().unwrap_or_default(),
+            };
+            Fingerprint::new(&key, bv)
+        });
+
+        let bx = match fingerprint {
+            Some(ref fingerprint) => Cow::Owned(fingerprint.bda(bx, bt)?),
+            None => regex.replace_all(
+                bx,
+                format!(
+                    r#"{{"key":"n","value":"{}"}}"#,
+                    general_purpose::STANDARD.encode(bt.to_string())
+                ),
             ),
-        );
+        };
 
         let mut form = vec![
             (
@@ -281,15 +373,31 @@ impl ArkoseToken {
         ];
 
         // If identifier is not empty, get blob
-        if let Ok(Some(blob)) = blob::get_blob(ctx.typed, ctx.identifier.clone()).await {
+        if let Ok(Some(blob)) = blob::get_blob(public_key.blob, ctx.identifier.clone()).await {
             form.push(("data[blob]", blob));
         }
 
-        let arkose_token = ctx
+        let (accept_language, sec_ch_ua, sec_ch_ua_platform) = match fingerprint {
+            Some(fingerprint) => (
+                fingerprint.accept_language(),
+                fingerprint.sec_ch_ua(),
+                fingerprint.sec_ch_ua_platform(),
+            ),
+            None => (
+                "zh-CN,zh;q=0.9",
+                Some(
+                    "\"Not_A Brand\";v=\"8\", \"Chromium\";v=\"120\", \"Google Chrome\";v=\"120\""
+                        .to_owned(),
+                ),
+                "\"macOS\"".to_owned(),
+            ),
+        };
+
+        let mut builder = ctx
             .client
-            .post(format!("{}/fc/gt2/public_key/{pk}", ctx.typed.origin_url()))
+            .post(format!("{}/fc/gt2/public_key/{pk}", public_key.origin_url))
             .header("Accept", "*/*")
-            .header("Accept-Language", "zh-CN,zh;q=0.9")
+            .header("Accept-Language", accept_language)
             .header(
                 "Content-Type",
                 "application/x-www-form-urlencoded; charset=UTF-8",
@@ -303,30 +411,29 @@ impl ArkoseToken {
                 ),
             )
             .header("DNT", "1")
-            .header("Origin", ctx.typed.origin_url())
-            .header("Referer", ctx.typed.origin_url())
+            .header("Origin", &public_key.origin_url)
+            .header("Referer", &public_key.origin_url)
             .header("Sec-Fetch-Dest", "empty")
             .header("Sec-Fetch-Mode", "cors")
             .header("Sec-Fetch-Site", "same-origin")
-            .header("User-Agent", bv)
-            .header(
-                "sec-ch-ua",
-                "\"Not_A Brand\";v=\"8\", \"Chromium\";v=\"120\", \"Google Chrome\";v=\"120\"",
-            )
-            .header("sec-ch-ua-mobile", "?0")
-            .header("sec-ch-ua-platform", "\"macOS\"")
-            .body(serde_urlencoded::to_string(&form)?)
-            .send()
-            .await?
-            .error_for_status()?
-            .json::<ArkoseToken>()
-            .await?;
+            .header("User-Agent", bv);
+
+        if let Some(sec_ch_ua) = sec_ch_ua {
+            builder = builder
+                .header("sec-ch-ua", sec_ch_ua)
+                .header("sec-ch-ua-mobile", "?0")
+                .header("sec-ch-ua-platform", sec_ch_ua_platform);
+        }
+
+        let builder = builder.body(serde_urlencoded::to_string(&form)?);
+        let arkose_token = send_public_key(builder, ctx.egress.as_deref()).await?;
 
         Ok(arkose_token)
     }
 
     /// Get ArkoseLabs token from HAR file (Support ChatGPT, Platform, Auth)
     #[inline]
+    #[tracing::instrument(name = "arkose.har", skip_all, fields(typed = ?ctx.typed))]
     pub async fn new_from_har(ctx: &mut ArkoseContext) -> anyhow::Result<Self> {
         let regex = get_or_init_regex().await;
 
@@ -365,7 +472,8 @@ impl ArkoseToken {
         ));
 
         // If identifier is not empty, get blob
-        if let Ok(Some(blob)) = blob::get_blob(entry.typed, ctx.identifier.clone()).await {
+        let blob_strategy = registry::default_key(entry.typed).blob;
+        if let Ok(Some(blob)) = blob::get_blob(blob_strategy, ctx.identifier.clone()).await {
             entry.body.push_str(&format!("&data[blob]={blob}"));
         }
 
@@ -389,19 +497,18 @@ impl ArkoseToken {
             builder = builder.header(h.name, h.value)
         }
 
+        let arkose_token = send_public_key(builder, ctx.egress.as_deref()).await?;
+
         // Update user agent
         ctx.user_agent = Some(entry.bv);
+        ctx.har_file = entry.file;
 
-        Ok(builder
-            .send()
-            .await?
-            .error_for_status()?
-            .json::<ArkoseToken>()
-            .await?)
+        Ok(arkose_token)
     }
 
     /// Get ArkoseLabs token from context (Support ChatGPT, Platform, Auth)
     #[inline]
+    #[tracing::instrument(name = "arkose.token", skip_all, fields(typed = ?ctx.typed))]
     pub async fn new_from_context(mut ctx: ArkoseContext) -> anyhow::Result<Self> {
         // If enable gpt3 arkoselabs experiment
         if ctx.typed.eq(&Type::GPT3)
@@ -425,39 +532,106 @@ impl ArkoseToken {
             let rid = rng.gen_range(1..=99);
             // experiment token
             let fake_token = format!("{before_dot}.{after_dot}|r=us-west-2|meta=3|metabgclr=transparent|metaiconclr=%23757575|guitextcolor=%23000000|pk=35536E1E-65B4-4D96-9D97-6ADB7EFF8147|at=40|sup=1|rid={rid}|ag=101|cdn_url=https%3A%2F%2Ftcr9i.chat.openai.com%2Fcdn%2Ffc|lurl=https%3A%2F%2Faudio-us-west-2.arkoselabs.com|surl=https%3A%2F%2Ftcr9i.chat.openai.com|smurl=https%3A%2F%2Ftcr9i.chat.openai.com%2Fcdn%2Ffc%2Fassets%2Fstyle-manager");
-            return Ok(ArkoseToken::from(fake_token));
+            let mut arkose_token = ArkoseToken::from(fake_token);
+            arkose_token.meta.source = Some(TokenSource::Experiment);
+            arkose_token.meta.created = now_duration()?.as_secs();
+            return Ok(arkose_token);
         }
 
         // Get arkose solver
-        let arkose_solver = with_context!(arkose_solver);
-        let typed = ctx.typed;
+        let reloadable = with_context!(reloadable);
+        let arkose_solver = reloadable.arkose_solver();
+
+        // If bx is preferred, try it before the har file
+        if ctx.source == Some(TokenSource::Bx) {
+            match ArkoseToken::new(&mut ctx).await {
+                Ok(arkose_token) => {
+                    metrics::inc_arkose_attempt(ArkoseSource::Bx, arkose_token.success());
+                    return Self::solve(arkose_solver, ctx, arkose_token, TokenSource::Bx).await;
+                }
+                Err(err) => {
+                    metrics::inc_arkose_attempt(ArkoseSource::Bx, false);
+                    debug!("Preferred bx failed, fall back to har: {err}");
+                }
+            }
+        }
 
         // If har path is not empty, use har file
         if let Ok(arkose_token) = ArkoseToken::new_from_har(&mut ctx).await {
-            let solver_context = ArkoseSolverContext::builder()
-                .user_agent(ctx.user_agent)
-                .typed(typed)
-                .arkose_token(arkose_token)
-                .client(ctx.client)
-                .build();
-            return Ok(valid_arkose_token(arkose_solver, solver_context).await);
+            metrics::inc_arkose_attempt(ArkoseSource::Har, arkose_token.success());
+            return Self::solve(arkose_solver, ctx, arkose_token, TokenSource::Har).await;
         }
 
         // If arkose solver is not empty, use bx
-        if arkose_solver.is_some() {
-            let arkose_token = ArkoseToken::new(&mut ctx).await?;
-            let solver_context = ArkoseSolverContext::builder()
-                .user_agent(ctx.user_agent)
-                .typed(typed)
-                .arkose_token(arkose_token)
-                .client(ctx.client)
-                .build();
-            return Ok(valid_arkose_token(arkose_solver, solver_context).await);
+        if arkose_solver.is_some() && ctx.source != Some(TokenSource::Bx) {
+            let arkose_token = match ArkoseToken::new(&mut ctx).await {
+                Ok(arkose_token) => arkose_token,
+                Err(err) => {
+                    metrics::inc_arkose_attempt(ArkoseSource::Bx, false);
+                    return Err(err.into());
+                }
+            };
+            metrics::inc_arkose_attempt(ArkoseSource::Bx, arkose_token.success());
+            return Self::solve(arkose_solver, ctx, arkose_token, TokenSource::Bx).await;
         }
 
         Err(ArkoseError::NoSolverAvailable.into())
     }
 
+    /// Record where the token came from and solve its funcaptcha if needed
+    async fn solve(
+        arkose_solver: Option<&ArkoseSolver>,
+        ctx: ArkoseContext,
+        mut arkose_token: ArkoseToken,
+        source: TokenSource,
+    ) -> anyhow::Result<Self> {
+        arkose_token.meta.source = Some(source);
+        arkose_token.meta.created = now_duration()?.as_secs();
+        let solver_context = ArkoseSolverContext::builder()
+            .user_agent(ctx.user_agent)
+            .typed(ctx.typed)
+            .arkose_token(arkose_token)
+            .client(ctx.client)
+            .build();
+        let (arkose_token, verified) = valid_arkose_token(arkose_solver, solver_context).await;
+
+        // Track the HAR file by its own outcome, stale captures are quarantined.
+        // A skipped or failing solver says nothing about the HAR file
+        if let (Some(file), Some(success)) = (ctx.har_file, verified) {
+            har::report(&file, success);
+        }
+        Ok(arkose_token)
+    }
+
+    /// Get ArkoseLabs token from the pre-generated pool, fall back to context when it is empty
+    #[inline]
+    pub async fn new_from_pool(ctx: ArkoseContext) -> anyhow::Result<Self> {
+        if let Some(arkose_token) = Self::take_pooled(&ctx, pool::take) {
+            return Ok(arkose_token);
+        }
+        ArkoseToken::new_from_context(ctx).await
+    }
+
+    /// Pooled tokens are minted without identity, contexts with an identifier or
+    /// user agent need their own token (dx blob, fingerprint)
+    fn take_pooled(
+        ctx: &ArkoseContext,
+        take: impl FnOnce(Type) -> Option<ArkoseToken>,
+    ) -> Option<ArkoseToken> {
+        if ctx.identifier.is_some() || ctx.user_agent.is_some() {
+            return None;
+        }
+
+        // The pool only keeps tokens of the default keys
+        let pooled = ctx
+            .public_key
+            .map(|key| key.pk == ctx.typed.pk())
+            .unwrap_or(true);
+        let mut arkose_token = pooled.then(|| take(ctx.typed)).flatten()?;
+        arkose_token.meta.pooled = true;
+        Some(arkose_token)
+    }
+
     /// Callback to arkose
     #[inline]
     pub async fn callback(&self) -> ArkoseResult<()> {
@@ -513,98 +687,275 @@ impl ArkoseToken {
     }
 }
 
+/// Send the public key request, transport and server errors count against the egress client
+async fn send_public_key(
+    builder: RequestBuilder,
+    egress: Option<&str>,
+) -> anyhow::Result<ArkoseToken> {
+    let result = builder.send().await;
+    if let Some(label) = egress {
+        let failed = result
+            .as_ref()
+            .map_or(true, |resp| resp.status().is_server_error());
+        with_context!(report_arkose_client, label, !failed);
+    }
+
+    Ok(result?.error_for_status()?.json::<ArkoseToken>().await?)
+}
+
+#[tracing::instrument(name = "arkose.solver", skip_all, fields(typed = ?ctx.typed))]
+/// Tiles of a round from the solver answer, tile selection rounds may take several tiles
+fn round_answer(multiple: bool, answer: Vec<i32>) -> Option<Vec<i32>> {
+    match multiple {
+        true => Some(answer).filter(|a| !a.is_empty()),
+        false => answer.first().map(|a| vec![*a]),
+    }
+}
+
+/// Solve the funcaptcha of the token if needed. Also returns whether Arkose accepted the
+/// session: valid without challenge or solved, rejected answers, none when the solver was
+/// skipped or failed
 async fn valid_arkose_token(
-    arkose_solver: Option<&'static ArkoseSolver>,
+    arkose_solver: Option<&ArkoseSolver>,
     ctx: ArkoseSolverContext,
-) -> ArkoseToken {
+) -> (ArkoseToken, Option<bool>) {
     // If success, return token
     if ctx.arkose_token.success() {
         // Submit token to funcaptcha callback
         let _ = ctx.arkose_token.callback().await;
-        return ctx.arkose_token;
+        return (ctx.arkose_token, Some(true));
     }
 
     // If arkose solver is not empty, use solver
     match submit_funcaptcha(arkose_solver, &ctx).await {
         Ok(arkose_token) => {
-            return arkose_token;
+            metrics::inc_arkose_attempt(ArkoseSource::Solver, true);
+            (arkose_token, Some(true))
         }
         Err(err) => {
+            if arkose_solver.is_some() {
+                metrics::inc_arkose_attempt(ArkoseSource::Solver, false);
+            }
             warn!("Funcaptcha solver error: {err}");
-            return ctx.arkose_token;
+            let verified = match err {
+                ArkoseError::FuncaptchaNotSolvedError(_) => Some(false),
+                _ => None,
+            };
+            (ctx.arkose_token, verified)
         }
     }
 }
 
+#[tracing::instrument(name = "funcaptcha.solve", skip_all)]
 async fn submit_funcaptcha(
-    arkose_solver: Option<&'static ArkoseSolver>,
+    arkose_solver: Option<&ArkoseSolver>,
     ctx: &ArkoseSolverContext,
 ) -> ArkoseResult<ArkoseToken> {
     // Try get arkose solver
     let arkose_solver = arkose_solver.ok_or_else(|| ArkoseError::NoSolverAvailable)?;
 
+    // Skip the solver when its spend limits are reached
+    let _permit = funcaptcha::spend::acquire(arkose_solver)?;
+
     // Start challenge, return session
     let session = funcaptcha::start_challenge(&ctx).await?;
 
     let funs = session
         .funcaptcha()
         .ok_or_else(|| ArkoseError::InvalidFunCaptcha)?;
+    let rounds = funs.len();
+
+    // Answer repeated images from the store, only the rest go to the solver
+    let keys = funs
+        .iter()
+        .map(funcaptcha::answer::key)
+        .collect::<Vec<String>>();
+    let mut answers = funcaptcha::answer::get(&keys)
+        .into_iter()
+        .map(|a| a.map(|a| vec![a]))
+        .collect::<Vec<Option<Vec<i32>>>>();
+    let cached = answers.iter().filter(|a| a.is_some()).count();
+    if cached > 0 {
+        debug!(
+            "Funcaptcha answered {cached}/{} images from store",
+            funs.len()
+        );
+    }
 
-    let mut answers = Vec::new();
+    // Which solver answered each image, saved with the images
+    let solver = arkose_solver.solver.to_string();
+    let solvers = answers
+        .iter()
+        .map(|a| match a {
+            Some(_) => "store".to_owned(),
+            None => solver.clone(),
+        })
+        .collect::<Vec<String>>();
+
+    let pending = funs
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| answers[*index].is_none())
+        .collect::<Vec<(usize, &FunCaptcha)>>();
+
+    // A batch answer has one index per image, so tile selection rounds go one image
+    // per task and every returned index is a tile of the round, as without batch
+    let multiple = session.multiple_answers();
+    let chunk_size = match multiple {
+        true => 1,
+        false => arkose_solver.limit.max(1),
+    };
 
-    match arkose_solver.solver {
-        Solver::Yescaptcha => {
-            for (_, fun) in funs.iter().enumerate() {
+    // Check the budget covers every submit of the challenge before the first one
+    let tasks = match arkose_solver.batch() {
+        false => pending.len(),
+        true => {
+            let mut variants = std::collections::HashMap::new();
+            for (_, fun) in pending.iter() {
+                *variants.entry(&fun.game_variant).or_insert(0) += 1;
+            }
+            variants
+                .values()
+                .map(|n| (n + chunk_size - 1) / chunk_size)
+                .sum()
+        }
+    };
+    funcaptcha::spend::check(arkose_solver, tasks as u64)?;
+
+    match arkose_solver.batch() {
+        false => {
+            for (index, fun) in pending {
                 let submit_task = SubmitSolver::builder()
-                    .arkose_solver(arkose_solver)
                     .question(&fun.instructions)
                     .image(&fun.image)
                     .build();
-                answers.extend(funcaptcha::solver::submit_task(submit_task).await?)
+                let answer = arkose_solver.submit_task(submit_task).await?;
+                answers[index] = round_answer(multiple, answer);
             }
         }
-        Solver::Capsolver | Solver::Fcsrv => {
+        true => {
             let mut classified_data = std::collections::HashMap::new();
 
-            for item in funs.iter() {
+            for (index, item) in pending {
                 let question = item.game_variant.clone();
                 classified_data
                     .entry(question)
                     .or_insert(Vec::new())
-                    .push(item);
+                    .push((index, item));
             }
 
             for data in classified_data {
-                let images_chunks = data
-                    .1
-                    .chunks(arkose_solver.limit.max(1))
-                    .map(|item| {
-                        item.iter()
-                            .map(|item| &item.image)
-                            .collect::<Vec<&String>>()
-                    })
-                    .collect::<Vec<Vec<&String>>>();
-
-                for (_, images) in images_chunks.into_iter().enumerate() {
+                for chunk in data.1.chunks(chunk_size) {
+                    let images = chunk
+                        .iter()
+                        .map(|(_, item)| &item.image)
+                        .collect::<Vec<&String>>();
                     let submit_task = SubmitSolver::builder()
-                        .arkose_solver(arkose_solver)
                         .question(&data.0)
                         .images(images)
                         .build();
-                    answers.extend(funcaptcha::solver::submit_task(submit_task).await?)
+                    let answer = arkose_solver.submit_task(submit_task).await?;
+                    match (multiple, chunk) {
+                        (true, [(index, _)]) => answers[*index] = round_answer(true, answer),
+                        _ => {
+                            for ((index, _), answer) in chunk.iter().zip(answer) {
+                                answers[*index] = Some(vec![answer]);
+                            }
+                        }
+                    }
                 }
             }
         }
     };
 
+    let answers = answers
+        .into_iter()
+        .collect::<Option<Vec<Vec<i32>>>>()
+        .ok_or_else(|| {
+            ArkoseError::SolverTaskError("The solver answers fewer images than given".to_owned())
+        })?;
+
     // Submit answers
-    let _ = session.submit_answer(answers.as_slice()).await?;
+    let result = session.submit_answer(answers.as_slice()).await;
+
+    match result {
+        // Store the verified answers, the store only keeps single tile rounds
+        Ok(_) => funcaptcha::answer::insert(
+            &keys
+                .into_iter()
+                .zip(answers.iter())
+                .filter_map(|(key, answer)| match answer.as_slice() {
+                    [answer] => Some((key, *answer)),
+                    _ => None,
+                })
+                .collect::<Vec<(String, i32)>>(),
+        ),
+        // Rejected stored answers are removed
+        Err(_) if cached > 0 => funcaptcha::answer::remove(&keys),
+        Err(_) => {}
+    }
 
-    // Store funcaptcha solved image
-    if let Some(dir) = with_context!(arkose_solver_image_dir) {
-        tokio::spawn(session.save_funcaptcha_to_dir(dir, answers));
+    // Store funcaptcha image with its label, rejected sessions included
+    if let Some(dir) = with_context!(reloadable).arkose_solver_image_dir() {
+        tokio::spawn(session.save_funcaptcha_to_dir(
+            dir.to_path_buf(),
+            answers,
+            solvers,
+            result.is_ok(),
+        ));
     }
 
+    result?;
+
     let new_token = ctx.arkose_token.value().replace("at=40", "at=40|sup=1");
-    Ok(ArkoseToken::from(new_token))
+    let mut arkose_token = ArkoseToken::from(new_token);
+    arkose_token.meta = TokenMeta {
+        rounds,
+        ..ctx.arkose_token.meta
+    };
+    Ok(arkose_token)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn context(identifier: Option<&str>, user_agent: Option<&str>) -> ArkoseContext {
+        ArkoseContext::builder()
+            .typed(Type::GPT4)
+            .identifier(identifier.map(ToOwned::to_owned))
+            .user_agent(user_agent.map(ToOwned::to_owned))
+            .client(Client::new())
+            .build()
+    }
+
+    fn pooled(_: Type) -> Option<ArkoseToken> {
+        Some(ArkoseToken::from("pooled|r=us-east-1|sup=1"))
+    }
+
+    #[test]
+    fn test_pool_skipped_with_identity() {
+        for ctx in [
+            context(Some("access_token"), None),
+            context(None, Some("Mozilla/5.0")),
+            context(Some("blob"), Some("Mozilla/5.0")),
+        ] {
+            assert!(ArkoseToken::take_pooled(&ctx, pooled).is_none());
+        }
+    }
+
+    #[test]
+    fn test_round_answer() {
+        assert_eq!(round_answer(false, vec![3, 5]), Some(vec![3]));
+        assert_eq!(round_answer(true, vec![3, 5]), Some(vec![3, 5]));
+        assert_eq!(round_answer(true, vec![]), None);
+        assert_eq!(round_answer(false, vec![]), None);
+    }
+
+    #[test]
+    fn test_pool_used_without_identity() {
+        let token = ArkoseToken::take_pooled(&context(None, None), pooled).unwrap();
+        assert!(token.meta.pooled);
+        assert_eq!(token.value(), "pooled|r=us-east-1|sup=1");
+    }
 }
@@ -0,0 +1,259 @@
+use super::{ArkoseContext, ArkoseToken, Type};
+use crate::{debug, info, metrics, warn, with_context};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Types not taken for this long are no longer refilled
+const IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+
+static POOL: OnceLock<TokenPool> = OnceLock::new();
+
+/// Pre-generated arkose tokens, kept per type
+struct TokenPool {
+    /// Tokens to keep ready per type
+    size: usize,
+    /// Tokens older than this are dropped
+    ttl: Duration,
+    /// Types not taken within this window stop being refilled
+    idle: Duration,
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    tokens: HashMap<Type, VecDeque<(Instant, ArkoseToken)>>,
+    /// Last take of each type, only recently taken types are kept filled
+    wanted: HashMap<Type, Instant>,
+    /// Types with a running refill task
+    refilling: HashSet<Type>,
+}
+
+/// Init the arkose token pool, disabled when size is 0.
+/// The given types are filled right away instead of on their first take.
+pub fn init(size: usize, ttl: u64, types: &[Type]) {
+    if size == 0 {
+        return;
+    }
+
+    let pool = TokenPool::new(size, Duration::from_secs(ttl.max(1)), IDLE_TIMEOUT, types);
+
+    if POOL.set(pool).is_ok() {
+        info!("Arkose token pool enabled, size: {size}, ttl: {ttl}s, types: {types:?}");
+        tokio::spawn(periodic_refill());
+    }
+}
+
+/// Take the oldest fresh token of the type, the pool is refilled in the background
+pub fn take(typed: Type) -> Option<ArkoseToken> {
+    let pool = POOL.get()?;
+    let token = pool.take(typed);
+    metrics::inc_arkose_pool(typed, token.is_some());
+    spawn_refill(pool, typed);
+    token
+}
+
+/// Drop expired tokens and top up the recently taken types
+async fn periodic_refill() {
+    let pool = match POOL.get() {
+        Some(pool) => pool,
+        None => return,
+    };
+
+    let mut interval = tokio::time::interval((pool.ttl / 2).max(Duration::from_secs(1)));
+    loop {
+        interval.tick().await;
+        pool.wanted()
+            .into_iter()
+            .for_each(|typed| spawn_refill(pool, typed));
+    }
+}
+
+fn spawn_refill(pool: &'static TokenPool, typed: Type) {
+    match pool.inner.lock() {
+        // A refill of the type is already running
+        Ok(mut inner) if !inner.refilling.insert(typed) => return,
+        Ok(_) => {}
+        Err(_) => return,
+    }
+
+    tokio::spawn(async move {
+        pool.refill(typed).await;
+        if let Ok(mut inner) = pool.inner.lock() {
+            inner.refilling.remove(&typed);
+        }
+    });
+}
+
+impl TokenPool {
+    fn new(size: usize, ttl: Duration, idle: Duration, types: &[Type]) -> Self {
+        let now = Instant::now();
+        let inner = Inner {
+            wanted: types.iter().map(|typed| (*typed, now)).collect(),
+            ..Default::default()
+        };
+        Self {
+            size,
+            ttl,
+            idle,
+            inner: Mutex::new(inner),
+        }
+    }
+
+    fn evict(&self, tokens: &mut VecDeque<(Instant, ArkoseToken)>) {
+        tokens.retain(|(created, _)| created.elapsed() < self.ttl);
+    }
+
+    /// Pop the oldest fresh token and mark the type as wanted
+    fn take(&self, typed: Type) -> Option<ArkoseToken> {
+        let mut inner = self.inner.lock().ok()?;
+        inner.wanted.insert(typed, Instant::now());
+        let tokens = inner.tokens.entry(typed).or_default();
+        self.evict(tokens);
+        tokens.pop_front().map(|(_, token)| token)
+    }
+
+    fn push(&self, typed: Type, token: ArkoseToken) {
+        if let Ok(mut inner) = self.inner.lock() {
+            inner
+                .tokens
+                .entry(typed)
+                .or_default()
+                .push_back((Instant::now(), token));
+        }
+    }
+
+    /// Types taken within the idle window, idle ones are forgotten until taken again
+    fn wanted(&self) -> Vec<Type> {
+        match self.inner.lock() {
+            Ok(mut inner) => {
+                inner
+                    .wanted
+                    .retain(|_, last_taken| last_taken.elapsed() < self.idle);
+                inner.wanted.keys().copied().collect()
+            }
+            Err(_) => vec![],
+        }
+    }
+
+    /// Number of tokens missing from the type
+    fn missing(&self, typed: Type) -> usize {
+        match self.inner.lock() {
+            Ok(mut inner) => {
+                let tokens = inner.tokens.entry(typed).or_default();
+                self.evict(tokens);
+                self.size.saturating_sub(tokens.len())
+            }
+            Err(_) => 0,
+        }
+    }
+
+    async fn refill(&self, typed: Type) {
+        while self.missing(typed) > 0 {
+            let (egress, egress_addr, client) = with_context!(arkose_client_with_egress);
+            let ctx = ArkoseContext::builder()
+                .client(client)
+                .typed(typed)
+                .egress(Some(egress.to_owned()))
+                .egress_addr(egress_addr)
+                .build();
+
+            match ArkoseToken::new_from_context(ctx).await {
+                Ok(token) if token.success() => self.push(typed, token),
+                Ok(_) => {
+                    debug!("Arkose token pool ({typed:?}) got an unsolved token, retry later");
+                    break;
+                }
+                Err(err) => {
+                    warn!("Failed to refill arkose token pool ({typed:?}): {err}");
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn token(id: &str) -> ArkoseToken {
+        ArkoseToken::from(format!("{id}|r=us-east-1|sup=1"))
+    }
+
+    fn pool(size: usize, ttl: Duration, idle: Duration, types: &[Type]) -> TokenPool {
+        TokenPool::new(size, ttl, idle, types)
+    }
+
+    #[test]
+    fn test_take_oldest_first() {
+        let pool = pool(2, Duration::from_secs(60), IDLE_TIMEOUT, &[]);
+        assert!(pool.take(Type::GPT4).is_none());
+
+        pool.push(Type::GPT4, token("first"));
+        pool.push(Type::GPT4, token("second"));
+        assert!(pool.take(Type::Auth).is_none());
+
+        assert_eq!(
+            pool.take(Type::GPT4).unwrap().value(),
+            token("first").value()
+        );
+        assert_eq!(
+            pool.take(Type::GPT4).unwrap().value(),
+            token("second").value()
+        );
+        assert!(pool.take(Type::GPT4).is_none());
+    }
+
+    #[test]
+    fn test_expired_tokens_are_dropped() {
+        let pool = pool(2, Duration::from_millis(50), IDLE_TIMEOUT, &[]);
+        pool.push(Type::GPT4, token("stale"));
+        assert_eq!(pool.missing(Type::GPT4), 1);
+
+        std::thread::sleep(Duration::from_millis(80));
+        assert_eq!(pool.missing(Type::GPT4), 2);
+        assert!(pool.take(Type::GPT4).is_none());
+    }
+
+    #[test]
+    fn test_refill_accounting() {
+        let pool = pool(3, Duration::from_secs(60), IDLE_TIMEOUT, &[]);
+        assert_eq!(pool.missing(Type::GPT3), 3);
+
+        (0..3).for_each(|i| pool.push(Type::GPT3, token(&i.to_string())));
+        assert_eq!(pool.missing(Type::GPT3), 0);
+
+        pool.take(Type::GPT3);
+        assert_eq!(pool.missing(Type::GPT3), 1);
+    }
+
+    #[test]
+    fn test_configured_types_are_prefilled() {
+        let pool = pool(
+            1,
+            Duration::from_secs(60),
+            IDLE_TIMEOUT,
+            &[Type::GPT4, Type::Auth],
+        );
+        let mut wanted = pool.wanted();
+        wanted.sort_by_key(|typed| typed.name());
+        assert_eq!(wanted, vec![Type::Auth, Type::GPT4]);
+    }
+
+    #[test]
+    fn test_idle_types_stop_refilling() {
+        let pool = pool(
+            1,
+            Duration::from_secs(60),
+            Duration::from_millis(50),
+            &[Type::GPT4],
+        );
+        pool.take(Type::Auth);
+        assert_eq!(pool.wanted().len(), 2);
+
+        std::thread::sleep(Duration::from_millis(80));
+        pool.take(Type::Auth);
+        assert_eq!(pool.wanted(), vec![Type::Auth]);
+    }
+}
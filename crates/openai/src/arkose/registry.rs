@@ -0,0 +1,227 @@
+use super::{Type, AUTH_BX, GPT3_BX, GPT4_BX, PLATFORM_BX};
+use crate::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use url::Url;
+
+static REGISTRY: OnceLock<Registry> = OnceLock::new();
+
+/// How the `data[blob]` of the public key request is obtained
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Blob {
+    /// No blob
+    #[default]
+    None,
+    /// Exchanged from ChatGPT, the identifier is the access token
+    Dx,
+    /// The identifier is the blob
+    Identifier,
+}
+
+/// Arkose public key entry, defined in the config file
+///
+/// ```toml
+/// [[arkose_public_keys]]
+/// pk = "35536E1E-65B4-4D96-9D97-6ADB7EFF8147"
+/// type = "gpt4"
+/// site_url = "https://chat.openai.com"
+/// origin_url = "https://tcr9i.chat.openai.com"
+/// blob = "dx"
+/// default = true
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PublicKey {
+    /// Public key
+    pub pk: String,
+    /// Built-in flow the key follows, e.g. gpt3/gpt4/auth/signup/platform
+    #[serde(rename = "type")]
+    pub typed: Type,
+    /// Site of the key, e.g. https://chat.openai.com
+    pub site_url: String,
+    /// Arkose origin of the key, e.g. https://tcr9i.chat.openai.com
+    pub origin_url: String,
+    #[serde(default)]
+    pub blob: Blob,
+    /// Fingerprint (bda) template JSON file, defaults to the built-in template of the type
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<PathBuf>,
+    /// Use the key for the type instead of the built-in key
+    #[serde(default)]
+    pub default: bool,
+
+    #[serde(skip)]
+    site_host: String,
+    #[serde(skip)]
+    origin_host: String,
+    #[serde(skip)]
+    template_content: Option<String>,
+}
+
+impl PublicKey {
+    fn builtin(typed: Type, pk: &str, site_url: &str, origin_url: &str, blob: Blob) -> Self {
+        Self {
+            pk: pk.to_owned(),
+            typed,
+            site_url: site_url.to_owned(),
+            origin_url: origin_url.to_owned(),
+            blob,
+            template: None,
+            default: true,
+            site_host: String::new(),
+            origin_host: String::new(),
+            template_content: None,
+        }
+        .resolve()
+        .expect("Invalid built-in arkose public key")
+    }
+
+    /// Check the urls and load the template
+    fn resolve(mut self) -> anyhow::Result<Self> {
+        let host = |url: &str| -> anyhow::Result<String> {
+            Url::parse(url)?
+                .host_str()
+                .map(ToOwned::to_owned)
+                .ok_or_else(|| anyhow::anyhow!("`{url}` has no host"))
+        };
+        self.site_url = self.site_url.trim_end_matches('/').to_owned();
+        self.origin_url = self.origin_url.trim_end_matches('/').to_owned();
+        self.site_host = host(&self.site_url)?;
+        self.origin_host = host(&self.origin_url)?;
+
+        if let Some(template) = self.template.as_ref() {
+            let content = std::fs::read_to_string(template).map_err(|err| {
+                anyhow::anyhow!("Failed to read template {}: {err}", template.display())
+            })?;
+            serde_json::from_str::<Vec<serde_json::Value>>(&content)
+                .map_err(|err| anyhow::anyhow!("Invalid template {}: {err}", template.display()))?;
+            self.template_content = Some(content);
+        }
+
+        Ok(self)
+    }
+
+    pub fn site_host(&self) -> &str {
+        &self.site_host
+    }
+
+    pub fn origin_host(&self) -> &str {
+        &self.origin_host
+    }
+
+    /// Fingerprint (bda) template of the key
+    pub fn template(&self) -> &str {
+        match self.template_content.as_deref() {
+            Some(template) => template,
+            None => match self.typed {
+                Type::GPT3 => GPT3_BX,
+                Type::GPT4 => GPT4_BX,
+                Type::Auth => AUTH_BX,
+                Type::SignUp | Type::Platform => PLATFORM_BX,
+            },
+        }
+    }
+}
+
+struct Registry {
+    keys: HashMap<String, PublicKey>,
+    defaults: HashMap<Type, String>,
+}
+
+impl Registry {
+    fn new(keys: Vec<PublicKey>) -> Self {
+        let mut registry = Registry {
+            keys: HashMap::new(),
+            defaults: HashMap::new(),
+        };
+
+        let builtin = [
+            PublicKey::builtin(
+                Type::GPT3,
+                "3D86FBBA-9D22-402A-B512-3420086BA6CC",
+                "https://chat.openai.com",
+                "https://tcr9i.chat.openai.com",
+                Blob::None,
+            ),
+            PublicKey::builtin(
+                Type::GPT4,
+                "35536E1E-65B4-4D96-9D97-6ADB7EFF8147",
+                "https://chat.openai.com",
+                "https://tcr9i.chat.openai.com",
+                Blob::Dx,
+            ),
+            PublicKey::builtin(
+                Type::Auth,
+                "0A1D34FC-659D-4E23-B17B-694DCFCF6A6C",
+                "https://auth0.openai.com",
+                "https://tcr9i.openai.com",
+                Blob::None,
+            ),
+            PublicKey::builtin(
+                Type::SignUp,
+                "0655BC92-82E1-43D9-B32E-9DF9B01AF50C",
+                "https://platform.openai.com",
+                "https://openai-api.arkoselabs.com",
+                Blob::Identifier,
+            ),
+            PublicKey::builtin(
+                Type::Platform,
+                "23AAD243-4799-4A9E-B01D-1166C5DE02DF",
+                "https://platform.openai.com",
+                "https://openai-api.arkoselabs.com",
+                Blob::None,
+            ),
+        ];
+
+        for key in builtin.into_iter().chain(keys) {
+            if key.default {
+                registry.defaults.insert(key.typed, key.pk.clone());
+            }
+            registry.keys.insert(key.pk.clone(), key);
+        }
+
+        registry
+    }
+}
+
+fn registry() -> &'static Registry {
+    REGISTRY.get_or_init(|| Registry::new(Vec::new()))
+}
+
+/// Init the public key registry with the configured keys, the built-in keys are always registered
+pub fn init(keys: Vec<PublicKey>) -> anyhow::Result<()> {
+    let keys = keys
+        .into_iter()
+        .map(PublicKey::resolve)
+        .collect::<anyhow::Result<Vec<PublicKey>>>()?;
+
+    keys.iter().for_each(|key| {
+        info!(
+            "Arkose public key registered: {} ({:?}, default: {})",
+            key.pk, key.typed, key.default
+        )
+    });
+
+    if REGISTRY.set(Registry::new(keys)).is_err() {
+        warn!("Arkose public key registry is already initialized");
+    }
+
+    Ok(())
+}
+
+/// Get the registered public key
+pub fn get(pk: &str) -> Option<&'static PublicKey> {
+    registry().keys.get(pk)
+}
+
+/// Get the public key used for the type
+pub fn default_key(typed: Type) -> &'static PublicKey {
+    let registry = registry();
+    registry
+        .defaults
+        .get(&typed)
+        .and_then(|pk| registry.keys.get(pk))
+        .expect("Every arkose type has a default public key")
+}
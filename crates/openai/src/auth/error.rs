@@ -55,6 +55,8 @@ pub enum AuthError {
     MFAFailed,
     #[error("MFA required")]
     MFARequired,
+    #[error("Invalid TOTP secret")]
+    InvalidTotpSecret,
     #[error("Json deserialize error ({0:?})")]
     DeserializeError(reqwest::Error),
     #[error("Implementation is not supported")]
@@ -1,6 +1,7 @@
 pub mod error;
 pub mod model;
 pub mod provide;
+pub mod totp;
 
 extern crate regex;
 
@@ -7,6 +7,8 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use typed_builder::TypedBuilder;
 
+use super::provide::AuthResult;
+use super::totp;
 use crate::arkose::ArkoseToken;
 
 #[derive(Deserialize, Serialize, Clone, PartialEq, Eq, Hash, Debug)]
@@ -35,6 +37,9 @@ pub struct AuthAccount {
     pub password: String,
     #[builder(setter(into, strip_option), default)]
     pub mfa: Option<String>,
+    /// Base32 TOTP secret, generates the mfa code when no code is given
+    #[builder(setter(into, strip_option), default)]
+    pub totp_secret: Option<String>,
     #[builder(setter(into, strip_option), default)]
     pub csrf_token: Option<String>,
     #[serde(default)]
@@ -46,6 +51,26 @@ pub struct AuthAccount {
     pub cf_turnstile_response: Option<String>,
 }
 
+impl AuthAccount {
+    /// The given mfa code, or the code generated from the TOTP secret
+    pub fn mfa_code(&self) -> AuthResult<Option<String>> {
+        if let Some(mfa) = self.mfa.as_deref().filter(|s| !s.is_empty()) {
+            return Ok(Some(mfa.to_owned()));
+        }
+        self.totp_secret().map(totp::now).transpose()
+    }
+
+    /// The TOTP secret, empty form fields are ignored
+    pub fn totp_secret(&self) -> Option<&str> {
+        self.totp_secret.as_deref().filter(|s| !s.trim().is_empty())
+    }
+
+    /// Whether the account can pass the mfa challenge
+    pub fn has_mfa(&self) -> bool {
+        self.mfa.as_deref().map(|s| !s.is_empty()).unwrap_or(false) || self.totp_secret().is_some()
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct OAuthAccessToken {
     pub access_token: String,
@@ -38,6 +38,7 @@ pub(crate) struct AppleAuthProvider {
 }
 
 impl AppleAuthProvider {
+    #[tracing::instrument(name = "auth.apple.authorize", skip_all)]
     async fn authorize(&self, ctx: &mut RequestContext<'_>) -> AuthResult<()> {
         // Get the preauth cookie.
         let preauth_cookie = self.preauth_provider.get_preauth_cookie()?;
@@ -79,6 +80,7 @@ impl AppleAuthProvider {
         AuthClient::response_handle_unit(resp).await
     }
 
+    #[tracing::instrument(name = "auth.apple.authenticate_username", skip_all)]
     async fn authenticate_username(&self, ctx: &mut RequestContext<'_>) -> AuthResult<()> {
         let url = format!("{OPENAI_OAUTH_URL}/u/login/identifier?state={}", ctx.state);
         let resp = self
@@ -106,6 +108,7 @@ impl AppleAuthProvider {
             .map_err(|_| AuthError::InvalidEmail)
     }
 
+    #[tracing::instrument(name = "auth.apple.authenticate_password", skip_all)]
     async fn authenticate_password(
         &self,
         ctx: &mut RequestContext<'_>,
@@ -154,6 +157,7 @@ impl AppleAuthProvider {
         Err(AuthError::FailedLogin)
     }
 
+    #[tracing::instrument(name = "auth.apple.authenticate_resume", skip_all)]
     async fn authenticate_resume(
         &self,
         ctx: &mut RequestContext<'_>,
@@ -180,7 +184,7 @@ impl AppleAuthProvider {
         let location: &str = AuthClient::get_location_path(&resp.headers())?;
         if location.starts_with("/u/mfa-otp-challenge?") {
             // If the location contains "/u/mfa-otp-challenge?", it means that MFA is required.
-            let mfa_code = ctx.account.mfa.clone().ok_or(AuthError::MFARequired)?;
+            let mfa_code = ctx.account.mfa_code()?.ok_or(AuthError::MFARequired)?;
             return self.authenticate_mfa(ctx, &mfa_code, location).await;
         }
 
@@ -193,6 +197,7 @@ impl AppleAuthProvider {
         Err(AuthError::FailedCallbackURL)
     }
 
+    #[tracing::instrument(name = "auth.apple.authenticate_mfa", skip_all)]
     async fn authenticate_mfa(
         &self,
         ctx: &mut RequestContext<'_>,
@@ -225,7 +230,7 @@ impl AppleAuthProvider {
         let location: &str = AuthClient::get_location_path(&resp.headers())?;
 
         // If the location contains "/authorize/resume?", it means that the login was successful.
-        if location.starts_with("/authorize/resume?") && ctx.account.mfa.is_none() {
+        if location.starts_with("/authorize/resume?") && !ctx.account.has_mfa() {
             return Err(AuthError::MFAFailed);
         }
 
@@ -258,6 +263,7 @@ impl AppleAuthProvider {
         Err(AuthError::FailedCallbackURL)
     }
 
+    #[tracing::instrument(name = "auth.apple.authorization_code", skip_all)]
     async fn authorization_code(
         &self,
         ctx: &mut RequestContext<'_>,
@@ -298,6 +304,7 @@ impl AuthProvider for AppleAuthProvider {
         t.eq(&AuthStrategy::Apple)
     }
 
+    #[tracing::instrument(name = "auth.apple.access_token", skip_all)]
     async fn do_access_token(
         &self,
         account: &model::AuthAccount,
@@ -319,6 +326,7 @@ impl AuthProvider for AppleAuthProvider {
         self.authenticate_password(&mut ctx).await
     }
 
+    #[tracing::instrument(name = "auth.apple.refresh_token", skip_all)]
     async fn do_refresh_token(&self, refresh_token: &str) -> AuthResult<model::RefreshToken> {
         let refresh_token = AuthClient::trim_bearer(refresh_token)?;
         let data = RefreshTokenData::builder()
@@ -338,6 +346,7 @@ impl AuthProvider for AppleAuthProvider {
         Ok(AuthClient::response_handle::<model::RefreshToken>(resp).await?)
     }
 
+    #[tracing::instrument(name = "auth.apple.revoke_token", skip_all)]
     async fn do_revoke_token(&self, refresh_token: &str) -> AuthResult<()> {
         let refresh_token = AuthClient::trim_bearer(refresh_token)?;
         let data = RevokeTokenData::builder()
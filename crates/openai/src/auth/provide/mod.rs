@@ -109,17 +109,23 @@ impl<'a> RequestContext<'a> {
         }
     }
 
+    #[tracing::instrument(name = "auth.arkose_token", skip_all)]
     async fn load_arkose_token(&mut self) -> AuthResult<()> {
         let arkose_token = match self.account.arkose_token.as_deref() {
             Some(arkose_token) => ArkoseToken::from(arkose_token),
-            None => arkose::ArkoseToken::new_from_context(
-                ArkoseContext::builder()
-                    .client(with_context!(arkose_client))
-                    .typed(Type::Auth)
-                    .build(),
-            )
-            .await
-            .map_err(AuthError::InvalidArkoseToken)?,
+            None => {
+                let (egress, egress_addr, client) = with_context!(arkose_client_with_egress);
+                arkose::ArkoseToken::new_from_context(
+                    ArkoseContext::builder()
+                        .client(client)
+                        .typed(Type::Auth)
+                        .egress(Some(egress.to_owned()))
+                        .egress_addr(egress_addr)
+                        .build(),
+                )
+                .await
+                .map_err(AuthError::InvalidArkoseToken)?
+            }
         };
 
         self.cookie
@@ -22,6 +22,7 @@ const OPENAI_OAUTH_PLATFORM_CALLBACK_URL: &str = "https://platform.openai.com/au
 pub(crate) struct PlatformAuthProvider(pub Client);
 
 impl PlatformAuthProvider {
+    #[tracing::instrument(name = "auth.platform.authorize", skip_all)]
     async fn authorize(&self, ctx: &mut RequestContext<'_>) -> AuthResult<()> {
         // Build url
         let url = format!("{OPENAI_OAUTH_URL}/authorize?client_id={PLATFORM_CLIENT_ID}&scope=openid%20email%20profile%20offline_access%20model.request%20model.read%20organization.read%20organization.write&audience=https://api.openai.com/v1&redirect_uri=https://platform.openai.com/auth/callback&response_type=code");
@@ -55,6 +56,7 @@ impl PlatformAuthProvider {
         AuthClient::response_handle_unit(resp).await
     }
 
+    #[tracing::instrument(name = "auth.platform.authenticate_username", skip_all)]
     async fn authenticate_username(&self, ctx: &mut RequestContext<'_>) -> AuthResult<()> {
         let url = format!("{OPENAI_OAUTH_URL}/u/login/identifier?state={}", ctx.state);
         let resp = self
@@ -81,6 +83,7 @@ impl PlatformAuthProvider {
             .map_err(|_| (AuthError::InvalidEmail))
     }
 
+    #[tracing::instrument(name = "auth.platform.authenticate_password", skip_all)]
     async fn authenticate_password(
         &self,
         ctx: &mut RequestContext<'_>,
@@ -128,6 +131,7 @@ impl PlatformAuthProvider {
         Err(AuthError::FailedLogin)
     }
 
+    #[tracing::instrument(name = "auth.platform.authenticate_resume", skip_all)]
     async fn authenticate_resume(
         &self,
         ctx: &mut RequestContext<'_>,
@@ -161,13 +165,14 @@ impl PlatformAuthProvider {
         Err(AuthError::FailedCallbackURL)
     }
 
+    #[tracing::instrument(name = "auth.platform.authenticate_mfa", skip_all)]
     async fn authenticate_mfa(
         &self,
         ctx: &mut RequestContext<'_>,
         location: &str,
     ) -> AuthResult<model::AccessToken> {
         // Get mfa code
-        let mfa_code = &ctx.account.mfa.clone().ok_or(AuthError::MFARequired)?;
+        let mfa_code = &ctx.account.mfa_code()?.ok_or(AuthError::MFARequired)?;
 
         // Parse url
         let url = Url::parse(&format!("{OPENAI_OAUTH_URL}{}", location))
@@ -203,7 +208,7 @@ impl PlatformAuthProvider {
         let location: &str = AuthClient::get_location_path(&resp.headers())?;
 
         // If location starts with /authorize/resume? and mfa is none, return mfa failed
-        if location.starts_with("/authorize/resume?") && ctx.account.mfa.is_none() {
+        if location.starts_with("/authorize/resume?") && !ctx.account.has_mfa() {
             return Err(AuthError::MFAFailed);
         }
 
@@ -230,6 +235,7 @@ impl PlatformAuthProvider {
         Err(AuthError::FailedCallbackURL)
     }
 
+    #[tracing::instrument(name = "auth.platform.authorization_code", skip_all)]
     async fn authorization_code(&self, location: &str) -> AuthResult<model::AccessToken> {
         // Parse url
         let url = Url::parse(location).map_err(AuthError::InvalidLoginUrl)?;
@@ -263,6 +269,7 @@ impl AuthProvider for PlatformAuthProvider {
         t.eq(&AuthStrategy::Platform)
     }
 
+    #[tracing::instrument(name = "auth.platform.access_token", skip_all)]
     async fn do_access_token(
         &self,
         account: &model::AuthAccount,
@@ -278,6 +285,7 @@ impl AuthProvider for PlatformAuthProvider {
         self.authenticate_password(&mut ctx).await
     }
 
+    #[tracing::instrument(name = "auth.platform.refresh_token", skip_all)]
     async fn do_refresh_token(&self, refresh_token: &str) -> AuthResult<model::RefreshToken> {
         let refresh_token = AuthClient::trim_bearer(refresh_token)?;
         let data = RefreshTokenData::builder()
@@ -297,6 +305,7 @@ impl AuthProvider for PlatformAuthProvider {
         Ok(AuthClient::response_handle::<model::RefreshToken>(resp).await?)
     }
 
+    #[tracing::instrument(name = "auth.platform.revoke_token", skip_all)]
     async fn do_revoke_token(&self, refresh_token: &str) -> AuthResult<()> {
         let refresh_token = AuthClient::trim_bearer(refresh_token)?;
         let data = RevokeTokenData::builder()
@@ -6,7 +6,7 @@ use crate::auth::error::AuthError;
 use crate::auth::{
     model::{self, AuthStrategy},
     provide::AuthenticateData,
-    AuthClient, OPENAI_OAUTH_URL,
+    totp, AuthClient, OPENAI_OAUTH_URL,
 };
 use crate::{debug, warn, URL_CHATGPT_API};
 use reqwest::{Client, StatusCode};
@@ -17,6 +17,7 @@ use url::Url;
 pub(crate) struct WebAuthProvider(pub Client);
 
 impl WebAuthProvider {
+    #[tracing::instrument(name = "auth.web.csrf_token", skip_all)]
     async fn csrf_token(&self, ctx: &mut RequestContext<'_>) -> AuthResult<()> {
         let resp = self
             .0
@@ -44,6 +45,7 @@ impl WebAuthProvider {
         }
     }
 
+    #[tracing::instrument(name = "auth.web.authorized", skip_all)]
     async fn authorized(&self, ctx: &mut RequestContext<'_>) -> AuthResult<()> {
         let resp = self
             .0
@@ -80,6 +82,7 @@ impl WebAuthProvider {
         }
     }
 
+    #[tracing::instrument(name = "auth.web.state", skip_all)]
     async fn state(&self, url: &str, ctx: &mut RequestContext<'_>) -> AuthResult<()> {
         let resp = self
             .0
@@ -107,6 +110,7 @@ impl WebAuthProvider {
             .map_err(|_| AuthError::FailedState)?)
     }
 
+    #[tracing::instrument(name = "auth.web.authenticate_username", skip_all)]
     async fn authenticate_username(&self, ctx: &mut RequestContext<'_>) -> AuthResult<()> {
         let url = format!("{OPENAI_OAUTH_URL}/u/login/identifier?state={}", ctx.state);
         let resp = self
@@ -133,6 +137,7 @@ impl WebAuthProvider {
             .map_err(|_| AuthError::InvalidEmail)
     }
 
+    #[tracing::instrument(name = "auth.web.authenticate_password", skip_all)]
     async fn authenticate_password(
         &self,
         ctx: &mut RequestContext<'_>,
@@ -183,6 +188,7 @@ impl WebAuthProvider {
         Err(AuthError::FailedLogin)
     }
 
+    #[tracing::instrument(name = "auth.web.authenticate_resume", skip_all)]
     async fn authenticate_resume(
         &self,
         ctx: &mut RequestContext<'_>,
@@ -205,7 +211,7 @@ impl WebAuthProvider {
         // If get_location_path returns an error, it means that the location is invalid.
         let location = AuthClient::get_location_path(resp.headers())?;
         if location.starts_with("/u/mfa-otp-challenge") {
-            let mfa = ctx.account.mfa.clone().ok_or(AuthError::MFARequired)?;
+            let mfa = ctx.account.mfa_code()?.ok_or(AuthError::MFARequired)?;
             return self.authenticate_mfa(ctx, &mfa, location).await;
         }
 
@@ -229,6 +235,7 @@ impl WebAuthProvider {
         Err(AuthError::FailedLogin)
     }
 
+    #[tracing::instrument(name = "auth.web.authenticate_mfa", skip_all)]
     async fn authenticate_mfa(
         &self,
         ctx: &mut RequestContext<'_>,
@@ -242,32 +249,51 @@ impl WebAuthProvider {
         // Get state from url
         let state = AuthClient::get_callback_state(&url)?;
 
-        let data = AuthenticateMfaData::builder()
-            .action("default")
-            .state(&state)
-            .code(mfa_code)
-            .build();
-
-        let resp = self
-            .0
-            .post(url)
-            .ext_context(ctx)
-            .json(&data)
-            .send()
-            .await
-            .map_err(AuthError::FailedRequest)?
-            .ext_context(ctx);
+        let mut mfa_code = mfa_code.to_owned();
+        let mut retried = false;
+        let resp = loop {
+            let data = AuthenticateMfaData::builder()
+                .action("default")
+                .state(&state)
+                .code(&mfa_code)
+                .build();
+
+            let resp = self
+                .0
+                .post(url.clone())
+                .ext_context(ctx)
+                .json(&data)
+                .send()
+                .await
+                .map_err(AuthError::FailedRequest)?
+                .ext_context(ctx);
+
+            // A rejected TOTP code is retried once with the code of the next time step
+            let rejected = resp.status().is_client_error()
+                || AuthClient::get_location_path(resp.headers())
+                    .map(|location| location.starts_with("/u/mfa-otp-challenge"))
+                    .unwrap_or(false);
+            match ctx.account.totp_secret() {
+                Some(secret) if rejected && !retried => {
+                    debug!("MFA code rejected, retry with the next TOTP code");
+                    mfa_code = totp::next(secret).await?;
+                    retried = true;
+                }
+                _ => break resp,
+            }
+        };
 
         let location = AuthClient::get_location_path(resp.headers())?;
 
         // If location path starts with /authorize/resume? and mfa is none return MFAFailed
-        if location.starts_with("/authorize/resume?") && ctx.account.mfa.is_none() {
+        if location.starts_with("/authorize/resume?") && !ctx.account.has_mfa() {
             return Err(AuthError::MFAFailed);
         }
 
         self.get_access_token(ctx).await
     }
 
+    #[tracing::instrument(name = "auth.web.get_access_token", skip_all)]
     async fn get_access_token(
         &self,
         ctx: &mut RequestContext<'_>,
@@ -292,6 +318,7 @@ impl AuthProvider for WebAuthProvider {
         t.eq(&AuthStrategy::Web)
     }
 
+    #[tracing::instrument(name = "auth.web.access_token", skip_all)]
     async fn do_access_token(
         &self,
         account: &model::AuthAccount,
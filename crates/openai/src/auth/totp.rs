@@ -0,0 +1,103 @@
+//! Time-based one-time password (RFC 6238), the code of the authenticator app
+//! is generated from the base32 secret shown when MFA is enabled.
+
+use std::time::Duration;
+
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+use super::error::AuthError;
+use super::provide::AuthResult;
+
+/// Time step in seconds
+const PERIOD: u64 = 30;
+const DIGITS: usize = 6;
+
+/// Decode the base32 secret, spaces, dashes, padding and lowercase letters are allowed
+fn decode(secret: &str) -> AuthResult<Vec<u8>> {
+    let secret = secret
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '='))
+        .map(|c| c.to_ascii_uppercase())
+        .collect::<String>();
+    BASE32_NOPAD
+        .decode(secret.as_bytes())
+        .ok()
+        .filter(|key| !key.is_empty())
+        .ok_or(AuthError::InvalidTotpSecret)
+}
+
+/// HOTP (RFC 4226) code of the counter
+fn hotp(key: &[u8], counter: u64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC can take key of any size");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    // Dynamic truncation
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset],
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]) & 0x7fff_ffff;
+
+    format!(
+        "{:0width$}",
+        binary % 10u32.pow(DIGITS as u32),
+        width = DIGITS
+    )
+}
+
+fn unix_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Generate the code at the unix time
+pub fn generate(secret: &str, time: u64) -> AuthResult<String> {
+    Ok(hotp(&decode(secret)?, time / PERIOD))
+}
+
+/// Generate the code of the current time step
+pub fn now(secret: &str) -> AuthResult<String> {
+    generate(secret, unix_time())
+}
+
+/// Wait for the next time step and generate its code,
+/// a code is only accepted once so a rejected code can not be submitted again
+pub async fn next(secret: &str) -> AuthResult<String> {
+    let time = unix_time();
+    let wait = PERIOD - time % PERIOD;
+    tokio::time::sleep(Duration::from_secs(wait)).await;
+    generate(secret, time + wait)
+}
+
+#[cfg(test)]
+mod test {
+    use super::generate;
+
+    /// RFC 6238 appendix B, the secret is `12345678901234567890`
+    const SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    #[test]
+    fn test_rfc6238() {
+        assert_eq!(generate(SECRET, 59).unwrap(), "287082");
+        assert_eq!(generate(SECRET, 1111111109).unwrap(), "081804");
+        assert_eq!(generate(SECRET, 1111111111).unwrap(), "050471");
+        assert_eq!(generate(SECRET, 1234567890).unwrap(), "005924");
+        assert_eq!(generate(SECRET, 2000000000).unwrap(), "279037");
+        assert_eq!(generate(SECRET, 20000000000).unwrap(), "353130");
+    }
+
+    #[test]
+    fn test_secret_format() {
+        let secret = "gezd gnbv gy3t qojq gezd gnbv gy3t qojq";
+        assert_eq!(generate(secret, 59).unwrap(), "287082");
+        assert!(generate("not base32!", 59).is_err());
+        assert!(generate("", 59).is_err());
+    }
+}
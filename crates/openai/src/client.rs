@@ -69,6 +69,12 @@ impl LookupIpStrategyExt {
     }
 }
 
+/// Consecutive failures before a client is considered unhealthy
+const MAX_FAILURES: usize = 3;
+
+/// Label of a client rebuilt on a random address of the IPv6 subnet
+const IPV6_SUBNET_LABEL: &str = "ipv6-subnet";
+
 static DNS_RESOLVER: OnceLock<Cache<LookupIpStrategyExt, Arc<TrustDnsResolver>>> = OnceLock::new();
 
 struct Config {
@@ -118,6 +124,10 @@ impl Config {
 pub struct ClientRoundRobinBalancer {
     config: Config,
     pool: (AtomicUsize, Vec<ClientAgent>),
+    /// Client labels, same order as pool
+    labels: Vec<String>,
+    /// Client consecutive failures, same order as pool
+    failures: Vec<AtomicUsize>,
 }
 
 impl ClientRoundRobinBalancer {
@@ -200,9 +210,11 @@ impl ClientRoundRobinBalancer {
 
         // init client pool
         let mut pool = Vec::with_capacity(proxies.len() + 1);
+        let mut labels = Vec::with_capacity(proxies.len() + 1);
 
         // Helper function to join client to the pool
         let mut join_client = |bind: Option<IpAddr>, proxy: Option<Url>| {
+            labels.push(client_label(bind, proxy.as_ref()));
             let client = build_fn(&config, bind, None, proxy, args.no_keepalive);
             pool.push(client_type(client));
         };
@@ -230,6 +242,7 @@ impl ClientRoundRobinBalancer {
 
         // Join a default client to the pool if it's still empty
         if pool.is_empty() {
+            labels.push(client_label(None, None));
             pool.push(client_type(build_fn(
                 &config,
                 None,
@@ -241,15 +254,20 @@ impl ClientRoundRobinBalancer {
 
         Ok(Self {
             config,
+            failures: pool.iter().map(|_| AtomicUsize::new(0)).collect(),
             pool: (AtomicUsize::new(0), pool),
+            labels,
         })
     }
 }
 
 impl ClientRoundRobinBalancer {
     /// rebuild client with ipv6
-    fn rebuild_client_with_ipv6(&self, client: &ClientAgent) -> ClientAgent {
-        let bind_addr = self.config.get_next_ipv6();
+    fn rebuild_client_with_ipv6(
+        &self,
+        client: &ClientAgent,
+        bind_addr: Option<IpAddr>,
+    ) -> ClientAgent {
         // if interface is not specified, use fallback bind address
         let fallback_bind_addr = self.config.get_next_interface();
         match client {
@@ -279,17 +297,75 @@ impl ClientRoundRobinBalancer {
 
     /// Get next client
     pub fn next(&self) -> ClientAgent {
+        self.next_with_label().1
+    }
+
+    /// Get next client with its label (proxy url or bind address)
+    pub fn next_with_label(&self) -> (&str, ClientAgent) {
+        let (label, _, client) = self.next_with_egress();
+        (label, client)
+    }
+
+    /// Get next client with its label, and the address chosen from the IPv6 subnet.
+    /// IPv6 subnet clients share one label, the address tells them apart
+    pub fn next_with_egress(&self) -> (&str, Option<IpAddr>, ClientAgent) {
         // if there is only one client, return it
         if self.pool.1.len() == 1 {
             let client = self.pool.1.first().expect("Init client failed");
             if !self.config.ipv6_subnets.1.is_empty() {
-                return self.rebuild_client_with_ipv6(client);
+                let bind_addr = self.config.get_next_ipv6();
+                let client = self.rebuild_client_with_ipv6(client, bind_addr);
+                return (IPV6_SUBNET_LABEL, bind_addr, client);
             }
-            return client.clone();
+            return (&self.labels[0], None, client.clone());
         }
 
         let new = get_next_index(self.pool.1.len(), &self.pool.0);
-        self.pool.1[new].clone()
+        (&self.labels[new], None, self.pool.1[new].clone())
+    }
+
+    /// Get all client labels
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+
+    /// Report the result of an upstream request sent by the labeled client
+    pub fn report(&self, label: &str, success: bool) {
+        // IPv6 subnet clients are rebuilt from the only pooled client
+        let index = match label {
+            IPV6_SUBNET_LABEL => Some(0),
+            _ => self.labels.iter().position(|l| l.eq(label)),
+        };
+        if let Some(index) = index {
+            let failures = &self.failures[index];
+            if success {
+                failures.store(0, Ordering::Relaxed);
+            } else {
+                failures.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Get the number of healthy clients
+    pub fn healthy(&self) -> usize {
+        self.failures
+            .iter()
+            .filter(|f| f.load(Ordering::Relaxed) < MAX_FAILURES)
+            .count()
+    }
+}
+
+/// Client label, credentials are stripped from proxy url
+fn client_label(bind: Option<IpAddr>, proxy: Option<&Url>) -> String {
+    match (proxy, bind) {
+        (Some(url), _) => {
+            let mut url = url.clone();
+            let _ = url.set_username("");
+            let _ = url.set_password(None);
+            url.to_string()
+        }
+        (None, Some(ip)) => format!("direct({ip})"),
+        (None, None) => "direct".to_owned(),
     }
 }
 
@@ -1,8 +1,12 @@
-use crate::{arkose::funcaptcha::solver::ArkoseSolver, proxy};
+use crate::{
+    arkose::{self, funcaptcha::solver::ArkoseSolver, registry::PublicKey},
+    proxy,
+};
 use reqwest::impersonate::Impersonate;
 use std::{
     net::{IpAddr, Ipv4Addr, SocketAddr},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 use typed_builder::TypedBuilder;
 
@@ -12,10 +16,39 @@ pub struct Args {
     #[builder(setter(into), default = Some(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 7999)))]
     pub(crate) bind: Option<SocketAddr>,
 
+    /// Configuration file, watched and hot reloaded on change
+    #[builder(setter(into), default)]
+    pub(crate) config: Option<ConfigLoader>,
+
+    /// Log format (text/json)
+    #[builder(setter(into), default = "text".to_string())]
+    pub(crate) log_format: String,
+
+    /// OTLP (gRPC) collector endpoint, e.g. http://localhost:4317
+    #[cfg(feature = "otel")]
+    #[builder(setter(into), default)]
+    pub(crate) otlp_endpoint: Option<String>,
+
     /// Server concurrent limit (Enforces a limit on the concurrent number of requests the underlying)
     #[builder(setter(into), default = 65535)]
     pub(crate) concurrent_limit: usize,
 
+    /// Per account concurrent conversation limit, 0 means unlimited
+    #[builder(setter(into), default = 0)]
+    pub(crate) conv_limit: usize,
+
+    /// Per account conversation queue timeout (second)
+    #[builder(setter(into), default = 30)]
+    pub(crate) conv_queue_timeout: u64,
+
+    /// Conversation transcript directory, disabled if not set
+    #[builder(setter(into), default)]
+    pub(crate) transcript_dir: Option<PathBuf>,
+
+    /// Conversation transcript retention (days), 0 means keep forever
+    #[builder(setter(into), default = 30)]
+    pub(crate) transcript_retention: u64,
+
     /// Enabled Cookie Store
     #[builder(default = false)]
     pub(crate) cookie_store: bool,
@@ -64,13 +97,37 @@ pub struct Args {
     #[builder(setter(into), default)]
     pub(crate) tls_key: Option<PathBuf>,
 
+    /// Metrics server bind address, `/metrics` is served on the main server with auth key if not set
+    #[builder(setter(into), default)]
+    pub(crate) metrics_bind: Option<SocketAddr>,
+
+    /// Trusted reverse proxies, the real client address is taken from their forwarded headers
+    #[builder(setter(into), default)]
+    pub(crate) trusted_proxies: Vec<cidr::IpCidr>,
+
+    /// Expect PROXY protocol (v1/v2) header on the server listener
+    #[builder(default = false)]
+    pub(crate) proxy_protocol: bool,
+
+    /// Client addresses allowed to access the server
+    #[builder(setter(into), default)]
+    pub(crate) allow_cidrs: Vec<cidr::IpCidr>,
+
+    /// Client addresses denied to access the server
+    #[builder(setter(into), default)]
+    pub(crate) deny_cidrs: Vec<cidr::IpCidr>,
+
+    /// Per route group allow/deny rules file, hot reloaded on change
+    #[builder(setter(into), default)]
+    pub(crate) cidr_file: Option<PathBuf>,
+
     /// Visitor email whitelist
     #[builder(setter(into), default)]
-    pub(super) visitor_email_whitelist: Option<Vec<String>>,
+    pub(crate) visitor_email_whitelist: Option<Vec<String>>,
 
     /// Login auth key
     #[builder(setter(into), default)]
-    pub(super) auth_key: Option<String>,
+    pub(crate) auth_key: Option<String>,
 
     /// Enable webui
     #[builder(setter(into), default = false)]
@@ -108,6 +165,10 @@ pub struct Args {
     #[builder(setter(into), default = false)]
     pub(crate) arkose_gpt3_experiment_solver: bool,
 
+    /// Generate a random browser fingerprint per identity / egress for Arkose
+    #[builder(setter(into), default = false)]
+    pub(crate) arkose_random_fingerprint: bool,
+
     /// arkoselabs solver
     #[builder(setter(into), default)]
     pub(crate) arkose_solver: Option<ArkoseSolver>,
@@ -120,6 +181,26 @@ pub struct Args {
     #[builder(setter(into), default)]
     pub(crate) arkose_solver_image_dir: Option<PathBuf>,
 
+    /// Pre-generated arkose tokens kept per type, 0 means disabled
+    #[builder(setter(into), default = 0)]
+    pub(crate) arkose_pool_size: usize,
+
+    /// Pre-generated arkose token time to live (second)
+    #[builder(setter(into), default = 120)]
+    pub(crate) arkose_pool_ttl: u64,
+
+    /// Arkose types prefilled when the pool starts
+    #[builder(setter(into), default)]
+    pub(crate) arkose_pool_types: Vec<arkose::Type>,
+
+    /// Arkose public keys registered in addition to the built-in keys
+    #[builder(setter(into), default)]
+    pub(crate) arkose_public_keys: Vec<PublicKey>,
+
+    /// Webhook posted when the arkose enforcement version changes
+    #[builder(setter(into), default)]
+    pub(crate) arkose_version_webhook: Option<String>,
+
     /// Enable Tokenbucket
     #[cfg(feature = "limit")]
     #[builder(setter(into), default = false)]
@@ -165,3 +246,32 @@ pub struct Args {
     #[builder(setter(into), default)]
     pub(crate) pkey: PathBuf,
 }
+
+/// Configuration file with the function parsing it into `Args`
+#[derive(Clone)]
+pub struct ConfigLoader {
+    path: PathBuf,
+    load: Arc<dyn Fn(&Path) -> anyhow::Result<Args> + Send + Sync>,
+}
+
+impl ConfigLoader {
+    pub fn new<F>(path: PathBuf, load: F) -> Self
+    where
+        F: Fn(&Path) -> anyhow::Result<Args> + Send + Sync + 'static,
+    {
+        Self {
+            path,
+            load: Arc::new(load),
+        }
+    }
+
+    /// Configuration file path
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Read and parse the configuration file
+    pub(crate) fn load(&self) -> anyhow::Result<Args> {
+        (self.load)(&self.path)
+    }
+}
@@ -1,5 +1,5 @@
 use crate::{
-    arkose::{self, Type},
+    arkose::{self, registry, Type},
     context::WORKER_DIR,
     homedir::home_dir,
     info, warn,
@@ -11,8 +11,9 @@ use std::{
     path::{Path, PathBuf},
     sync::{
         atomic::{AtomicUsize, Ordering},
-        OnceLock, RwLock,
+        Mutex, OnceLock, RwLock,
     },
+    time::SystemTime,
 };
 use tokio::fs::ReadDir;
 
@@ -21,11 +22,17 @@ use crate::urldecoding;
 use anyhow::Result;
 use base64::Engine;
 use moka::sync::Cache;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use time::format_description::well_known::Rfc3339;
 
 pub static HAR: OnceLock<RwLock<HashMap<arkose::Type, HarProvider>>> = OnceLock::new();
 
+/// Quarantined HAR files are moved to this subdirectory
+pub const QUARANTINE_DIR: &str = "quarantine";
+/// Consecutive tokens without `sup=1` before a HAR file is quarantined
+const QUARANTINE_THRESHOLD: u32 = 5;
+static TRACKER: OnceLock<Mutex<Tracker>> = OnceLock::new();
+
 struct HarPath {
     dir: PathBuf,
     filepath: Option<PathBuf>,
@@ -83,6 +90,16 @@ impl HarProvider {
             });
     }
 
+    /// HAR directory
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// HAR file names in the pool
+    pub fn files(&self) -> &[String] {
+        &self.pool.1
+    }
+
     fn reset_pool(&mut self) {
         self.pool.1.clear();
         Self::init(&self.dir, &mut self.pool.1)
@@ -175,6 +192,10 @@ fn get_or_init_cache() -> &'static Cache<String, RequestEntry> {
     CACHE.get_or_init(|| Cache::new(u64::MAX))
 }
 
+/// Inspected HAR files of the type, invalidated when the file is modified
+static INSPECT_CACHE: OnceLock<Cache<(Type, PathBuf), (SystemTime, Result<HarInfo, String>)>> =
+    OnceLock::new();
+
 // Arkose request entry
 #[derive(Clone)]
 pub struct RequestEntry {
@@ -185,6 +206,8 @@ pub struct RequestEntry {
     pub body: String,
     pub bx: String,
     pub bv: String,
+    /// HAR file the entry is parsed from
+    pub file: Option<PathBuf>,
 }
 
 fn get_har_path(_type: &Type) -> anyhow::Result<HarPath> {
@@ -198,11 +221,85 @@ fn get_har_path(_type: &Type) -> anyhow::Result<HarPath> {
         .ok_or_else(|| anyhow!("Failed to get har pool"))
 }
 
+/// HAR file details
+#[derive(Serialize, Clone)]
+pub struct HarInfo {
+    #[serde(rename = "type")]
+    pub typed: String,
+    pub user_agent: String,
+    /// Unix timestamp the bda fingerprint was generated at
+    pub bda_timestamp: Option<i64>,
+}
+
+impl From<&RequestEntry> for HarInfo {
+    fn from(entry: &RequestEntry) -> Self {
+        HarInfo {
+            typed: format!("{:?}", entry.typed),
+            user_agent: entry.bv.clone(),
+            bda_timestamp: bda_timestamp(&entry.bx),
+        }
+    }
+}
+
 // valid har data
 #[inline]
-pub fn valid(s: &[u8]) -> anyhow::Result<RequestEntry> {
-    let har = serde_json::from_slice::<Har>(&s)?;
-    parse(har)
+pub fn valid(_type: &Type, s: &[u8]) -> anyhow::Result<HarInfo> {
+    let har =
+        serde_json::from_slice::<Har>(&s).map_err(|err| anyhow!("Invalid HAR file: {err}"))?;
+    let entry = parse(har)?;
+    check_type(_type, &entry)?;
+    Ok(HarInfo::from(&entry))
+}
+
+/// Inspect the HAR file in the type directory, the result is cached until the file is modified
+pub fn inspect_file(_type: &Type, filename: &str) -> anyhow::Result<HarInfo> {
+    let filepath = get_har_path(_type)?.dir.join(filename);
+    check_file_extension(&filepath).map_err(|s| anyhow!(s))?;
+    let modified = std::fs::metadata(&filepath)?.modified()?;
+
+    let cache = INSPECT_CACHE.get_or_init(|| Cache::new(1024));
+    let key = (*_type, filepath);
+    if let Some((mtime, info)) = cache.get(&key) {
+        if mtime == modified {
+            return info.map_err(|err| anyhow!(err));
+        }
+    }
+
+    let info = read_entry(&key.1)
+        .and_then(|entry| {
+            check_type(_type, &entry)?;
+            Ok(HarInfo::from(&entry))
+        })
+        .map_err(|err| err.to_string());
+    cache.insert(key, (modified, info.clone()));
+    info.map_err(|err| anyhow!(err))
+}
+
+fn check_type(_type: &Type, entry: &RequestEntry) -> Result<()> {
+    if entry.typed.ne(_type) {
+        anyhow::bail!(
+            "The HAR file records a {:?} arkose request, but {:?} is selected",
+            entry.typed,
+            _type
+        )
+    }
+    Ok(())
+}
+
+/// Decode the `n` timestamp of the bda fingerprint
+fn bda_timestamp(bx: &str) -> Option<i64> {
+    #[derive(Deserialize)]
+    struct Item {
+        key: String,
+        value: serde_json::Value,
+    }
+
+    let items = serde_json::from_str::<Vec<Item>>(bx).ok()?;
+    let n = items.into_iter().find(|item| item.key.eq("n"))?;
+    let n = base64::engine::general_purpose::STANDARD
+        .decode(n.value.as_str()?)
+        .ok()?;
+    String::from_utf8(n).ok()?.parse().ok()
 }
 
 /// Get entry
@@ -210,7 +307,10 @@ pub fn valid(s: &[u8]) -> anyhow::Result<RequestEntry> {
 pub fn get_entry(_type: &arkose::Type) -> anyhow::Result<RequestEntry> {
     let path = get_har_path(_type)?;
     if let Some(filepath) = path.filepath {
-        parse_from_file(filepath)
+        parse_from_file(&filepath).map(|mut entry| {
+            entry.file = Some(filepath);
+            entry
+        })
     } else {
         anyhow::bail!("Failed to get har file path")
     }
@@ -222,6 +322,97 @@ pub async fn read_dir(_type: &Type) -> Result<ReadDir> {
     Ok(tokio::fs::read_dir(path.dir).await?)
 }
 
+/// Read quarantine dir
+pub async fn read_quarantine_dir(_type: &Type) -> Result<ReadDir> {
+    let path = get_har_path(_type)?;
+    Ok(tokio::fs::read_dir(path.dir.join(QUARANTINE_DIR)).await?)
+}
+
+/// HAR file quarantine event
+#[derive(Clone, Serialize)]
+pub struct QuarantineEvent {
+    /// Consecutive failed tokens
+    pub failures: u32,
+    /// Quarantined time, RFC 3339
+    pub time: String,
+}
+
+#[derive(Default)]
+struct Tracker {
+    /// Consecutive failed tokens per HAR file
+    failures: HashMap<PathBuf, u32>,
+    /// Quarantine events by quarantined file path
+    events: HashMap<PathBuf, QuarantineEvent>,
+}
+
+fn tracker() -> &'static Mutex<Tracker> {
+    TRACKER.get_or_init(|| Mutex::new(Tracker::default()))
+}
+
+/// Report whether the HAR file produced a valid token,
+/// files failing repeatedly are moved to the quarantine directory
+pub fn report(file: &Path, success: bool) {
+    let mut tracker = match tracker().lock() {
+        Ok(tracker) => tracker,
+        Err(_) => return,
+    };
+
+    if success {
+        tracker.failures.remove(file);
+        return;
+    }
+
+    let failures = tracker.failures.entry(file.to_path_buf()).or_default();
+    *failures += 1;
+    let failures = *failures;
+    if failures < QUARANTINE_THRESHOLD {
+        return;
+    }
+
+    tracker.failures.remove(file);
+    match quarantine(file) {
+        Ok(target) => {
+            warn!(
+                "HAR file: {} quarantined after {failures} consecutive failed tokens",
+                file.display()
+            );
+            let event = QuarantineEvent {
+                failures,
+                time: time::OffsetDateTime::now_utc()
+                    .format(&Rfc3339)
+                    .unwrap_or_default(),
+            };
+            tracker.events.insert(target, event);
+        }
+        Err(err) => warn!("Failed to quarantine HAR file: {}: {err}", file.display()),
+    }
+}
+
+/// Get the quarantine event of the quarantined file
+pub fn quarantine_event(_type: &Type, filename: &str) -> Option<QuarantineEvent> {
+    let filepath = get_har_path(_type)
+        .ok()?
+        .dir
+        .join(QUARANTINE_DIR)
+        .join(filename);
+    let tracker = tracker().lock().ok()?;
+    tracker.events.get(&filepath).cloned()
+}
+
+fn quarantine(file: &Path) -> Result<PathBuf> {
+    let dir = file
+        .parent()
+        .ok_or_else(|| anyhow!("{} has no parent directory", file.display()))?
+        .join(QUARANTINE_DIR);
+    std::fs::create_dir_all(&dir)?;
+    let target = dir.join(
+        file.file_name()
+            .ok_or_else(|| anyhow!("{} not a file", file.display()))?,
+    );
+    std::fs::rename(file, &target)?;
+    Ok(target)
+}
+
 /// Write entry to file
 #[inline]
 pub async fn write_file(
@@ -265,6 +456,13 @@ fn check_file_extension(file: &PathBuf) -> Result<(), &'static str> {
 
 /// Parse file
 #[inline]
+fn read_entry<P: AsRef<Path>>(path: P) -> Result<RequestEntry> {
+    let bytes = std::fs::read(path)?;
+    let har = serde_json::from_slice::<Har>(&bytes)?;
+    drop(bytes);
+    parse(har)
+}
+
 fn parse_from_file<P: AsRef<Path>>(path: P) -> Result<RequestEntry> {
     // Check if the path is a file
     path.as_ref()
@@ -279,12 +477,7 @@ fn parse_from_file<P: AsRef<Path>>(path: P) -> Result<RequestEntry> {
     let key = format!("{}", path.as_ref().display());
 
     // Try to get the value from the cache
-    let result = cache.try_get_with(key, || {
-        let bytes = std::fs::read(path)?;
-        let har = serde_json::from_slice::<Har>(&bytes)?;
-        drop(bytes);
-        parse(har)
-    });
+    let result = cache.try_get_with(key, || read_entry(path));
 
     match result {
         Ok(value) => Ok(value),
@@ -294,91 +487,97 @@ fn parse_from_file<P: AsRef<Path>>(path: P) -> Result<RequestEntry> {
 
 #[inline]
 fn parse(har: Har) -> Result<RequestEntry> {
-    if let Some(entry) = har
+    let entry = har
         .log
         .entries
         .into_iter()
         .find(|e| e.request.url.contains("fc/gt2/public_key"))
-    {
-        // Check if the entry has a started date time
-        if entry.started_date_time.is_empty() {
-            anyhow::bail!("Invalid HAR file");
-        }
-
-        // Get the public key
-        let pk = entry
-            .request
-            .url
-            .rsplit('/')
-            .next()
-            .ok_or_else(|| anyhow!("Invalid HAR file"))?;
-
-        let typed = Type::from_pk(pk)?;
-
-        let url = format!("{}/fc/gt2/public_key/{}", typed.origin_url(), typed.pk());
-
-        // Request started date time
-        let started_date_time = time::OffsetDateTime::parse(&entry.started_date_time, &Rfc3339)?;
-
-        let bt = started_date_time.unix_timestamp();
-        let bw = bt - (bt % 21600);
-        let mut bv = String::new();
+        .ok_or_else(|| anyhow!("Unable to find the fc/gt2/public_key request entry"))?;
 
-        if let Some(data) = entry.request.post_data {
-            let headers = entry.request.headers;
-
-            if let Some(h) = headers
-                .iter()
-                .find(|h| h.name.eq_ignore_ascii_case("user-agent"))
-            {
-                bv.push_str(&h.value);
-            }
+    // Check if the entry has a started date time
+    if entry.started_date_time.is_empty() {
+        anyhow::bail!("The fc/gt2/public_key request has no started date time");
+    }
 
-            if let Some(bda_param) = data
-                .params
-                .unwrap_or_default()
-                .iter()
-                .find(|p| p.name.eq_ignore_ascii_case("bda"))
-            {
-                let cow = urldecoding::decode(&bda_param.value)?;
-                let bda = base64::engine::general_purpose::STANDARD.decode(cow.into_owned())?;
-                let bx = crypto::decrypt(bda, &format!("{bv}{bw}"))?;
-
-                let entry = RequestEntry {
-                    typed,
-                    url,
-                    method: entry.request.method,
-                    headers: headers
-                        .into_iter()
-                        .filter(|h| {
-                            let name = &h.name;
-                            !name.starts_with(":")
-                                && !name.eq_ignore_ascii_case("content-length")
-                                && !name.eq_ignore_ascii_case("connection")
-                        })
-                        .collect::<Vec<Header>>(),
-                    body: data
-                        .text
-                        .unwrap_or_default()
-                        .split("&")
-                        .into_iter()
-                        .filter(|s| {
-                            !s.contains("bda")
-                                && !s.contains("rnd")
-                                && !s.contains("data[blob]")
-                                && !s.contains("capi_version")
-                        })
-                        .collect::<Vec<&str>>()
-                        .join("&"),
-                    bx,
-                    bv,
-                };
-                return Ok(entry);
-            }
-        }
+    // Get the public key
+    let pk = entry
+        .request
+        .url
+        .rsplit('/')
+        .next()
+        .ok_or_else(|| anyhow!("The fc/gt2/public_key request has no public key"))?;
+
+    let public_key =
+        registry::get(pk).ok_or_else(|| anyhow!("The public key {pk} is not registered"))?;
+    let typed = public_key.typed;
+
+    let url = format!(
+        "{}/fc/gt2/public_key/{}",
+        public_key.origin_url, public_key.pk
+    );
+
+    // Request started date time
+    let started_date_time = time::OffsetDateTime::parse(&entry.started_date_time, &Rfc3339)?;
+
+    let bt = started_date_time.unix_timestamp();
+    let bw = bt - (bt % 21600);
+    let mut bv = String::new();
+
+    let data = entry
+        .request
+        .post_data
+        .ok_or_else(|| anyhow!("The fc/gt2/public_key request has no post data"))?;
+    let headers = entry.request.headers;
+
+    if let Some(h) = headers
+        .iter()
+        .find(|h| h.name.eq_ignore_ascii_case("user-agent"))
+    {
+        bv.push_str(&h.value);
     }
 
-    anyhow::bail!("Unable to find har related request entry")
+    let params = data.params.unwrap_or_default();
+    let bda_param = params
+        .iter()
+        .find(|p| p.name.eq_ignore_ascii_case("bda"))
+        .ok_or_else(|| anyhow!("The fc/gt2/public_key request has no bda parameter"))?;
+
+    let cow = urldecoding::decode(&bda_param.value)?;
+    let bda = base64::engine::general_purpose::STANDARD.decode(cow.into_owned())?;
+    let bx = crypto::decrypt(bda, &format!("{bv}{bw}")).map_err(|err| {
+        anyhow!("Failed to decrypt bda, the user agent or request time does not match: {err}")
+    })?;
+
+    Ok(RequestEntry {
+        typed,
+        url,
+        method: entry.request.method,
+        headers: headers
+            .into_iter()
+            .filter(|h| {
+                let name = &h.name;
+                !name.starts_with(":")
+                    && !name.eq_ignore_ascii_case("content-length")
+                    && !name.eq_ignore_ascii_case("connection")
+            })
+            .collect::<Vec<Header>>(),
+        body: data
+            .text
+            .unwrap_or_default()
+            .split("&")
+            .into_iter()
+            .filter(|s| {
+                !s.contains("bda")
+                    && !s.contains("rnd")
+                    && !s.contains("data[blob]")
+                    && !s.contains("capi_version")
+            })
+            .collect::<Vec<&str>>()
+            .join("&"),
+        bx,
+        bv,
+        file: None,
+    })
 }
 
 #[derive(Debug, Deserialize)]
@@ -425,3 +624,59 @@ pub struct Param {
     pub name: String,
     pub value: String,
 }
+
+#[cfg(test)]
+mod test {
+    use super::{report, QUARANTINE_DIR, QUARANTINE_THRESHOLD};
+    use std::path::{Path, PathBuf};
+
+    fn har_file(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ninja-har-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join(format!("{name}.har"));
+        std::fs::write(&file, "{}").unwrap();
+        file
+    }
+
+    fn quarantined(file: &Path) -> PathBuf {
+        let dir = file.parent().unwrap().join(QUARANTINE_DIR);
+        dir.join(file.file_name().unwrap())
+    }
+
+    #[test]
+    fn test_quarantine_threshold() {
+        let file = har_file("threshold");
+        for _ in 1..QUARANTINE_THRESHOLD {
+            report(&file, false);
+        }
+        assert!(file.exists());
+        assert!(!quarantined(&file).exists());
+
+        report(&file, false);
+        assert!(!file.exists());
+        assert!(quarantined(&file).exists());
+
+        let _ = std::fs::remove_dir_all(file.parent().unwrap());
+    }
+
+    #[test]
+    fn test_success_resets_failures() {
+        let file = har_file("reset");
+        for _ in 1..QUARANTINE_THRESHOLD {
+            report(&file, false);
+        }
+        report(&file, true);
+
+        // The count starts over after a success
+        for _ in 1..QUARANTINE_THRESHOLD {
+            report(&file, false);
+        }
+        assert!(file.exists());
+
+        report(&file, false);
+        assert!(quarantined(&file).exists());
+
+        let _ = std::fs::remove_dir_all(file.parent().unwrap());
+    }
+}
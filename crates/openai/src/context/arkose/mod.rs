@@ -1,9 +1,10 @@
 pub mod har;
 pub mod version;
 
-use self::version::ArkoseVersion;
+use self::version::{ArkoseVersion, ArkoseVersionChange};
 use crate::arkose::Type;
 use crate::homedir::home_dir;
+use crate::with_context;
 use moka::sync::Cache;
 use native_db::{Database, DatabaseBuilder};
 use std::path::PathBuf;
@@ -31,6 +32,9 @@ impl ArkoseVersionContext<'_> {
                 .define::<ArkoseVersion>()
                 .expect("define table failed");
             builder
+                .define::<ArkoseVersionChange>()
+                .expect("define table failed");
+            builder
         });
 
         let path = home_dir()
@@ -71,6 +75,28 @@ impl ArkoseVersionContext<'_> {
         None
     }
 
+    /// Get the version changes of the given type, newest first
+    pub fn history(&self, version_type: Type) -> Vec<ArkoseVersionChange> {
+        let mut changes = match self.db.r_transaction() {
+            Ok(r) => match r.scan().primary::<ArkoseVersionChange>() {
+                Ok(scan) => scan
+                    .all()
+                    .filter(|change| change.typed == version_type)
+                    .collect::<Vec<ArkoseVersionChange>>(),
+                Err(err) => {
+                    warn!("Failed to scan arkose version history: {}", err);
+                    Vec::new()
+                }
+            },
+            Err(err) => {
+                warn!("Failed to read arkose version history: {}", err);
+                Vec::new()
+            }
+        };
+        changes.reverse();
+        changes
+    }
+
     /// Run a periodic task to upgrade the arkose version
     pub async fn periodic_upgrade(&self) {
         info!("Arkose Periodic task is running");
@@ -82,7 +108,7 @@ impl ArkoseVersionContext<'_> {
     }
 
     /// Upgrade the arkose version
-    async fn upgrade(&self) {
+    pub async fn upgrade(&self) {
         // Auth
         self.insert_version(Type::Auth).await;
         // GPT-4
@@ -101,19 +127,68 @@ impl ArkoseVersionContext<'_> {
 
     async fn insert_version(&self, version_type: Type) {
         match version::latest_arkose_version(version_type).await {
-            Ok(version) => {
-                if let Ok(rw) = self.db.rw_transaction() {
-                    if let Some(err) = rw.insert(version).err() {
-                        warn!("Failed to insert arkose version: {}", err)
-                    }
-                    if let Some(err) = rw.commit().err() {
-                        warn!("Failed to commit transaction: {}", err)
-                    }
+            Ok(version) => match self.save_version(version_type, version) {
+                Ok(Some(change)) => self.notify(change).await,
+                Ok(None) => {}
+                Err(err) => {
+                    warn!("Failed to insert arkose version: {}", err)
                 }
-            }
+            },
             Err(err) => {
                 warn!("Failed to get latest arkose version: {}", err)
             }
         }
     }
+
+    /// Save the version, returns the change if it differs from the stored version
+    fn save_version(
+        &self,
+        version_type: Type,
+        version: ArkoseVersion,
+    ) -> anyhow::Result<Option<ArkoseVersionChange>> {
+        let rw = self.db.rw_transaction()?;
+        let change = match rw.get().primary::<ArkoseVersion>(version.pk().to_owned())? {
+            Some(previous) if previous.version().eq(version.version()) => None,
+            previous => Some(ArkoseVersionChange::new(
+                version_type,
+                &version,
+                previous.map(|v| v.version().to_owned()),
+            )),
+        };
+
+        if let Some(change) = change.as_ref() {
+            rw.insert(change.clone())?;
+        }
+        rw.insert(version)?;
+        rw.commit()?;
+
+        // Drop the cached version, so the new one is read back
+        self.cache.invalidate(&version_type);
+        Ok(change)
+    }
+
+    /// Log the version change and post it to the webhook
+    async fn notify(&self, change: ArkoseVersionChange) {
+        let previous = match change.previous.as_deref() {
+            Some(previous) => previous,
+            // First seen version, nothing changed
+            None => return,
+        };
+
+        warn!(
+            "Arkose {} enforcement version changed: {} -> {}",
+            change.typed.name(),
+            previous,
+            change.version
+        );
+
+        let webhook = with_context!(reloadable)
+            .arkose_version_webhook()
+            .map(ToOwned::to_owned);
+        if let Some(webhook) = webhook {
+            if let Some(err) = version::post_webhook(&webhook, &change).await.err() {
+                warn!("Failed to post arkose version change to webhook: {}", err)
+            }
+        }
+    }
 }
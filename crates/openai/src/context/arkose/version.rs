@@ -10,10 +10,11 @@ use crate::{arkose::Type, with_context};
 
 static RE: OnceLock<regex::Regex> = OnceLock::new();
 static RE_VERSION: OnceLock<regex::Regex> = OnceLock::new();
+static WEBHOOK_CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
 
 #[native_db]
 #[native_model(id = 1, version = 1)]
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
 pub struct ArkoseVersion {
     #[primary_key]
     pk: String,
@@ -40,6 +41,42 @@ impl ArkoseVersion {
     }
 }
 
+/// Enforcement version change of a type
+#[native_db]
+#[native_model(id = 2, version = 1)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct ArkoseVersionChange {
+    /// `{type}|{changed}`
+    #[primary_key]
+    key: String,
+    #[serde(rename = "type")]
+    pub typed: Type,
+    pub pk: String,
+    /// Version before the change, none for the first seen version
+    pub previous: Option<String>,
+    pub version: String,
+    pub ref_enforcement_js: String,
+    /// Unix timestamp
+    pub changed: u64,
+}
+
+impl ArkoseVersionChange {
+    pub(super) fn new(typed: Type, version: &ArkoseVersion, previous: Option<String>) -> Self {
+        let changed = crate::now_duration()
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        Self {
+            key: format!("{}|{changed:020}", typed.name()),
+            typed,
+            pk: version.pk.clone(),
+            previous,
+            version: version.version.clone(),
+            ref_enforcement_js: version.ref_enforcement_js.clone(),
+            changed,
+        }
+    }
+}
+
 pub(super) async fn latest_arkose_version(typed: Type) -> Result<ArkoseVersion> {
     let client = with_context!(api_client);
     // Response content
@@ -82,3 +119,79 @@ pub(super) async fn latest_arkose_version(typed: Type) -> Result<ArkoseVersion>
         ref_enforcement_html,
     })
 }
+
+/// Post the version change to the webhook as JSON.
+/// The webhook is an operator endpoint, it is reached directly instead of through the upstream proxies
+pub(super) async fn post_webhook(url: &str, change: &ArkoseVersionChange) -> Result<()> {
+    let client = WEBHOOK_CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .no_proxy()
+            .timeout(std::time::Duration::from_secs(10))
+            .build()
+            .expect("Failed to build webhook client")
+    });
+
+    client
+        .post(url)
+        .json(change)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[cfg(all(test, feature = "serve"))]
+mod test {
+    use super::*;
+    use axum::extract::State;
+    use axum::routing::post;
+    use axum::{Json, Router};
+    use serde_json::Value;
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    type Received = Arc<Mutex<Vec<Value>>>;
+
+    async fn hook(State(received): State<Received>, Json(body): Json<Value>) {
+        received.lock().unwrap().push(body);
+    }
+
+    #[tokio::test]
+    async fn test_post_webhook() {
+        let received = Received::default();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let router = Router::new()
+            .route("/hook", post(hook))
+            .with_state(received.clone());
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(router.into_make_service()),
+        );
+
+        let version = ArkoseVersion {
+            pk: Type::GPT4.pk().to_owned(),
+            version: "2.3.0".to_owned(),
+            ref_enforcement_js: "/v2/2.3.0/enforcement.js".to_owned(),
+            ref_enforcement_html: "/v2/2.3.0/enforcement.html".to_owned(),
+        };
+        let change = ArkoseVersionChange::new(Type::GPT4, &version, Some("2.2.0".to_owned()));
+
+        post_webhook(&format!("http://{addr}/hook"), &change)
+            .await
+            .unwrap();
+        {
+            let received = received.lock().unwrap();
+            assert_eq!(received.len(), 1);
+            assert_eq!(received[0]["type"], "gpt4");
+            assert_eq!(received[0]["previous"], "2.2.0");
+            assert_eq!(received[0]["version"], "2.3.0");
+        }
+
+        // Error statuses are reported
+        assert!(post_webhook(&format!("http://{addr}/missing"), &change)
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,42 @@
+use super::WORKER_DIR;
+use crate::{homedir::home_dir, warn};
+use native_db::{Database, DatabaseBuilder};
+use std::sync::OnceLock;
+
+/// Open a database file in the worker directory.
+///
+/// The tables are defined once per builder, the builder must outlive the database.
+/// Returns `None` when the file cannot be created, callers run without persistence.
+pub(crate) fn open(
+    builder: &'static OnceLock<DatabaseBuilder>,
+    define: impl FnOnce(&mut DatabaseBuilder),
+    name: &str,
+) -> Option<Database<'static>> {
+    let builder = builder.get_or_init(|| {
+        let mut builder = DatabaseBuilder::new();
+        define(&mut builder);
+        builder
+    });
+
+    let dir = match home_dir() {
+        Some(dir) => dir.join(WORKER_DIR),
+        None => {
+            warn!("Failed to get home directory, database {name} disabled");
+            return None;
+        }
+    };
+
+    if let Some(err) = std::fs::create_dir_all(&dir).err() {
+        warn!("Failed to create directory: {}: {err}", dir.display());
+        return None;
+    }
+
+    let path = dir.join(name);
+    match builder.create(&path) {
+        Ok(db) => Some(db),
+        Err(err) => {
+            warn!("Failed to create database: {}: {err}", path.display());
+            None
+        }
+    }
+}
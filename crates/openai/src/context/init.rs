@@ -5,10 +5,13 @@ use super::{
         ArkoseVersionContext,
     },
     preauth::PreauthCookieProvider,
-    CfTurnstile, Context, CTX,
+    Context, Reloadable, CTX,
 };
 use crate::{arkose, client::ClientRoundRobinBalancer, error};
-use std::{collections::HashMap, sync::RwLock};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
 
 /// Use Once to guarantee initialization only once
 pub fn init(args: Args) {
@@ -36,22 +39,9 @@ fn init_context(args: Args) -> Context {
         arkose_client: ClientRoundRobinBalancer::new_arkose_client(&args)
             .expect("Failed to initialize the requesting arkose client"),
         preauth_provider: args.pbind.is_some().then(|| PreauthCookieProvider::new()),
-        arkose_endpoint: args.arkose_endpoint,
         arkose_context: ArkoseVersionContext::new(),
-        arkose_solver: args.arkose_solver,
-        arkose_gpt3_experiment: args.arkose_gpt3_experiment,
-        arkose_gpt3_experiment_solver: args.arkose_gpt3_experiment_solver,
-        arkose_solver_tguess_endpoint: args.arkose_solver_tguess_endpoint,
-        arkose_solver_image_dir: args.arkose_solver_image_dir,
-        enable_file_proxy: args.enable_file_proxy,
-        auth_key: args.auth_key,
-        visitor_email_whitelist: args.visitor_email_whitelist,
-        cf_turnstile: args.cf_site_key.and_then(|site_key| {
-            args.cf_secret_key.map(|secret_key| CfTurnstile {
-                site_key,
-                secret_key,
-            })
-        }),
+        conv_limit: args.conv_limit,
+        reloadable: RwLock::new(Arc::new(Reloadable::from(&args))),
     }
 }
 
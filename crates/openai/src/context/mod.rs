@@ -1,5 +1,6 @@
 pub mod args;
 pub mod arkose;
+pub(crate) mod database;
 pub mod init;
 mod preauth;
 
@@ -7,10 +8,12 @@ use self::preauth::PreauthCookieProvider;
 use crate::{
     arkose::funcaptcha::solver::ArkoseSolver, auth::AuthClient, client::ClientRoundRobinBalancer,
 };
+use cidr::IpCidr;
 use reqwest::Client;
 use std::{
+    net::IpAddr,
     path::{Path, PathBuf},
-    sync::OnceLock,
+    sync::{Arc, OnceLock, RwLock},
 };
 
 pub const WORKER_DIR: &str = ".ninja";
@@ -34,26 +37,27 @@ pub fn init(args: args::Args) {
     init::init(args);
 }
 
+/// Swap the hot reloadable settings of the program context
+pub fn reload(args: &args::Args) {
+    init::instance().reload(args);
+}
+
+#[derive(Clone)]
 pub struct CfTurnstile {
     pub site_key: String,
     pub secret_key: String,
 }
 
-pub struct Context {
-    /// Requesting client
-    api_client: ClientRoundRobinBalancer,
-    /// Requesting oauth client
-    auth_client: ClientRoundRobinBalancer,
-    /// Requesting arkose client
-    arkose_client: ClientRoundRobinBalancer,
-    /// Arkoselabs context
-    arkose_context: arkose::ArkoseVersionContext<'static>,
-    /// arkoselabs solver
-    arkose_solver: Option<ArkoseSolver>,
+/// Settings that can be swapped at runtime when the configuration file changes
+pub struct Reloadable {
     /// Enable files proxy
     enable_file_proxy: bool,
+    /// Per account conversation queue timeout
+    conv_queue_timeout: u64,
     /// Login auth key
     auth_key: Option<String>,
+    /// Trusted reverse proxies
+    trusted_proxies: Vec<IpCidr>,
     /// visitor_email_whitelist
     visitor_email_whitelist: Option<Vec<String>>,
     /// Cloudflare Turnstile
@@ -64,12 +68,109 @@ pub struct Context {
     arkose_gpt3_experiment: bool,
     /// Enable Arkose GPT-3.5 experiment solver
     arkose_gpt3_experiment_solver: bool,
+    /// Generate a random browser fingerprint for Arkose
+    arkose_random_fingerprint: bool,
+    /// arkoselabs solver
+    arkose_solver: Option<ArkoseSolver>,
     /// Arkose solver tguess endpoint
     arkose_solver_tguess_endpoint: Option<String>,
     /// Arkose solver image store directory
     arkose_solver_image_dir: Option<PathBuf>,
+    /// Arkose enforcement version change webhook
+    arkose_version_webhook: Option<String>,
+    /// Effective settings, changes requiring a restart are not included
+    args: Arc<args::Args>,
+}
+
+impl From<&args::Args> for Reloadable {
+    fn from(args: &args::Args) -> Self {
+        Reloadable {
+            enable_file_proxy: args.enable_file_proxy,
+            conv_queue_timeout: args.conv_queue_timeout,
+            auth_key: args.auth_key.clone(),
+            trusted_proxies: args.trusted_proxies.clone(),
+            visitor_email_whitelist: args.visitor_email_whitelist.clone(),
+            cf_turnstile: args.cf_site_key.clone().and_then(|site_key| {
+                args.cf_secret_key.clone().map(|secret_key| CfTurnstile {
+                    site_key,
+                    secret_key,
+                })
+            }),
+            arkose_endpoint: args.arkose_endpoint.clone(),
+            arkose_gpt3_experiment: args.arkose_gpt3_experiment,
+            arkose_gpt3_experiment_solver: args.arkose_gpt3_experiment_solver,
+            arkose_random_fingerprint: args.arkose_random_fingerprint,
+            arkose_solver: args.arkose_solver.clone(),
+            arkose_solver_tguess_endpoint: args.arkose_solver_tguess_endpoint.clone(),
+            arkose_solver_image_dir: args.arkose_solver_image_dir.clone(),
+            arkose_version_webhook: args.arkose_version_webhook.clone(),
+            args: Arc::new(args.clone()),
+        }
+    }
+}
+
+impl Reloadable {
+    /// Login auth key
+    pub fn auth_key(&self) -> Option<&str> {
+        self.auth_key.as_deref()
+    }
+
+    /// Get the trusted reverse proxies
+    pub fn trusted_proxies(&self) -> &[IpCidr] {
+        &self.trusted_proxies
+    }
+
+    /// Get the visitor email whitelist
+    pub fn visitor_email_whitelist(&self) -> Option<&[String]> {
+        self.visitor_email_whitelist.as_deref()
+    }
+
+    /// Cloudflare Turnstile config
+    pub fn cf_turnstile(&self) -> Option<&CfTurnstile> {
+        self.cf_turnstile.as_ref()
+    }
+
+    /// Arkoselabs endpoint
+    pub fn arkose_endpoint(&self) -> Option<&str> {
+        self.arkose_endpoint.as_deref()
+    }
+
+    /// Get the arkoselabs solver
+    pub fn arkose_solver(&self) -> Option<&ArkoseSolver> {
+        self.arkose_solver.as_ref()
+    }
+
+    /// Get the arkose solver tguess endpoint, Example: https://tguess.arkoselabs.com
+    pub fn arkose_solver_tguess_endpoint(&self) -> Option<&str> {
+        self.arkose_solver_tguess_endpoint.as_deref()
+    }
+
+    /// Get the arkose solver image store directory, Example: /home/user/.ninja/image
+    pub fn arkose_solver_image_dir(&self) -> Option<&Path> {
+        self.arkose_solver_image_dir.as_deref()
+    }
+
+    /// Get the arkose enforcement version change webhook
+    pub fn arkose_version_webhook(&self) -> Option<&str> {
+        self.arkose_version_webhook.as_deref()
+    }
+}
+
+pub struct Context {
+    /// Requesting client
+    api_client: ClientRoundRobinBalancer,
+    /// Requesting oauth client
+    auth_client: ClientRoundRobinBalancer,
+    /// Requesting arkose client
+    arkose_client: ClientRoundRobinBalancer,
+    /// Arkoselabs context
+    arkose_context: arkose::ArkoseVersionContext<'static>,
+    /// Per account concurrent conversation limit
+    conv_limit: usize,
     /// PreAuth cookie cache
     preauth_provider: Option<PreauthCookieProvider>,
+    /// Hot reloadable settings, swapped as a whole
+    reloadable: RwLock<Arc<Reloadable>>,
 }
 
 impl Context {
@@ -78,6 +179,17 @@ impl Context {
         self.api_client.next().into()
     }
 
+    /// Get the reqwest client with its label
+    pub fn api_client_with_label(&self) -> (&str, Client) {
+        let (label, client) = self.api_client.next_with_label();
+        (label, client.into())
+    }
+
+    /// Report the result of an upstream request sent by the labeled api client
+    pub fn report_api_client(&self, label: &str, success: bool) {
+        self.api_client.report(label, success)
+    }
+
     /// Get the reqwest auth client
     pub fn auth_client(&self) -> AuthClient {
         self.auth_client.next().into()
@@ -88,24 +200,20 @@ impl Context {
         self.arkose_client.next().into()
     }
 
-    /// Get the arkoselabs solver
-    pub fn arkose_solver(&self) -> Option<&ArkoseSolver> {
-        self.arkose_solver.as_ref()
-    }
-
-    /// Cloudflare Turnstile config
-    pub fn cf_turnstile(&self) -> Option<&CfTurnstile> {
-        self.cf_turnstile.as_ref()
+    /// Get the reqwest arkose client with its label and the chosen IPv6 subnet address
+    pub fn arkose_client_with_egress(&self) -> (&str, Option<IpAddr>, Client) {
+        let (label, addr, client) = self.arkose_client.next_with_egress();
+        (label, addr, client.into())
     }
 
-    /// Arkoselabs endpoint
-    pub fn arkose_endpoint(&self) -> Option<&str> {
-        self.arkose_endpoint.as_deref()
+    /// Report the result of an arkose request sent by the labeled arkose client
+    pub fn report_arkose_client(&self, label: &str, success: bool) {
+        self.arkose_client.report(label, success)
     }
 
-    /// Login auth key
-    pub fn auth_key(&self) -> Option<&str> {
-        self.auth_key.as_deref()
+    /// Effective settings, hot reloaded ones included
+    pub fn args(&self) -> Arc<args::Args> {
+        self.reloadable().args.clone()
     }
 
     /// Push a preauth cookie
@@ -122,24 +230,81 @@ impl Context {
         self.preauth_provider.as_ref().map(|p| p.get()).flatten()
     }
 
+    /// List the preauth cookies
+    #[cfg(feature = "preauth")]
+    pub fn preauth_cookies(&self) -> Vec<String> {
+        self.preauth_provider
+            .as_ref()
+            .map(|p| p.list())
+            .unwrap_or_default()
+    }
+
+    /// Clear the preauth cookies
+    #[cfg(feature = "preauth")]
+    pub fn clear_preauth_cookies(&self) {
+        if let Some(p) = self.preauth_provider.as_ref() {
+            p.clear()
+        }
+    }
+
+    /// Get the balancer client labels
+    pub fn client_labels(&self) -> [(&'static str, &[String]); 3] {
+        [
+            ("api", self.api_client.labels()),
+            ("auth", self.auth_client.labels()),
+            ("arkose", self.arkose_client.labels()),
+        ]
+    }
+
+    /// Get the balancer healthy and total client counts
+    pub fn client_health(&self) -> [(&'static str, usize, usize); 3] {
+        [
+            (
+                "api",
+                self.api_client.healthy(),
+                self.api_client.labels().len(),
+            ),
+            (
+                "auth",
+                self.auth_client.healthy(),
+                self.auth_client.labels().len(),
+            ),
+            (
+                "arkose",
+                self.arkose_client.healthy(),
+                self.arkose_client.labels().len(),
+            ),
+        ]
+    }
+
     /// Get the arkose gpt3 experiment
     pub fn arkose_gpt3_experiment(&self) -> bool {
-        self.arkose_gpt3_experiment
+        self.reloadable().arkose_gpt3_experiment
+    }
+
+    /// Generate a random browser fingerprint for Arkose
+    pub fn arkose_random_fingerprint(&self) -> bool {
+        self.reloadable().arkose_random_fingerprint
     }
 
     /// Enable file proxy
     pub fn enable_file_proxy(&self) -> bool {
-        self.enable_file_proxy
+        self.reloadable().enable_file_proxy
     }
 
-    /// Get the visitor email whitelist
-    pub fn visitor_email_whitelist(&self) -> Option<&[String]> {
-        self.visitor_email_whitelist.as_deref()
+    /// Get the per account concurrent conversation limit
+    pub fn conv_limit(&self) -> usize {
+        self.conv_limit
+    }
+
+    /// Get the per account conversation queue timeout
+    pub fn conv_queue_timeout(&self) -> u64 {
+        self.reloadable().conv_queue_timeout
     }
 
     /// Get the arkose gpt3 experiment solver
     pub fn arkose_gpt3_experiment_solver(&self) -> bool {
-        self.arkose_gpt3_experiment_solver
+        self.reloadable().arkose_gpt3_experiment_solver
     }
 
     /// Get the arkose context
@@ -147,13 +312,20 @@ impl Context {
         &self.arkose_context
     }
 
-    /// Get the arkose solver tguess endpoint, Example: https://tguess.arkoselabs.com
-    pub fn arkose_solver_tguess_endpoint(&self) -> Option<&str> {
-        self.arkose_solver_tguess_endpoint.as_deref()
+    /// Current snapshot of the hot reloadable settings, take one per request and borrow from it
+    pub fn reloadable(&self) -> Arc<Reloadable> {
+        match self.reloadable.read() {
+            Ok(lock) => lock.clone(),
+            Err(err) => err.into_inner().clone(),
+        }
     }
 
-    /// Get the arkose solver image store directory, Example: /home/user/.ninja/image
-    pub fn arkose_solver_image_dir(&self) -> Option<&Path> {
-        self.arkose_solver_image_dir.as_deref()
+    /// Swap the hot reloadable settings
+    fn reload(&self, args: &args::Args) {
+        let reloadable = Arc::new(Reloadable::from(args));
+        match self.reloadable.write() {
+            Ok(mut lock) => *lock = reloadable,
+            Err(err) => *err.into_inner() = reloadable,
+        }
     }
 }
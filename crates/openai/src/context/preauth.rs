@@ -1,4 +1,4 @@
-use crate::{error, homedir::home_dir, info, now_duration};
+use crate::{debug, error, homedir::home_dir, now_duration};
 use moka::sync::Cache;
 use std::{
     path::{Path, PathBuf},
@@ -108,7 +108,7 @@ impl PreauthCookieProvider {
                 let device_id = &value[..colon_index];
                 // If is invalid, skip
                 if !Self::is_invalid(value, Some(max_age)) {
-                    info!("Loading preauth cookie value: {value}",);
+                    debug!("Loading preauth cookie, device id: {device_id}");
                     get_or_init_cache(Some(max_age)).insert(device_id.to_owned(), value.to_owned())
                 }
             });
@@ -122,7 +122,7 @@ impl PreauthCookieProvider {
     pub fn push(&self, value: &str, max_age: Option<u32>) {
         value.find(":").map(|colon_index| {
             let device_id = &value[..colon_index];
-            info!("Push PreAuth Cookie: {value}");
+            debug!("Push preauth cookie, device id: {device_id}");
             get_or_init_cache(max_age).insert(device_id.to_owned(), value.to_owned());
             self.sync_to_file(&self.path, max_age);
         });
@@ -142,6 +142,24 @@ impl PreauthCookieProvider {
         None
     }
 
+    /// List all preauth cookies
+    pub fn list(&self) -> Vec<String> {
+        get_or_init_cache(self.max_age)
+            .iter()
+            .map(|(_, v)| v)
+            .collect()
+    }
+
+    /// Clear all preauth cookies
+    pub fn clear(&self) {
+        let cache = get_or_init_cache(self.max_age);
+        cache.invalidate_all();
+        cache.run_pending_tasks();
+        if let Some(err) = std::fs::write(&self.path, "").err() {
+            error!("Failed to clear preauth cookie file: {}", err);
+        }
+    }
+
     /// Check if is invalid
     fn is_invalid(input: &str, max_age: Option<u32>) -> bool {
         let parts: Vec<&str> = input.split(':').collect();
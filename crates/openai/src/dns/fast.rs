@@ -10,7 +10,11 @@ use trust_dns_resolver::{
     TokioAsyncResolver,
 };
 
-pub(super) const FASTEST_DNS_CONFIG: OnceCell<ResolverConfig> = OnceCell::const_new();
+/// Fastest DNS group picked at startup.
+///
+/// A `static`, not a `const`: every use of a `const` cell is a fresh empty cell,
+/// so the probed group was dropped and the resolver always fell back to its default group.
+pub(super) static FASTEST_DNS_CONFIG: OnceCell<ResolverConfig> = OnceCell::const_new();
 
 /// IP addresses for Tencent Public DNS
 pub const TENCENT_IPS: &[IpAddr] = &[
@@ -49,6 +53,11 @@ impl ResolverConfigExt for ResolverConfig {
     }
 }
 
+/// Whether the fastest DNS resolver has been loaded
+pub fn is_loaded() -> bool {
+    FASTEST_DNS_CONFIG.initialized()
+}
+
 /// Fastest DNS resolver
 pub async fn load_fastest_dns(enabled: bool) -> anyhow::Result<()> {
     if !enabled {
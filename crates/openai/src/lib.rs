@@ -9,6 +9,7 @@ pub mod eventsource;
 pub mod gpt_model;
 pub mod homedir;
 mod log;
+pub mod metrics;
 pub mod platform;
 pub mod proxy;
 
@@ -0,0 +1,195 @@
+use crate::arkose::Type;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+struct Metrics {
+    registry: Registry,
+    /// route, status
+    http_requests: IntCounterVec,
+    /// route, status
+    http_request_duration: HistogramVec,
+    /// client
+    upstream_errors: IntCounterVec,
+    /// source, success
+    arkose_attempts: IntCounterVec,
+    tokenbucket_rejections: IntCounter,
+    /// result (hit/miss)
+    puid_cache: IntCounterVec,
+    /// type, result (hit/miss)
+    arkose_pool: IntCounterVec,
+    sse_streams: IntGauge,
+    transcripts_dropped: IntCounter,
+}
+
+impl Metrics {
+    fn new() -> anyhow::Result<Self> {
+        let registry = Registry::new_custom(Some("ninja".to_owned()), None)?;
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "Total number of HTTP requests"),
+            &["route", "status"],
+        )?;
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "HTTP request latency until response headers are sent",
+            )
+            .buckets(vec![
+                0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0,
+            ]),
+            &["route", "status"],
+        )?;
+        let upstream_errors = IntCounterVec::new(
+            Opts::new("upstream_errors_total", "Total number of upstream errors"),
+            &["client"],
+        )?;
+        let arkose_attempts = IntCounterVec::new(
+            Opts::new(
+                "arkose_attempts_total",
+                "Total number of arkose token attempts",
+            ),
+            &["source", "success"],
+        )?;
+        let tokenbucket_rejections = IntCounter::new(
+            "tokenbucket_rejections_total",
+            "Total number of requests rejected by the token bucket",
+        )?;
+        let puid_cache = IntCounterVec::new(
+            Opts::new("puid_cache_total", "Total number of puid cache lookups"),
+            &["result"],
+        )?;
+        let arkose_pool = IntCounterVec::new(
+            Opts::new(
+                "arkose_pool_total",
+                "Total number of arkose token pool lookups",
+            ),
+            &["type", "result"],
+        )?;
+        let sse_streams = IntGauge::new("sse_streams_active", "Number of active SSE streams")?;
+        let transcripts_dropped = IntCounter::new(
+            "transcripts_dropped_total",
+            "Total number of transcripts dropped because the writer fell behind",
+        )?;
+
+        registry.register(Box::new(http_requests.clone()))?;
+        registry.register(Box::new(http_request_duration.clone()))?;
+        registry.register(Box::new(upstream_errors.clone()))?;
+        registry.register(Box::new(arkose_attempts.clone()))?;
+        registry.register(Box::new(tokenbucket_rejections.clone()))?;
+        registry.register(Box::new(puid_cache.clone()))?;
+        registry.register(Box::new(arkose_pool.clone()))?;
+        registry.register(Box::new(sse_streams.clone()))?;
+        registry.register(Box::new(transcripts_dropped.clone()))?;
+
+        Ok(Self {
+            registry,
+            http_requests,
+            http_request_duration,
+            upstream_errors,
+            arkose_attempts,
+            tokenbucket_rejections,
+            puid_cache,
+            arkose_pool,
+            sse_streams,
+            transcripts_dropped,
+        })
+    }
+}
+
+fn metrics() -> &'static Metrics {
+    METRICS.get_or_init(|| Metrics::new().expect("Failed to initialize metrics"))
+}
+
+/// Arkose token source
+#[derive(Clone, Copy, Debug)]
+pub enum ArkoseSource {
+    Har,
+    Solver,
+    Bx,
+}
+
+impl ArkoseSource {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ArkoseSource::Har => "har",
+            ArkoseSource::Solver => "solver",
+            ArkoseSource::Bx => "bx",
+        }
+    }
+}
+
+pub fn observe_request(route: &str, status: u16, latency: Duration) {
+    let status = status.to_string();
+    let m = metrics();
+    m.http_requests.with_label_values(&[route, &status]).inc();
+    m.http_request_duration
+        .with_label_values(&[route, &status])
+        .observe(latency.as_secs_f64());
+}
+
+pub fn inc_upstream_error(client: &str) {
+    metrics().upstream_errors.with_label_values(&[client]).inc();
+}
+
+pub fn inc_arkose_attempt(source: ArkoseSource, success: bool) {
+    metrics()
+        .arkose_attempts
+        .with_label_values(&[source.as_str(), if success { "true" } else { "false" }])
+        .inc();
+}
+
+pub fn inc_tokenbucket_rejection() {
+    metrics().tokenbucket_rejections.inc();
+}
+
+pub fn inc_transcript_dropped() {
+    metrics().transcripts_dropped.inc();
+}
+
+pub fn inc_puid_cache(hit: bool) {
+    metrics()
+        .puid_cache
+        .with_label_values(&[if hit { "hit" } else { "miss" }])
+        .inc();
+}
+
+pub fn inc_arkose_pool(typed: Type, hit: bool) {
+    metrics()
+        .arkose_pool
+        .with_label_values(&[
+            &format!("{typed:?}").to_lowercase(),
+            if hit { "hit" } else { "miss" },
+        ])
+        .inc();
+}
+
+/// Active SSE stream guard, the gauge is decreased when dropped
+pub struct SseGuard(());
+
+// No `Default`, creating a guard increments the gauge
+#[allow(clippy::new_without_default)]
+impl SseGuard {
+    pub fn new() -> Self {
+        metrics().sse_streams.inc();
+        Self(())
+    }
+}
+
+impl Drop for SseGuard {
+    fn drop(&mut self) {
+        metrics().sse_streams.dec();
+    }
+}
+
+/// Encode all metrics in Prometheus text format
+pub fn encode() -> anyhow::Result<String> {
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&metrics().registry.gather(), &mut buffer)?;
+    Ok(String::from_utf8(buffer)?)
+}
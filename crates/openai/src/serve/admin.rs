@@ -0,0 +1,297 @@
+use super::error::{ProxyError, ResponseError};
+use super::middleware::tokenbucket::{TokenBucket, TokenBucketProvider};
+use super::puid;
+use crate::arkose::Type;
+use crate::context::args::Args;
+use crate::context::arkose::har::HAR;
+use crate::proxy::{InnerProxy, Proxy};
+use crate::{info, with_context};
+use axum::extract::State;
+use axum::headers::authorization::Bearer;
+use axum::headers::Authorization;
+use axum::http::Request;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router, TypedHeader};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+const REDACTED: &str = "******";
+
+#[derive(Clone)]
+pub(super) struct AdminState {
+    pub limit: Arc<TokenBucketProvider>,
+}
+
+/// Admin API, requests are rejected while no auth key is configured
+pub(super) fn config(router: Router, state: AdminState) -> Router {
+    let admin = Router::new()
+        .route("/admin/args", get(get_args))
+        .route("/admin/har", get(get_har_pools))
+        .route("/admin/puid", get(get_puid).delete(delete_puid))
+        .route("/admin/clients", get(get_clients))
+        .route("/admin/tokenbucket", get(get_tokenbucket))
+        .route("/admin/arkose/upgrade", post(post_arkose_upgrade))
+        .route("/admin/arkose/versions", get(get_arkose_versions));
+
+    #[cfg(feature = "preauth")]
+    let admin = admin.route("/admin/preauth", get(get_preauth).delete(delete_preauth));
+
+    router.merge(
+        admin
+            .route_layer(axum::middleware::from_fn(admin_auth_middleware))
+            .with_state(state),
+    )
+}
+
+async fn admin_auth_middleware<B>(
+    bearer: Option<TypedHeader<Authorization<Bearer>>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Result<Response, ResponseError> {
+    let reloadable = with_context!(reloadable);
+    let auth_key = reloadable
+        .auth_key()
+        .ok_or_else(|| ResponseError::Forbidden(ProxyError::AuthKeyRequired))?;
+    let bearer = bearer.ok_or_else(|| ResponseError::Unauthorized(ProxyError::AuthKeyRequired))?;
+    if auth_key.ne(bearer.token()) {
+        return Err(ResponseError::Forbidden(ProxyError::AuthKeyError));
+    }
+    Ok(next.run(request).await)
+}
+
+/// GET /admin/args
+async fn get_args() -> impl IntoResponse {
+    Json(redacted_args(&with_context!(args)))
+}
+
+/// GET /admin/har
+async fn get_har_pools() -> Result<impl IntoResponse, ResponseError> {
+    let lock = HAR.get().and_then(|s| s.read().ok()).ok_or_else(|| {
+        ResponseError::InternalServerError(anyhow::anyhow!("Failed to get har lock"))
+    })?;
+
+    let pools = Type::ALL
+        .iter()
+        .filter_map(|t| lock.get(t).map(|h| (t, h)))
+        .map(|(t, h)| {
+            json!({
+                "type": t.name(),
+                "dir": h.dir().display().to_string(),
+                "count": h.files().len(),
+                "files": h.files(),
+            })
+        })
+        .collect::<Vec<Value>>();
+
+    Ok(Json(pools))
+}
+
+/// GET /admin/puid
+async fn get_puid() -> impl IntoResponse {
+    let entries = puid::list()
+        .await
+        .into_iter()
+        .map(|(email, puid)| json!({ "email": email, "puid": puid }))
+        .collect::<Vec<Value>>();
+    Json(entries)
+}
+
+/// DELETE /admin/puid
+async fn delete_puid() -> impl IntoResponse {
+    puid::clear().await;
+    info!("Admin cleared puid cache");
+    Json(json!({ "success": true }))
+}
+
+/// GET /admin/preauth
+#[cfg(feature = "preauth")]
+async fn get_preauth() -> impl IntoResponse {
+    Json(with_context!(preauth_cookies))
+}
+
+/// DELETE /admin/preauth
+#[cfg(feature = "preauth")]
+async fn delete_preauth() -> impl IntoResponse {
+    with_context!(clear_preauth_cookies);
+    info!("Admin cleared preauth cookie cache");
+    Json(json!({ "success": true }))
+}
+
+/// GET /admin/clients
+async fn get_clients() -> impl IntoResponse {
+    let clients = with_context!(client_labels)
+        .iter()
+        .map(|(pool, labels)| json!({ "pool": pool, "count": labels.len(), "clients": labels }))
+        .collect::<Vec<Value>>();
+    Json(clients)
+}
+
+/// GET /admin/tokenbucket
+async fn get_tokenbucket(
+    State(state): State<AdminState>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let entries = state
+        .limit
+        .entries()
+        .map_err(ResponseError::InternalServerError)?;
+    Ok(Json(entries))
+}
+
+/// POST /admin/arkose/upgrade
+async fn post_arkose_upgrade() -> impl IntoResponse {
+    let ctx = with_context!(arkose_context);
+    ctx.upgrade().await;
+    info!("Admin triggered arkose version upgrade");
+
+    let versions = Type::ALL
+        .iter()
+        .map(|t| {
+            json!({
+                "type": t.name(),
+                "version": ctx.version(*t).map(|v| v.version().to_owned()),
+            })
+        })
+        .collect::<Vec<Value>>();
+    Json(versions)
+}
+
+/// GET /admin/arkose/versions
+async fn get_arkose_versions() -> impl IntoResponse {
+    let ctx = with_context!(arkose_context);
+    let versions = Type::ALL
+        .iter()
+        .map(|t| {
+            let history = ctx.history(*t);
+            json!({
+                "type": t.name(),
+                "version": ctx.version(*t).map(|v| v.version().to_owned()),
+                "previous": history.first().and_then(|c| c.previous.clone()),
+                "changed": history.first().map(|c| c.changed),
+                "history": history,
+            })
+        })
+        .collect::<Vec<Value>>();
+    Json(versions)
+}
+
+/// Effective args, secrets are redacted
+fn redacted_args(args: &Args) -> Value {
+    let redact = |v: Option<&String>| v.map(|_| REDACTED);
+
+    let proxies = args
+        .proxies
+        .iter()
+        .map(|p| {
+            let inner = match p {
+                Proxy::All(inner)
+                | Proxy::Api(inner)
+                | Proxy::Auth(inner)
+                | Proxy::Arkose(inner) => inner,
+            };
+            let inner = match inner {
+                InnerProxy::Proxy(url) => redact_url(url.as_str()),
+                InnerProxy::Interface(ip) => ip.to_string(),
+                InnerProxy::IPv6Subnet(subnet) => subnet.to_string(),
+            };
+            format!("{}|{inner}", p.proto().to_lowercase())
+        })
+        .collect::<Vec<String>>();
+
+    #[allow(unused_mut)]
+    let mut value = json!({
+        "bind": args.bind,
+        "log_format": args.log_format,
+        "concurrent_limit": args.concurrent_limit,
+        "conv_limit": args.conv_limit,
+        "conv_queue_timeout": args.conv_queue_timeout,
+        "transcript_dir": args.transcript_dir,
+        "transcript_retention": args.transcript_retention,
+        "cookie_store": args.cookie_store,
+        "fastest_dns": args.fastest_dns,
+        "tcp_keepalive": args.tcp_keepalive,
+        "no_keepalive": args.no_keepalive,
+        "pool_idle_timeout": args.pool_idle_timeout,
+        "timeout": args.timeout,
+        "connect_timeout": args.connect_timeout,
+        "enable_direct": args.enable_direct,
+        "proxies": proxies,
+        "impersonate_uas": args
+            .impersonate_uas
+            .as_ref()
+            .map(|uas| uas.iter().map(|ua| format!("{ua:?}")).collect::<Vec<String>>()),
+        "tls_cert": args.tls_cert,
+        "tls_key": args.tls_key,
+        "metrics_bind": args.metrics_bind,
+        "trusted_proxies": args.trusted_proxies,
+        "proxy_protocol": args.proxy_protocol,
+        "allow_cidrs": args.allow_cidrs,
+        "deny_cidrs": args.deny_cidrs,
+        "cidr_file": args.cidr_file,
+        "visitor_email_whitelist": args.visitor_email_whitelist,
+        "auth_key": redact(args.auth_key.as_ref()),
+        "enable_webui": args.enable_webui,
+        "enable_file_proxy": args.enable_file_proxy,
+        "enable_arkose_proxy": args.enable_arkose_proxy,
+        "cf_site_key": args.cf_site_key,
+        "cf_secret_key": redact(args.cf_secret_key.as_ref()),
+        "arkose_endpoint": args.arkose_endpoint,
+        "arkose_har_dir": args.arkose_har_dir,
+        "arkose_gpt3_experiment": args.arkose_gpt3_experiment,
+        "arkose_gpt3_experiment_solver": args.arkose_gpt3_experiment_solver,
+        "arkose_random_fingerprint": args.arkose_random_fingerprint,
+        "arkose_solver": args.arkose_solver.as_ref().map(|solver| json!({
+            "solver": solver.solver,
+            "limit": solver.limit,
+            "budget": solver.budget,
+            "client_key": REDACTED,
+        })),
+        "arkose_solver_tguess_endpoint": args.arkose_solver_tguess_endpoint,
+        "arkose_solver_image_dir": args.arkose_solver_image_dir,
+        "arkose_pool_size": args.arkose_pool_size,
+        "arkose_pool_ttl": args.arkose_pool_ttl,
+        "arkose_pool_types": args.arkose_pool_types,
+        "arkose_public_keys": args.arkose_public_keys,
+        "arkose_version_webhook": redact(args.arkose_version_webhook.as_ref()),
+        "tb_enable": args.tb_enable,
+        "tb_strategy": args.tb_strategy,
+        "tb_capacity": args.tb_capacity,
+        "tb_fill_rate": args.tb_fill_rate,
+        "tb_expired": args.tb_expired,
+    });
+
+    #[cfg(feature = "preauth")]
+    if let Some(map) = value.as_object_mut() {
+        map.insert("pbind".to_owned(), json!(args.pbind));
+        map.insert(
+            "pupstream".to_owned(),
+            json!(args.pupstream.as_deref().map(redact_url)),
+        );
+        map.insert("pcert".to_owned(), json!(args.pcert));
+        map.insert("pkey".to_owned(), json!(args.pkey));
+    }
+
+    #[cfg(feature = "otel")]
+    if let Some(map) = value.as_object_mut() {
+        map.insert(
+            "otlp_endpoint".to_owned(),
+            json!(args.otlp_endpoint.as_deref().map(redact_url)),
+        );
+    }
+
+    value
+}
+
+/// Redact the password of url
+fn redact_url(url: &str) -> String {
+    match url::Url::parse(url) {
+        Ok(mut url) => {
+            if url.password().is_some() {
+                let _ = url.set_password(Some(REDACTED));
+            }
+            url.to_string()
+        }
+        Err(_) => url.to_owned(),
+    }
+}
@@ -0,0 +1,189 @@
+use super::error::{ProxyError, ResponseError};
+use super::puid::reduce_key;
+use crate::{debug, with_context};
+use axum::body::{HttpBody, StreamBody};
+use axum::response::{IntoResponse, Response};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// email -> conversation semaphore, an entry lives as long as its permits and waiters
+type Semaphores = Mutex<HashMap<String, Arc<Semaphore>>>;
+
+static SEMAPHORES: OnceLock<Semaphores> = OnceLock::new();
+
+fn lock(semaphores: &Semaphores) -> MutexGuard<'_, HashMap<String, Arc<Semaphore>>> {
+    semaphores.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Drop the account semaphore when the map and the caller hold the last references,
+/// references are only cloned under the lock so no permit or waiter can be missed
+fn prune(
+    semaphores: &mut HashMap<String, Arc<Semaphore>>,
+    email: &str,
+    semaphore: &Arc<Semaphore>,
+) {
+    if Arc::strong_count(semaphore) == 2
+        && semaphores
+            .get(email)
+            .map(|s| Arc::ptr_eq(s, semaphore))
+            .unwrap_or(false)
+    {
+        semaphores.remove(email);
+    }
+}
+
+/// Account conversation permit, released when the response is finished
+pub struct ConversationPermit {
+    semaphores: &'static Semaphores,
+    email: String,
+    semaphore: Arc<Semaphore>,
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for ConversationPermit {
+    fn drop(&mut self) {
+        let mut semaphores = lock(self.semaphores);
+        drop(self.permit.take());
+        prune(&mut semaphores, &self.email, &self.semaphore);
+    }
+}
+
+/// Acquire a conversation permit for the account, waiting in queue until timeout
+pub(super) async fn acquire(token: &str) -> Result<Option<ConversationPermit>, ResponseError> {
+    let limit = with_context!(conv_limit);
+    if limit == 0 {
+        return Ok(None);
+    }
+
+    let email = reduce_key(token)?;
+    let timeout = Duration::from_secs(with_context!(conv_queue_timeout));
+    let semaphores = SEMAPHORES.get_or_init(Default::default);
+    acquire_permit(semaphores, email, limit, timeout)
+        .await
+        .map(Some)
+}
+
+/// Wait for a permit of the account semaphore, at most `limit` conversations run at once
+async fn acquire_permit(
+    semaphores: &'static Semaphores,
+    email: String,
+    limit: usize,
+    timeout: Duration,
+) -> Result<ConversationPermit, ResponseError> {
+    let semaphore = lock(semaphores)
+        .entry(email.clone())
+        .or_insert_with(|| Arc::new(Semaphore::new(limit)))
+        .clone();
+
+    let result = tokio::time::timeout(timeout, semaphore.clone().acquire_owned()).await;
+    if let Ok(Ok(permit)) = result {
+        return Ok(ConversationPermit {
+            semaphores,
+            email,
+            semaphore,
+            permit: Some(permit),
+        });
+    }
+
+    prune(&mut lock(semaphores), &email, &semaphore);
+    match result {
+        Ok(Err(err)) => Err(ResponseError::InternalServerError(err)),
+        _ => {
+            debug!("Account {email} conversation queue timeout");
+            Err(ResponseError::TooManyRequests(
+                ProxyError::TooManyConversations,
+            ))
+        }
+    }
+}
+
+/// Keep the guard (conversation permit, stream gauge) alive until the response body is fully sent
+pub(super) fn hold<G: Send + 'static>(resp: Response, guard: G) -> Response {
+    let (parts, mut body) = resp.into_parts();
+    let stream = async_stream::stream! {
+        let _guard = guard;
+        while let Some(chunk) = body.data().await {
+            yield chunk;
+        }
+    };
+    (parts, StreamBody::new(stream)).into_response()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::http::StatusCode;
+
+    const TIMEOUT: Duration = Duration::from_millis(50);
+
+    fn semaphores() -> &'static Semaphores {
+        Box::leak(Box::default())
+    }
+
+    async fn acquire(
+        semaphores: &'static Semaphores,
+        email: &str,
+        limit: usize,
+    ) -> Result<ConversationPermit, ResponseError> {
+        acquire_permit(semaphores, email.to_owned(), limit, TIMEOUT).await
+    }
+
+    #[tokio::test]
+    async fn test_per_account_limit() {
+        let semaphores = semaphores();
+        let first = acquire(semaphores, "a@example.com", 2).await;
+        let second = acquire(semaphores, "a@example.com", 2).await;
+        assert!(first.is_ok() && second.is_ok());
+
+        // The limit is per account, other accounts are not affected
+        assert!(acquire(semaphores, "a@example.com", 2).await.is_err());
+        assert!(acquire(semaphores, "b@example.com", 2).await.is_ok());
+
+        drop(first);
+        assert!(acquire(semaphores, "a@example.com", 2).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_queue_timeout() {
+        let semaphores = semaphores();
+        let permit = acquire(semaphores, "a@example.com", 1).await;
+        assert!(permit.is_ok());
+
+        let err = match acquire(semaphores, "a@example.com", 1).await {
+            Ok(_) => panic!("expected queue timeout"),
+            Err(err) => err,
+        };
+        assert_eq!(err.into_response().status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn test_queued_request_gets_released_permit() {
+        let semaphores = semaphores();
+        let permit = acquire(semaphores, "a@example.com", 1).await;
+        assert!(permit.is_ok());
+
+        tokio::spawn(async move {
+            tokio::time::sleep(TIMEOUT / 5).await;
+            drop(permit);
+        });
+        assert!(acquire(semaphores, "a@example.com", 1).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_semaphore_kept_while_permit_held() {
+        let semaphores = semaphores();
+        let permit = acquire(semaphores, "a@example.com", 1).await;
+        assert!(permit.is_ok());
+
+        // A timed out waiter does not drop the semaphore of the held permit
+        assert!(acquire(semaphores, "a@example.com", 1).await.is_err());
+        assert!(lock(semaphores).contains_key("a@example.com"));
+        assert!(acquire(semaphores, "a@example.com", 1).await.is_err());
+
+        // The last permit drops the semaphore
+        drop(permit);
+        assert!(lock(semaphores).is_empty());
+    }
+}
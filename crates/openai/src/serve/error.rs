@@ -34,6 +34,8 @@ pub enum ProxyError {
     InvalidUploadField,
     #[error("Too Many Requests")]
     TooManyRequests,
+    #[error("Too many concurrent conversations on this account, please try again later")]
+    TooManyConversations,
     #[error("Your access is not in the whitelist")]
     AccessNotInWhitelist,
     #[error("Auth Key required!")]
@@ -44,6 +46,10 @@ pub enum ProxyError {
     DeserializeError(serde_json::Error),
     #[error("Invalid access token")]
     InvalidAccessToken,
+    #[error("Client address not found")]
+    ClientAddrNotFound,
+    #[error("Your address is not allowed")]
+    AddressNotAllowed,
 
     /// get access token profile error
     #[error("Get access token profile error")]
@@ -80,6 +86,13 @@ impl ResponseError {
     }
 }
 
+impl ResponseError {
+    /// Whether the error is a 5xx error
+    pub fn is_server_error(&self) -> bool {
+        self.code >= 500
+    }
+}
+
 // Tell axum how to convert `ResponseError` into a response.
 impl IntoResponse for ResponseError {
     fn into_response(self) -> Response {
@@ -0,0 +1,210 @@
+use crate::arkose::Type;
+use crate::context::args::Args;
+use crate::context::arkose::har::HAR;
+use crate::dns;
+use crate::with_context;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde_json::{json, Map, Value};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+#[derive(Clone)]
+pub(super) struct HealthState {
+    fastest_dns: bool,
+    /// HAR files are only required when a HAR directory is configured
+    har_dir: bool,
+    pbind: Option<SocketAddr>,
+}
+
+impl From<&Args> for HealthState {
+    fn from(args: &Args) -> Self {
+        HealthState {
+            fastest_dns: args.fastest_dns,
+            har_dir: args.arkose_har_dir.is_some(),
+            #[cfg(feature = "preauth")]
+            pbind: args.pbind,
+            #[cfg(not(feature = "preauth"))]
+            pbind: None,
+        }
+    }
+}
+
+pub(super) fn config(router: Router, args: &Args) -> Router {
+    router.merge(
+        Router::new()
+            .route("/healthz", get(get_healthz))
+            .route("/readyz", get(get_readyz))
+            .with_state(HealthState::from(args)),
+    )
+}
+
+/// GET /healthz
+async fn get_healthz() -> impl IntoResponse {
+    Json(json!({ "status": "ok" }))
+}
+
+/// GET /readyz
+async fn get_readyz(State(state): State<HealthState>) -> impl IntoResponse {
+    let checks = [
+        ("dns", check_dns(state.fastest_dns)),
+        ("arkose_version", check_arkose_version()),
+        ("har", check_har(state.har_dir)),
+        ("clients", check_clients()),
+        ("preauth", check_preauth(state.pbind).await),
+    ];
+
+    let ready = checks.iter().all(|(_, check)| check.ok);
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    let checks = checks
+        .into_iter()
+        .map(|(name, check)| {
+            (
+                name.to_owned(),
+                json!({ "ok": check.ok, "detail": check.detail }),
+            )
+        })
+        .collect::<Map<String, Value>>();
+
+    (
+        status,
+        Json(json!({
+            "status": if ready { "ready" } else { "unready" },
+            "checks": checks,
+        })),
+    )
+}
+
+struct Check {
+    ok: bool,
+    detail: Value,
+}
+
+/// Fastest DNS resolver finished loading
+fn check_dns(enabled: bool) -> Check {
+    let (ok, detail) = match (enabled, dns::fast::is_loaded()) {
+        (false, _) => (true, "disabled"),
+        (true, true) => (true, "loaded"),
+        (true, false) => (false, "loading"),
+    };
+    Check {
+        ok,
+        detail: json!(detail),
+    }
+}
+
+/// Each arkose type has a loaded version
+fn check_arkose_version() -> Check {
+    let ctx = with_context!(arkose_context);
+    let versions = Type::ALL
+        .iter()
+        .map(|t| {
+            (
+                format!("{t:?}"),
+                ctx.version(*t).map(|v| v.version().to_owned()),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    Check {
+        ok: versions.iter().all(|(_, v)| v.is_some()),
+        detail: Value::Object(versions.into_iter().map(|(t, v)| (t, json!(v))).collect()),
+    }
+}
+
+/// Each HAR pool has at least one file, informational without a configured HAR directory
+fn check_har(required: bool) -> Check {
+    let lock = match HAR.get().and_then(|s| s.read().ok()) {
+        Some(lock) => lock,
+        None => {
+            return Check {
+                ok: !required,
+                detail: json!("Failed to get har lock"),
+            }
+        }
+    };
+
+    let pools = Type::ALL
+        .iter()
+        .map(|t| (format!("{t:?}"), lock.get(t).map_or(0, |h| h.files().len())))
+        .collect::<Vec<_>>();
+
+    Check {
+        ok: !required || pools.iter().all(|(_, count)| *count > 0),
+        detail: Value::Object(
+            pools
+                .into_iter()
+                .map(|(t, n)| (t, json!({ "files": n, "required": required })))
+                .collect(),
+        ),
+    }
+}
+
+/// Balancer pools whose clients report upstream failures, the others are informational
+const REPORTED_POOLS: [&str; 2] = ["api", "arkose"];
+
+/// Each reported balancer pool has at least one healthy client
+fn check_clients() -> Check {
+    let pools = with_context!(client_health);
+
+    Check {
+        ok: pools
+            .iter()
+            .filter(|(pool, ..)| REPORTED_POOLS.contains(pool))
+            .all(|(_, healthy, _)| *healthy > 0),
+        detail: Value::Object(
+            pools
+                .iter()
+                .map(|(pool, healthy, total)| {
+                    (
+                        pool.to_string(),
+                        json!({
+                            "healthy": healthy,
+                            "total": total,
+                            "required": REPORTED_POOLS.contains(pool),
+                        }),
+                    )
+                })
+                .collect(),
+        ),
+    }
+}
+
+/// Preauth MITM listener accepts connections
+async fn check_preauth(pbind: Option<SocketAddr>) -> Check {
+    let mut addr = match pbind {
+        Some(addr) => addr,
+        None => {
+            return Check {
+                ok: true,
+                detail: json!("disabled"),
+            }
+        }
+    };
+
+    // Connect through loopback when listening on all interfaces
+    if addr.ip().is_unspecified() {
+        addr.set_ip(match addr.ip() {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        });
+    }
+
+    let ok = matches!(
+        tokio::time::timeout(Duration::from_secs(1), tokio::net::TcpStream::connect(addr)).await,
+        Ok(Ok(_))
+    );
+
+    Check {
+        ok,
+        detail: json!(if ok { "listening" } else { "unreachable" }),
+    }
+}
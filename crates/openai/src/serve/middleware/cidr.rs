@@ -0,0 +1,378 @@
+use crate::serve::error::{ProxyError, ResponseError};
+use crate::serve::realip;
+use crate::{info, warn};
+use axum::{extract::State, http::Request, middleware::Next, response::Response};
+use cidr::IpCidr;
+use hotwatch::{Event, EventKind, Hotwatch};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+/// Route group, rules can be applied to all groups or to a single one
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Group {
+    Api,
+    WebUI,
+    Har,
+    Arkose,
+}
+
+impl Group {
+    fn from_path(path: &str) -> Self {
+        if path.starts_with("/har/") {
+            return Group::Har;
+        }
+
+        if path.starts_with("/auth/arkose_token/") {
+            return Group::Arkose;
+        }
+
+        match path {
+            "/auth/token"
+            | "/auth/refresh_token"
+            | "/auth/revoke_token"
+            | "/auth/refresh_session"
+            | "/auth/sess_token"
+            | "/auth/billing" => Group::Api,
+            _ if path.starts_with("/v1/")
+                || path.starts_with("/dashboard/")
+                || path.starts_with("/backend-api/")
+                || path.starts_with("/public-api/")
+                || path.starts_with("/files/") =>
+            {
+                Group::Api
+            }
+            _ => Group::WebUI,
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl FromStr for Group {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "api" => Ok(Group::Api),
+            "webui" => Ok(Group::WebUI),
+            "har" => Ok(Group::Har),
+            "arkose" => Ok(Group::Arkose),
+            _ => anyhow::bail!("route group: {} is not supported", s),
+        }
+    }
+}
+
+impl std::fmt::Display for Group {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Group::Api => write!(f, "api"),
+            Group::WebUI => write!(f, "webui"),
+            Group::Har => write!(f, "har"),
+            Group::Arkose => write!(f, "arkose"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct Rule {
+    allow: Vec<IpCidr>,
+    deny: Vec<IpCidr>,
+}
+
+#[derive(Debug, Default, Clone)]
+struct Rules {
+    /// Rules applied to all groups
+    global: Rule,
+    /// Rules applied to a single group, indexed by `Group`
+    groups: [Rule; 4],
+}
+
+impl Rules {
+    /// Deny rules always win; a group allow list replaces the global one
+    fn check(&self, group: Group, ip: &IpAddr) -> bool {
+        let rule = &self.groups[group.index()];
+
+        if self
+            .global
+            .deny
+            .iter()
+            .chain(rule.deny.iter())
+            .any(|cidr| cidr.contains(ip))
+        {
+            return false;
+        }
+
+        let allow = if rule.allow.is_empty() {
+            &self.global.allow
+        } else {
+            &rule.allow
+        };
+
+        allow.is_empty() || allow.iter().any(|cidr| cidr.contains(ip))
+    }
+
+    fn is_empty(&self) -> bool {
+        std::iter::once(&self.global)
+            .chain(self.groups.iter())
+            .all(|rule| rule.allow.is_empty() && rule.deny.is_empty())
+    }
+
+    /// Parse rules file, one rule per line: `allow|deny [api|webui|har|arkose] <cidr>`
+    fn parse(&mut self, content: &str) -> anyhow::Result<()> {
+        for (n, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let parts = line.split_whitespace().collect::<Vec<&str>>();
+            let (action, group, cidr) = match parts.as_slice() {
+                [action, cidr] => (*action, None, *cidr),
+                [action, group, cidr] => (*action, Some(Group::from_str(group)?), *cidr),
+                _ => anyhow::bail!("Invalid rule at line {}: {}", n + 1, line),
+            };
+
+            let cidr = IpCidr::from_str(cidr)
+                .map_err(|_| anyhow::anyhow!("Invalid cidr at line {}: {}", n + 1, cidr))?;
+
+            let rule = match group {
+                Some(group) => &mut self.groups[group.index()],
+                None => &mut self.global,
+            };
+
+            match action {
+                "allow" => rule.allow.push(cidr),
+                "deny" => rule.deny.push(cidr),
+                _ => anyhow::bail!("Invalid action at line {}: {}", n + 1, action),
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct CidrProvider {
+    /// Rules from command line
+    base: Rules,
+    /// Effective rules
+    rules: Arc<RwLock<Rules>>,
+    /// Rules file
+    file: Option<PathBuf>,
+    /// File Hotwatch
+    hotwatch: Option<Hotwatch>,
+}
+
+impl CidrProvider {
+    pub fn new(allow: Vec<IpCidr>, deny: Vec<IpCidr>, file: Option<PathBuf>) -> Self {
+        let base = Rules {
+            global: Rule { allow, deny },
+            ..Default::default()
+        };
+
+        let rules = Arc::new(RwLock::new(base.clone()));
+        let mut provider = CidrProvider {
+            base,
+            rules,
+            file,
+            hotwatch: None,
+        };
+
+        if let Some(file) = provider.file.clone() {
+            if let Some(err) = provider.reload().err() {
+                warn!("Failed to load cidr rules file: {}: {err}", file.display());
+            }
+            provider.hotwatch = Some(watch_cidr_file(
+                file,
+                provider.base.clone(),
+                provider.rules.clone(),
+            ));
+        }
+
+        provider
+    }
+
+    fn reload(&self) -> anyhow::Result<()> {
+        if let Some(file) = self.file.as_ref() {
+            load_rules(file, &self.base, &self.rules)?;
+        }
+        Ok(())
+    }
+
+    /// Check whether the client address can access the route group
+    pub fn check(&self, group: Group, ip: &IpAddr) -> bool {
+        // A poisoned lock still holds complete rules, the deny lists keep applying
+        let rules = self.rules.read().unwrap_or_else(|err| err.into_inner());
+        rules.is_empty() || rules.check(group, ip)
+    }
+}
+
+impl Drop for CidrProvider {
+    fn drop(&mut self) {
+        if let (Some(hotwatch), Some(file)) = (self.hotwatch.as_mut(), self.file.as_ref()) {
+            if let Some(err) = hotwatch.unwatch(watch_dir(file)).err() {
+                warn!("hotwatch stop error: {err}")
+            }
+        }
+    }
+}
+
+fn load_rules(file: &Path, base: &Rules, rules: &RwLock<Rules>) -> anyhow::Result<()> {
+    let mut new_rules = base.clone();
+    if file.exists() {
+        new_rules.parse(&std::fs::read_to_string(file)?)?;
+    }
+    *rules.write().unwrap_or_else(|err| err.into_inner()) = new_rules;
+    Ok(())
+}
+
+/// Watch the parent directory, editors usually replace the file instead of writing in place
+pub(crate) fn watch_dir(file: &Path) -> PathBuf {
+    file.parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+fn watch_cidr_file(file: PathBuf, base: Rules, rules: Arc<RwLock<Rules>>) -> Hotwatch {
+    let mut hotwatch = Hotwatch::new().expect("hotwatch failed to initialize!");
+    let dir = watch_dir(&file);
+    info!("Start watching cidr rules file: {}", file.display());
+    hotwatch
+        .watch(dir, move |event: Event| match event.kind {
+            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_) => {
+                if !event
+                    .paths
+                    .iter()
+                    .any(|path| path.file_name() == file.file_name())
+                {
+                    return;
+                }
+                info!("Cidr rules file: {} changes observed", file.display());
+                if let Some(err) = load_rules(&file, &base, &rules).err() {
+                    warn!(
+                        "Failed to reload cidr rules file: {}: {err}",
+                        file.display()
+                    );
+                }
+            }
+            _ => {}
+        })
+        .expect("failed to watch file!");
+    hotwatch
+}
+
+pub(crate) async fn cidr_middleware<B>(
+    State(provider): State<Arc<CidrProvider>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Result<Response, ResponseError> {
+    let addr = realip::resolve(request.extensions(), request.headers())
+        .map_err(ResponseError::InternalServerError)?;
+    let group = Group::from_path(request.uri().path());
+
+    if provider.check(group, &addr) {
+        return Ok(next.run(request).await);
+    }
+
+    warn!(
+        "Rejected request from {addr} to {group} route: {}",
+        request.uri().path()
+    );
+    Err(ResponseError::Forbidden(ProxyError::AddressNotAllowed))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rules(content: &str) -> Rules {
+        let mut rules = Rules::default();
+        rules.parse(content).unwrap();
+        rules
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let rules = rules(
+            "# office\n\
+             allow 10.0.0.0/8\n\
+             \n\
+             deny har 10.1.0.0/16 # contractors\n\
+             allow api 2001:db8::/32\n",
+        );
+        assert_eq!(rules.global.allow.len(), 1);
+        assert!(rules.global.deny.is_empty());
+        assert_eq!(rules.groups[Group::Har.index()].deny.len(), 1);
+        assert_eq!(rules.groups[Group::Api.index()].allow.len(), 1);
+        assert!(!rules.is_empty());
+        assert!(Rules::default().is_empty());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for content in [
+            "allow",
+            "allow api 10.0.0.0/8 extra",
+            "permit 10.0.0.0/8",
+            "allow admin 10.0.0.0/8",
+            "deny 10.0.0.300/8",
+        ] {
+            assert!(Rules::default().parse(content).is_err(), "{content}");
+        }
+    }
+
+    #[test]
+    fn test_deny_over_allow() {
+        let rules = rules("allow 10.0.0.0/8\ndeny 10.1.0.0/16\nallow har 10.1.2.0/24");
+        assert!(rules.check(Group::Api, &ip("10.2.0.1")));
+        assert!(!rules.check(Group::Api, &ip("10.1.0.1")));
+        // A group allow does not override a global deny
+        assert!(!rules.check(Group::Har, &ip("10.1.2.3")));
+        assert!(!rules.check(Group::Api, &ip("192.168.1.1")));
+    }
+
+    #[test]
+    fn test_group_rules() {
+        let rules = rules("allow 10.0.0.0/8\nallow har 192.168.0.0/16\ndeny arkose 10.0.0.1/32");
+
+        // The group allow list replaces the global one
+        assert!(rules.check(Group::Har, &ip("192.168.1.1")));
+        assert!(!rules.check(Group::Har, &ip("10.0.0.2")));
+        assert!(!rules.check(Group::WebUI, &ip("192.168.1.1")));
+
+        // Group denies only apply to their group
+        assert!(!rules.check(Group::Arkose, &ip("10.0.0.1")));
+        assert!(rules.check(Group::Arkose, &ip("10.0.0.2")));
+        assert!(rules.check(Group::Api, &ip("10.0.0.1")));
+    }
+
+    #[test]
+    fn test_poisoned_rules_still_apply() {
+        let provider = CidrProvider::new(vec![], vec!["10.0.0.0/8".parse().unwrap()], None);
+        let rules = provider.rules.clone();
+        let _ = std::thread::spawn(move || {
+            let _lock = rules.write().unwrap();
+            panic!("poison the cidr rules lock");
+        })
+        .join();
+        assert!(provider.rules.is_poisoned());
+        assert!(!provider.check(Group::Api, &ip("10.0.0.1")));
+        assert!(provider.check(Group::Api, &ip("192.168.1.1")));
+    }
+
+    #[test]
+    fn test_group_from_path() {
+        assert_eq!(Group::from_path("/har/upload"), Group::Har);
+        assert_eq!(Group::from_path("/auth/arkose_token/gpt4"), Group::Arkose);
+        assert_eq!(Group::from_path("/auth/token"), Group::Api);
+        assert_eq!(Group::from_path("/backend-api/conversation"), Group::Api);
+        assert_eq!(Group::from_path("/"), Group::WebUI);
+    }
+}
@@ -0,0 +1,19 @@
+use crate::metrics;
+use axum::{extract::MatchedPath, http::Request, middleware::Next, response::Response};
+use std::time::Instant;
+
+pub(crate) async fn metrics_middleware<B>(
+    matched_path: Option<MatchedPath>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    // Use the route template to keep label cardinality bounded
+    let route = matched_path
+        .as_ref()
+        .map(|p| p.as_str().to_owned())
+        .unwrap_or_else(|| "unmatched".to_owned());
+    let start = Instant::now();
+    let response = next.run(request).await;
+    metrics::observe_request(&route, response.status().as_u16(), start.elapsed());
+    response
+}
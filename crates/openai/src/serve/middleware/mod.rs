@@ -1,6 +1,8 @@
 pub mod auth;
+pub mod cidr;
 pub mod csrf;
 #[cfg(feature = "limit")]
 pub mod limit;
+pub mod metrics;
 #[cfg(feature = "limit")]
 pub mod tokenbucket;
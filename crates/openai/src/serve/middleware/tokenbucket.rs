@@ -1,6 +1,7 @@
 use moka::sync::Cache;
 use serde::{Deserialize, Serialize};
 use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::{Arc, OnceLock};
 use std::time::Duration;
 
@@ -9,6 +10,16 @@ use crate::{context, debug, error, now_duration};
 
 pub trait TokenBucket: Send + Sync {
     fn acquire(&self, ip: IpAddr) -> anyhow::Result<bool>;
+
+    /// List all bucket entries
+    fn entries(&self) -> anyhow::Result<Vec<BucketEntry>>;
+}
+
+#[derive(Serialize, Debug)]
+pub struct BucketEntry {
+    pub ip: IpAddr,
+    pub tokens: u32,
+    pub last_time: u64,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -36,6 +47,43 @@ impl std::str::FromStr for Strategy {
     }
 }
 
+/// Token bucket parameters, can be updated at runtime
+pub struct BucketParams {
+    enable: AtomicBool,
+    /// token bucket capacity `capacity`
+    capacity: AtomicU32,
+    /// token bucket fill rate `fill_rate`
+    fill_rate: AtomicU32,
+}
+
+impl BucketParams {
+    fn new(enable: bool, capacity: u32, fill_rate: u32) -> Self {
+        Self {
+            enable: AtomicBool::new(enable),
+            capacity: AtomicU32::new(capacity),
+            fill_rate: AtomicU32::new(fill_rate),
+        }
+    }
+
+    fn update(&self, enable: bool, capacity: u32, fill_rate: u32) {
+        self.enable.store(enable, Ordering::Relaxed);
+        self.capacity.store(capacity, Ordering::Relaxed);
+        self.fill_rate.store(fill_rate, Ordering::Relaxed);
+    }
+
+    fn enable(&self) -> bool {
+        self.enable.load(Ordering::Relaxed)
+    }
+
+    fn capacity(&self) -> u32 {
+        self.capacity.load(Ordering::Relaxed)
+    }
+
+    fn fill_rate(&self) -> u32 {
+        self.fill_rate.load(Ordering::Relaxed)
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct BucketState {
     tokens: u32,
@@ -43,11 +91,7 @@ struct BucketState {
 }
 
 pub struct MemTokenBucket {
-    enable: bool,
-    /// token bucket capacity `capacity`
-    capacity: u32,
-    /// token bucket fill rate `fill_rate`
-    fill_rate: u32,
+    params: BucketParams,
     /// ip -> token backet
     buckets: moka::sync::Cache<IpAddr, BucketState>,
 }
@@ -59,9 +103,7 @@ impl MemTokenBucket {
             .time_to_idle(Duration::from_secs(expired as u64))
             .build();
         Self {
-            enable,
-            capacity,
-            fill_rate,
+            params: BucketParams::new(enable, capacity, fill_rate),
             buckets,
         }
     }
@@ -69,9 +111,10 @@ impl MemTokenBucket {
 
 impl TokenBucket for MemTokenBucket {
     fn acquire(&self, ip: IpAddr) -> anyhow::Result<bool> {
-        if !self.enable {
+        if !self.params.enable() {
             return Ok(true);
         }
+        let capacity = self.params.capacity();
 
         let now_timestamp = now_duration()?.as_secs();
 
@@ -79,14 +122,14 @@ impl TokenBucket for MemTokenBucket {
             .buckets
             .entry(ip)
             .or_insert(BucketState {
-                tokens: self.capacity,
+                tokens: capacity,
                 last_time: now_timestamp,
             })
             .into_value();
 
         let elapsed = now_timestamp - bucket.last_time;
-        let tokens_to_add = (elapsed as u32) * self.fill_rate;
-        bucket.tokens = (bucket.tokens + tokens_to_add).min(self.capacity);
+        let tokens_to_add = (elapsed as u32) * self.params.fill_rate();
+        bucket.tokens = (bucket.tokens + tokens_to_add).min(capacity);
         bucket.last_time = now_timestamp;
 
         if bucket.tokens > 0 {
@@ -97,6 +140,18 @@ impl TokenBucket for MemTokenBucket {
             Ok(false)
         }
     }
+
+    fn entries(&self) -> anyhow::Result<Vec<BucketEntry>> {
+        Ok(self
+            .buckets
+            .iter()
+            .map(|(ip, bucket)| BucketEntry {
+                ip: *ip,
+                tokens: bucket.tokens,
+                last_time: bucket.last_time,
+            })
+            .collect())
+    }
 }
 
 use anyhow::Result;
@@ -117,11 +172,7 @@ struct ReDBBucketState {
 
 #[derive(typed_builder::TypedBuilder)]
 pub struct RedisTokenBucket<'a> {
-    enable: bool,
-    /// token bucket capacity `capacity`
-    capacity: u32,
-    /// token bucket fill rate `fill_rate`
-    fill_rate: u32,
+    params: BucketParams,
     /// native db
     db: Arc<native_db::Database<'a>>,
 }
@@ -149,9 +200,7 @@ impl<'a> RedisTokenBucket<'a> {
         // clear expired buckets every expired seconds
         clear_expired_buckets_every(db.clone(), expired);
         Self {
-            enable,
-            capacity,
-            fill_rate,
+            params: BucketParams::new(enable, capacity, fill_rate),
             db,
         }
     }
@@ -212,9 +261,10 @@ fn clear_expired_buckets_every(db: Arc<Database<'static>>, expired: u32) {
 
 impl TokenBucket for RedisTokenBucket<'_> {
     fn acquire(&self, ip: IpAddr) -> anyhow::Result<bool> {
-        if !self.enable {
+        if !self.params.enable() {
             return Ok(true);
         }
+        let capacity = self.params.capacity();
 
         let rw = self.db.rw_transaction()?;
         let pk = ip_to_number(ip);
@@ -223,14 +273,14 @@ impl TokenBucket for RedisTokenBucket<'_> {
             Some(bucket) => bucket,
             None => ReDBBucketState {
                 ip: pk,
-                tokens: self.capacity,
+                tokens: capacity,
                 last_time: now_timestamp,
             },
         };
 
         let elapsed = now_timestamp - bucket.last_time;
-        let tokens_to_add = (elapsed as u32) * self.fill_rate;
-        bucket.tokens = (bucket.tokens + tokens_to_add).min(self.capacity);
+        let tokens_to_add = (elapsed as u32) * self.params.fill_rate();
+        bucket.tokens = (bucket.tokens + tokens_to_add).min(capacity);
         bucket.last_time = now_timestamp;
 
         if bucket.tokens > 0 {
@@ -242,6 +292,21 @@ impl TokenBucket for RedisTokenBucket<'_> {
             Ok(false)
         }
     }
+
+    fn entries(&self) -> anyhow::Result<Vec<BucketEntry>> {
+        let r = self.db.r_transaction()?;
+        let entries = r
+            .scan()
+            .primary::<ReDBBucketState>()?
+            .all()
+            .map(|bucket| BucketEntry {
+                ip: number_to_ip(bucket.ip),
+                tokens: bucket.tokens,
+                last_time: bucket.last_time,
+            })
+            .collect();
+        Ok(entries)
+    }
 }
 
 fn ip_to_number(ip: IpAddr) -> u128 {
@@ -275,6 +340,13 @@ fn ip_to_number(ip: IpAddr) -> u128 {
     }
 }
 
+fn number_to_ip(number: u128) -> IpAddr {
+    match u32::try_from(number) {
+        Ok(v4) => IpAddr::V4(v4.into()),
+        Err(_) => IpAddr::V6(number.into()),
+    }
+}
+
 pub enum TokenBucketProvider {
     Mem(MemTokenBucket),
     ReDB(RedisTokenBucket<'static>),
@@ -290,6 +362,16 @@ impl From<(Strategy, bool, u32, u32, u32)> for TokenBucketProvider {
     }
 }
 
+impl TokenBucketProvider {
+    /// Update the bucket parameters, the store strategy and expiry are kept
+    pub fn update(&self, enable: bool, capacity: u32, fill_rate: u32) {
+        match self {
+            Self::Mem(t) => t.params.update(enable, capacity, fill_rate),
+            Self::ReDB(t) => t.params.update(enable, capacity, fill_rate),
+        }
+    }
+}
+
 impl TokenBucket for TokenBucketProvider {
     fn acquire(&self, ip: IpAddr) -> anyhow::Result<bool> {
         let condition = match self {
@@ -298,4 +380,11 @@ impl TokenBucket for TokenBucketProvider {
         };
         Ok(condition?)
     }
+
+    fn entries(&self) -> anyhow::Result<Vec<BucketEntry>> {
+        match self {
+            Self::Mem(t) => t.entries(),
+            Self::ReDB(t) => t.entries(),
+        }
+    }
 }
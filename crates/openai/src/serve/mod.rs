@@ -1,30 +1,45 @@
+mod admin;
+mod conversation;
 mod error;
+mod health;
+mod logging;
 mod middleware;
+#[cfg(feature = "otel")]
+mod otel;
 #[cfg(feature = "preauth")]
 mod preauth;
 mod proxy;
+mod proxy_protocol;
 mod puid;
+mod realip;
+mod reload;
 #[cfg(feature = "template")]
 mod router;
 mod signal;
+mod transcript;
 mod turnstile;
+mod usage;
 mod whitelist;
 
 use self::proxy::ext::RequestExt;
 use self::proxy::ext::SendRequestExt;
 use self::proxy::resp::response_convert;
+use self::proxy_protocol::ProxyProtocolAcceptor;
 use crate::arkose;
 use crate::arkose::ArkoseContext;
 use crate::arkose::ArkoseToken;
+use crate::arkose::{TokenMeta, TokenSource};
 use crate::auth::model::{AccessToken, AuthAccount, RefreshToken, SessionAccessToken};
 use crate::auth::provide::AuthProvider;
 use crate::constant::API_AUTH_SESSION_COOKIE_KEY;
 use crate::context;
 use crate::context::args::Args;
 use crate::dns;
+use crate::metrics;
 use crate::proxy::{InnerProxy, Proxy};
 use crate::serve::error::ProxyError;
 use crate::serve::error::ResponseError;
+use crate::serve::middleware::cidr::CidrProvider;
 use crate::serve::middleware::tokenbucket::{Strategy, TokenBucketProvider};
 use crate::{info, warn, with_context};
 use crate::{URL_CHATGPT_API, URL_PLATFORM_API};
@@ -50,23 +65,44 @@ use std::ops::Not;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
 use tower_http::trace;
 use tracing::Level;
-use tracing_subscriber::prelude::__tracing_subscriber_SubscriberExt;
-use tracing_subscriber::util::SubscriberInitExt;
 
 type ProxyResult<T> = std::result::Result<T, ProxyError>;
 
 fn print_boot_message(inner: &Args) {
     info!("OS: {}", std::env::consts::OS);
     info!("Arch: {}", std::env::consts::ARCH);
+    info!("Log format: {}", inner.log_format);
+    #[cfg(feature = "otel")]
+    inner.otlp_endpoint.as_ref().map(|endpoint| {
+        info!("OTLP endpoint: {endpoint}");
+    });
     info!("Concurrent limit: {}", inner.concurrent_limit);
+    info!("Account conversation limit: {}", inner.conv_limit);
+    inner.metrics_bind.as_ref().map(|bind| {
+        info!("Metrics bind address: {bind}");
+    });
     info!("Timeout {} seconds", inner.timeout);
     info!("Connect timeout {} seconds", inner.connect_timeout);
     info!("Keepalive {} seconds", inner.tcp_keepalive);
     info!("TCP keepalive: {}", inner.no_keepalive.not());
     info!("Cookie store: {}", inner.cookie_store);
     info!("Enable direct connection: {}", inner.enable_direct);
+    info!("PROXY protocol: {}", inner.proxy_protocol);
+    inner.trusted_proxies.iter().for_each(|cidr| {
+        info!("Trusted proxy: {cidr}");
+    });
+    inner.allow_cidrs.iter().for_each(|cidr| {
+        info!("Allow cidr: {cidr}");
+    });
+    inner.deny_cidrs.iter().for_each(|cidr| {
+        info!("Deny cidr: {cidr}");
+    });
+    inner.cidr_file.as_ref().map(|file| {
+        info!("Cidr rules file: {}", file.display());
+    });
     info!("Enable WebUI: {}", inner.enable_webui);
     info!("Enable File endpoint: {}", inner.enable_file_proxy);
     info!(
@@ -115,29 +151,47 @@ impl Serve {
     /// from issue: https://github.com/hyperium/hyper/issues/3140
     #[tokio::main]
     pub async fn run(self) -> anyhow::Result<()> {
-        tracing_subscriber::registry()
-            .with(
-                tracing_subscriber::EnvFilter::try_from_default_env()
-                    .unwrap_or_else(|_| "RUST_LOG=warn".into()),
-            )
-            .with(tracing_subscriber::fmt::layer())
-            .init();
+        logging::init(&self.0)?;
 
         // print boot message
         print_boot_message(&self.0);
 
+        // init arkose public key registry
+        arkose::registry::init(self.0.arkose_public_keys.clone())?;
+
         // init context
         context::init(self.0.clone());
 
+        // init conversation transcript recording
+        transcript::init(self.0.transcript_dir.clone(), self.0.transcript_retention);
+
+        // init usage statistics, shown on the WebUI usage page
+        if self.0.enable_webui {
+            usage::init();
+        }
+
         // init global layer provider
         let global_layer = tower::ServiceBuilder::new()
+            .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
             .layer(
                 tower_http::trace::TraceLayer::new_for_http()
-                    .make_span_with(trace::DefaultMakeSpan::new().level(Level::INFO))
-                    .on_response(trace::DefaultOnResponse::new().level(Level::INFO))
+                    .make_span_with(logging::AccessLog)
+                    .on_response(logging::AccessLog)
                     .on_request(trace::DefaultOnRequest::new().level(Level::INFO))
                     .on_failure(trace::DefaultOnFailure::new().level(Level::WARN)),
             )
+            .layer(PropagateRequestIdLayer::x_request_id())
+            .layer(axum::middleware::from_fn(
+                middleware::metrics::metrics_middleware,
+            ))
+            .layer(axum::middleware::from_fn_with_state(
+                Arc::new(CidrProvider::new(
+                    self.0.allow_cidrs.clone(),
+                    self.0.deny_cidrs.clone(),
+                    self.0.cidr_file.clone(),
+                )),
+                middleware::cidr::cidr_middleware,
+            ))
             .layer(tower::limit::ConcurrencyLimitLayer::new(
                 self.0.concurrent_limit,
             ))
@@ -156,24 +210,27 @@ impl Serve {
             )))
             .layer(axum::extract::DefaultBodyLimit::max(200 * 1024 * 1024));
 
+        // init token bucket provider
+        let limit_context = Arc::new(TokenBucketProvider::from((
+            Strategy::from_str(self.0.tb_strategy.as_str())?,
+            self.0.tb_enable,
+            self.0.tb_capacity,
+            self.0.tb_fill_rate,
+            self.0.tb_expired,
+        )));
+
+        // Hot reload the config file, the watcher lives as long as the server
+        let _config_watcher = reload::watch(&self.0, limit_context.clone());
+
         // init auth layer provider
-        let app_layer = {
-            let limit_context = TokenBucketProvider::from((
-                Strategy::from_str(self.0.tb_strategy.as_str())?,
-                self.0.tb_enable,
-                self.0.tb_capacity,
-                self.0.tb_fill_rate,
-                self.0.tb_expired,
+        let app_layer = tower::ServiceBuilder::new()
+            .layer(axum::middleware::from_fn(middleware::auth::auth_middleware))
+            .layer(axum::middleware::from_fn(usage::usage_middleware))
+            .layer(axum::middleware::from_fn_with_state(
+                limit_context.clone(),
+                middleware::limit::limit_middleware,
             ));
 
-            tower::ServiceBuilder::new()
-                .layer(axum::middleware::from_fn(middleware::auth::auth_middleware))
-                .layer(axum::middleware::from_fn_with_state(
-                    Arc::new(limit_context),
-                    middleware::limit::limit_middleware,
-                ))
-        };
-
         let router = Router::new()
             .route("/dashboard/*path", any(official_proxy))
             .route("/v1/*path", any(official_proxy))
@@ -187,10 +244,31 @@ impl Serve {
             .route("/auth/sess_token", post(post_sess_token))
             .route("/auth/billing", post(post_billing));
 
+        // Metrics endpoint, served on a separate listener if configured
+        let router = if self.0.metrics_bind.is_none() {
+            router.route("/metrics", get(get_metrics))
+        } else {
+            router
+        };
+
+        // Health and readiness probes
+        let router = health::config(router, &self.0);
+
+        // Admin API
+        let router = admin::config(
+            router,
+            admin::AdminState {
+                limit: limit_context,
+            },
+        );
+
         let router = router::config(
             // Enable arkose token endpoint proxy
             if self.0.enable_arkose_proxy {
-                router.route("/auth/arkose_token/:path", get(get_arkose_token))
+                router.route(
+                    "/auth/arkose_token/:path",
+                    get(get_arkose_token).post(post_arkose_token),
+                )
             } else {
                 router
             },
@@ -213,6 +291,30 @@ impl Serve {
         // upgrade arkose version.
         tokio::spawn(with_context!(arkose_context).periodic_upgrade());
 
+        // pre-generate arkose tokens.
+        arkose::pool::init(
+            self.0.arkose_pool_size,
+            self.0.arkose_pool_ttl,
+            &self.0.arkose_pool_types,
+        );
+
+        // check arkose solver balance.
+        tokio::spawn(check_solver_balance());
+
+        // metrics server
+        if let Some(metrics_bind) = self.0.metrics_bind {
+            info!("Starting metrics server at http://{metrics_bind}/metrics");
+            let metrics_router = Router::new().route("/metrics", get(metrics_handler));
+            let metrics_server = axum_server::bind(metrics_bind)
+                .handle(handle.clone())
+                .serve(metrics_router.into_make_service());
+            tokio::spawn(async move {
+                if let Some(err) = metrics_server.await.err() {
+                    warn!("Metrics server error: {}", err);
+                }
+            });
+        }
+
         // http server tcp keepalive
         let tcp_keepalive = Duration::from_secs(self.0.tcp_keepalive as u64 + 1);
 
@@ -256,26 +358,44 @@ impl Serve {
         );
 
         // Run http server
+        let bind = self.0.bind.unwrap();
+        let proxy_protocol = self.0.proxy_protocol;
+        let make_service = router.into_make_service_with_connect_info::<SocketAddr>();
         let result = match (self.0.tls_cert, self.0.tls_key) {
             (Some(cert), Some(key)) => {
                 let tls_config = RustlsConfig::from_pem_file(cert, key)
                     .await
                     .expect("Failed to load TLS keypair");
 
-                axum_server::bind_rustls(self.0.bind.unwrap(), tls_config)
+                let server = axum_server::bind_rustls(bind, tls_config)
                     .handle(handle)
                     .addr_incoming_config(incoming_config)
-                    .http_config(http_config)
-                    .serve(router.into_make_service_with_connect_info::<SocketAddr>())
-                    .await
+                    .http_config(http_config);
+
+                // The PROXY header precedes the TLS handshake
+                if proxy_protocol {
+                    server
+                        .map(|acceptor| acceptor.acceptor(ProxyProtocolAcceptor))
+                        .serve(make_service)
+                        .await
+                } else {
+                    server.serve(make_service).await
+                }
             }
             _ => {
-                axum_server::bind(self.0.bind.unwrap())
+                let server = axum_server::bind(bind)
                     .handle(handle)
                     .addr_incoming_config(incoming_config)
-                    .http_config(http_config)
-                    .serve(router.into_make_service_with_connect_info::<SocketAddr>())
-                    .await
+                    .http_config(http_config);
+
+                if proxy_protocol {
+                    server
+                        .acceptor(ProxyProtocolAcceptor)
+                        .serve(make_service)
+                        .await
+                } else {
+                    server.serve(make_service).await
+                }
             }
         };
 
@@ -287,11 +407,42 @@ impl Serve {
             warn!("Send shutdown signal error: {}", err);
         }
 
+        // Flush spans off the runtime threads, the batch exporter runs on them
+        #[cfg(feature = "otel")]
+        let _ = tokio::task::spawn_blocking(otel::shutdown).await;
+
         tokio::time::sleep(Duration::from_secs(1)).await;
         Ok(())
     }
 }
 
+/// GET /metrics
+async fn get_metrics(
+    bearer: Option<TypedHeader<Authorization<Bearer>>>,
+) -> Result<impl IntoResponse, ResponseError> {
+    if let Some(auth_key) = with_context!(reloadable).auth_key() {
+        // check bearer token exist
+        let bearer =
+            bearer.ok_or_else(|| ResponseError::Unauthorized(ProxyError::AuthKeyRequired))?;
+        if auth_key.ne(bearer.token()) {
+            return Err(ResponseError::Forbidden(ProxyError::AuthKeyError));
+        }
+    }
+    metrics_handler().await
+}
+
+/// GET /metrics (separate listener)
+async fn metrics_handler() -> Result<impl IntoResponse, ResponseError> {
+    let body = metrics::encode().map_err(ResponseError::InternalServerError)?;
+    Ok((
+        [(
+            header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        body,
+    ))
+}
+
 /// POST /auth/billing
 async fn post_billing(
     TypedHeader(bearer): TypedHeader<Authorization<Bearer>>,
@@ -346,7 +497,7 @@ async fn post_access_token(
     // check username/email in whitelist
     whitelist::check_whitelist(&account.username).map_err(ResponseError::Forbidden)?;
 
-    if let Some(auth_key) = with_context!(auth_key) {
+    if let Some(auth_key) = with_context!(reloadable).auth_key() {
         // check bearer token exist
         let bearer =
             bearer.ok_or_else(|| ResponseError::Unauthorized(ProxyError::AuthKeyRequired))?;
@@ -390,6 +541,21 @@ async fn post_revoke_token(
     }
 }
 
+/// Log the balance of the paid arkose solver
+async fn check_solver_balance() {
+    if let Some(solver) = with_context!(reloadable).arkose_solver() {
+        let name = solver.solver.to_string();
+        match solver.balance().await {
+            Ok(Some(balance)) if balance <= 0.0 => {
+                warn!("Arkose solver {name} balance is exhausted: {balance}")
+            }
+            Ok(Some(balance)) => info!("Arkose solver {name} balance: {balance}"),
+            Ok(None) => {}
+            Err(err) => warn!("Failed to get arkose solver {name} balance: {err}"),
+        }
+    }
+}
+
 /// GET /auth/arkose_token/:path
 /// Example: /auth//arkose_token/35536E1E-65B4-4D96-9D97-6ADB7EFF8147
 #[derive(serde::Deserialize)]
@@ -402,8 +568,120 @@ async fn get_arkose_token(
     pk: Path<String>,
     blob: Option<Query<Blob>>,
 ) -> Result<Json<ArkoseToken>, ResponseError> {
+    let public_key = arkose_token_public_key(bearer, &pk)?;
+
+    let (egress, egress_addr, client) = with_context!(arkose_client_with_egress);
+    ArkoseToken::new_from_pool(
+        ArkoseContext::builder()
+            .client(client)
+            .typed(public_key.typed)
+            .public_key(Some(public_key))
+            .identifier(blob.map(|v| v.0.blob).flatten())
+            .egress(Some(egress.to_owned()))
+            .egress_addr(egress_addr)
+            .build(),
+    )
+    .await
+    .map(Json)
+    .map_err(ResponseError::ExpectationFailed)
+}
+
+/// POST /auth/arkose_token/:path
+/// Example: {"blob": "...", "user_agent": "...", "source": "har"}
+#[derive(serde::Deserialize, Default)]
+struct ArkoseTokenOptions {
+    blob: Option<String>,
+    /// User agent used for the bda and the funcaptcha
+    user_agent: Option<String>,
+    /// Preferred token source (pool/har/bx), defaults to the pool
+    source: Option<TokenSource>,
+}
+
+impl ArkoseTokenOptions {
+    /// Pooled tokens carry no blob or user agent, so either one bypasses the pool
+    fn pooled(&self) -> Result<bool, ResponseError> {
+        match self.source {
+            Some(TokenSource::Experiment) => Err(ResponseError::BadRequest(anyhow::anyhow!(
+                "Unsupported arkose token source (experiment)"
+            ))),
+            Some(TokenSource::Har) | Some(TokenSource::Bx) => Ok(false),
+            Some(TokenSource::Pool) | None => Ok(self.blob.is_none() && self.user_agent.is_none()),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct ArkoseTokenDetail {
+    #[serde(flatten)]
+    token: ArkoseToken,
+    #[serde(flatten)]
+    meta: TokenMeta,
+    /// The token is accepted without challenge (sup=1)
+    solved: bool,
+    /// Time taken to get the token (millisecond)
+    latency: u128,
+    /// Estimated expiry unix timestamp
+    expires_at: u64,
+    /// Estimated seconds until expiry
+    expires_in: u64,
+}
+
+async fn post_arkose_token(
+    bearer: Option<TypedHeader<Authorization<Bearer>>>,
+    pk: Path<String>,
+    options: Option<Json<ArkoseTokenOptions>>,
+) -> Result<Json<ArkoseTokenDetail>, ResponseError> {
+    let public_key = arkose_token_public_key(bearer, &pk)?;
+    let options = options.map(|v| v.0).unwrap_or_default();
+    let pooled = options.pooled()?;
+
+    let (egress, egress_addr, client) = with_context!(arkose_client_with_egress);
+    let ctx = ArkoseContext::builder()
+        .client(client)
+        .typed(public_key.typed)
+        .public_key(Some(public_key))
+        .identifier(options.blob)
+        .user_agent(options.user_agent)
+        .source(options.source)
+        .egress(Some(egress.to_owned()))
+        .egress_addr(egress_addr)
+        .build();
+
+    let start = std::time::Instant::now();
+    let token = match pooled {
+        true => ArkoseToken::new_from_pool(ctx).await,
+        false => ArkoseToken::new_from_context(ctx).await,
+    }
+    .map_err(ResponseError::ExpectationFailed)?;
+    let latency = start.elapsed().as_millis();
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(ResponseError::InternalServerError)?
+        .as_secs();
+    let meta = *token.meta();
+    let expires_at = match meta.created {
+        0 => now,
+        created => created,
+    } + arkose::TOKEN_LIFETIME;
+
+    Ok(Json(ArkoseTokenDetail {
+        solved: token.success(),
+        token,
+        meta,
+        latency,
+        expires_at,
+        expires_in: expires_at.saturating_sub(now),
+    }))
+}
+
+/// Check the auth key and get the registered arkose public key
+fn arkose_token_public_key(
+    bearer: Option<TypedHeader<Authorization<Bearer>>>,
+    pk: &str,
+) -> Result<&'static arkose::registry::PublicKey, ResponseError> {
     // Require auth key
-    if let Some(auth_key) = with_context!(auth_key) {
+    if let Some(auth_key) = with_context!(reloadable).auth_key() {
         // check bearer token exist
         let bearer =
             bearer.ok_or_else(|| ResponseError::Unauthorized(ProxyError::AuthKeyRequired))?;
@@ -412,19 +690,9 @@ async fn get_arkose_token(
         }
     }
 
-    // Require arkose token endpoint public key
-    let typed = arkose::Type::from_pk(pk.as_str()).map_err(ResponseError::BadRequest)?;
-
-    ArkoseToken::new_from_context(
-        ArkoseContext::builder()
-            .client(with_context!(arkose_client))
-            .typed(typed)
-            .identifier(blob.map(|v| v.0.blob).flatten())
-            .build(),
-    )
-    .await
-    .map(Json)
-    .map_err(ResponseError::ExpectationFailed)
+    // Require a registered arkose token endpoint public key
+    arkose::registry::get(pk)
+        .ok_or_else(|| ResponseError::BadRequest(anyhow::anyhow!("Invalid public key ({pk})")))
 }
 
 /// match path /dashboard/{tail.*}
@@ -437,20 +705,41 @@ async fn get_arkose_token(
 /// platform API match path /v1/{tail.*}
 /// reference: https://platform.openai.com/docs/api-reference
 async fn official_proxy(req: RequestExt) -> Result<impl IntoResponse, ResponseError> {
-    let resp = with_context!(api_client)
+    let (label, client) = with_context!(api_client_with_label);
+    logging::record_upstream(label);
+    let resp = client
         .send_request(URL_PLATFORM_API, req)
-        .await?;
+        .await
+        .map_err(|err| observe_upstream_error(label, err))?;
+    observe_upstream(label, resp.inner.status().is_server_error());
     response_convert(resp).await
 }
 
 /// reference: doc/http.rest
 async fn unofficial_proxy(req: RequestExt) -> Result<impl IntoResponse, ResponseError> {
-    let resp = with_context!(api_client)
+    let (label, client) = with_context!(api_client_with_label);
+    logging::record_upstream(label);
+    let resp = client
         .send_request(URL_CHATGPT_API, req)
-        .await?;
+        .await
+        .map_err(|err| observe_upstream_error(label, err))?;
+    observe_upstream(label, resp.inner.status().is_server_error());
     response_convert(resp).await
 }
 
+/// Count server side failures against the upstream client
+fn observe_upstream_error(label: &str, err: ResponseError) -> ResponseError {
+    observe_upstream(label, err.is_server_error());
+    err
+}
+
+fn observe_upstream(label: &str, server_error: bool) {
+    if server_error {
+        metrics::inc_upstream_error(label);
+    }
+    with_context!(report_api_client, label, !server_error);
+}
+
 impl TryInto<Response<Body>> for SessionAccessToken {
     type Error = ResponseError;
 
@@ -509,3 +798,43 @@ async fn check_wan_address() {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn options(json: &str) -> ArkoseTokenOptions {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_arkose_token_options() {
+        for (json, pooled) in [
+            ("{}", true),
+            (r#"{"source": "pool"}"#, true),
+            (r#"{"source": "har"}"#, false),
+            (r#"{"source": "bx"}"#, false),
+            (r#"{"blob": "blob"}"#, false),
+            (r#"{"user_agent": "Mozilla/5.0"}"#, false),
+            (r#"{"source": "pool", "blob": "blob"}"#, false),
+        ] {
+            assert_eq!(options(json).pooled().ok(), Some(pooled), "{json}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_post_arkose_token_rejects_experiment() {
+        let result = post_arkose_token(
+            None,
+            Path(arkose::Type::GPT4.pk().to_owned()),
+            Some(Json(options(r#"{"source": "experiment"}"#))),
+        )
+        .await;
+
+        let status = match result {
+            Ok(_) => panic!("experiment source accepted"),
+            Err(err) => err.into_response().status(),
+        };
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
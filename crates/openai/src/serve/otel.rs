@@ -0,0 +1,39 @@
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, trace, Resource};
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::registry::LookupSpan;
+
+/// OTLP (gRPC) span exporter layer, `None` if the endpoint is not set
+pub(super) fn layer<S>(
+    endpoint: Option<&str>,
+) -> anyhow::Result<Option<OpenTelemetryLayer<S, trace::Tracer>>>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let endpoint = match endpoint {
+        Some(endpoint) => endpoint,
+        None => return Ok(None),
+    };
+
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(trace::config().with_resource(Resource::new(vec![
+            KeyValue::new("service.name", "ninja"),
+            KeyValue::new("service.version", crate::LIB_VERSION),
+        ])))
+        .install_batch(runtime::Tokio)?;
+
+    Ok(Some(tracing_opentelemetry::layer().with_tracer(tracer)))
+}
+
+/// Flush the remaining spans
+pub(super) fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
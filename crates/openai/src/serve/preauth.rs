@@ -35,43 +35,47 @@ fn collect_preauth_cookie(headers: &HeaderMap<HeaderValue>) {
     }
 }
 
-pub fn log_req(req: &Request<Body>) {
-    let headers = req.headers();
-    let mut header_formated = String::new();
-    for (key, value) in headers {
-        let v = match value.to_str() {
-            Ok(v) => v.to_string(),
-            Err(_) => {
-                format!("[u8]; {}", value.len())
-            }
-        };
-        write!(
-            &mut header_formated,
-            "\t{:<20}{}\r\n",
-            format!("{}:", key.as_str()),
-            v
-        )
-        .unwrap();
-    }
+/// Headers whose values must never be logged
+const SENSITIVE_HEADERS: [&str; 4] = [
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+];
 
+pub fn log_req(req: &Request<Body>) {
     tracing::debug!(
         "{} {}
 Headers:
 {}",
         req.method(),
-        req.uri().to_string(),
-        header_formated
+        req.uri().path(),
+        format_headers(req.headers())
     )
 }
 
 pub fn log_res(res: &Response<Body>) {
-    let headers = res.headers();
+    tracing::debug!(
+        "{} {:?}
+Headers:
+{}",
+        res.status(),
+        res.version(),
+        format_headers(res.headers())
+    )
+}
+
+fn format_headers(headers: &HeaderMap<HeaderValue>) -> String {
     let mut header_formated = String::new();
     for (key, value) in headers {
-        let v = match value.to_str() {
-            Ok(v) => v.to_string(),
-            Err(_) => {
-                format!("[u8]; {}", value.len())
+        let v = if SENSITIVE_HEADERS.contains(&key.as_str()) {
+            format!("[redacted]; {}", value.len())
+        } else {
+            match value.to_str() {
+                Ok(v) => v.to_string(),
+                Err(_) => {
+                    format!("[u8]; {}", value.len())
+                }
             }
         };
         write!(
@@ -82,13 +86,5 @@ pub fn log_res(res: &Response<Body>) {
         )
         .unwrap();
     }
-
-    tracing::debug!(
-        "{} {:?}
-Headers:
-{}",
-        res.status(),
-        res.version(),
-        header_formated
-    )
+    header_formated
 }
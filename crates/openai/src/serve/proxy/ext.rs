@@ -12,7 +12,9 @@ use http::header::CONTENT_TYPE;
 use http::{header, Uri};
 use typed_builder::TypedBuilder;
 
+use crate::serve::conversation::ConversationPermit;
 use crate::serve::error::ResponseError;
+use crate::serve::transcript::Recorder;
 
 /// Context extension.
 #[derive(TypedBuilder)]
@@ -29,6 +31,12 @@ pub struct ResponseExt {
     #[builder(setter(into), default)]
     pub context: Option<Context>,
     pub inner: reqwest::Response,
+    /// Account conversation permit, released when the response is finished
+    #[builder(default)]
+    pub permit: Option<ConversationPermit>,
+    /// Conversation transcript recorder, fed with the upstream response body
+    #[builder(default)]
+    pub transcript: Option<Recorder>,
 }
 
 /// Extractor for request parts.
@@ -4,6 +4,7 @@ pub mod resp;
 mod toapi;
 
 use super::error::ResponseError;
+use super::logging::X_REQUEST_ID;
 use crate::constant::CF_CLEARANCE;
 use crate::constant::PUID;
 use crate::debug;
@@ -35,6 +36,9 @@ pub(crate) fn header_convert(
         headers.insert("Access-Control-Request-Method", h.clone());
     });
 
+    h.get(X_REQUEST_ID)
+        .map(|v| headers.insert(X_REQUEST_ID, v.clone()));
+
     h.get("X-Ms-Blob-Type")
         .map(|v| headers.insert("X-Ms-Blob-Type", v.clone()));
 
@@ -17,8 +17,13 @@ use crate::{arkose, with_context};
 use super::ext::{RequestExt, ResponseExt, SendRequestExt};
 use super::header_convert;
 use super::toapi;
+use crate::serve::conversation::{self, ConversationPermit};
 use crate::serve::error::{ProxyError, ResponseError};
+use crate::serve::logging::{self, X_REQUEST_ID};
 use crate::serve::puid::{get_or_init, reduce_key};
+use crate::serve::transcript::{self, Recorder};
+use crate::serve::usage;
+use tracing::Instrument;
 
 #[async_trait]
 impl SendRequestExt for reqwest::Client {
@@ -43,11 +48,18 @@ impl SendRequestExt for reqwest::Client {
         let url = format!("{origin}{path_and_query}");
 
         // Handle conversation request
-        handle_conv_request(&mut req).await?;
+        let (permit, recorder) = handle_conv_request(&mut req).await?;
 
         // Handle dashboard request
         handle_dashboard_request(&mut req).await?;
 
+        // Upstream request span, query is omitted as it may carry secrets
+        let span = tracing::info_span!(
+            "upstream",
+            method = %req.method,
+            url = %format!("{origin}{}", req.uri.path()),
+        );
+
         // Build request
         let mut builder =
             self.request(req.method, url)
@@ -57,7 +69,11 @@ impl SendRequestExt for reqwest::Client {
         }
 
         // Send request
-        Ok(ResponseExt::builder().inner(builder.send().await?).build())
+        Ok(ResponseExt::builder()
+            .inner(builder.send().instrument(span).await?)
+            .permit(permit)
+            .transcript(recorder)
+            .build())
     }
 }
 
@@ -72,10 +88,12 @@ pub(super) fn has_puid(headers: &HeaderMap) -> Result<bool, ResponseError> {
 }
 
 /// Handle conversation request
-async fn handle_conv_request(req: &mut RequestExt) -> Result<(), ResponseError> {
+async fn handle_conv_request(
+    req: &mut RequestExt,
+) -> Result<(Option<ConversationPermit>, Option<Recorder>), ResponseError> {
     // Only handle POST request
     if !(req.uri.path().eq("/backend-api/conversation") && req.method.eq(&Method::POST)) {
-        return Ok(());
+        return Ok((None, None));
     }
 
     // Handle empty body
@@ -95,6 +113,8 @@ async fn handle_conv_request(req: &mut RequestExt) -> Result<(), ResponseError>
         .get(MODEL)
         .and_then(|m| m.as_str())
         .ok_or(ResponseError::BadRequest(ProxyError::ModelRequired))?;
+    logging::record_model(model);
+    usage::record_model(model);
 
     // extract token from Authorization header
     let token = req
@@ -102,6 +122,23 @@ async fn handle_conv_request(req: &mut RequestExt) -> Result<(), ResponseError>
         .ok_or(ResponseError::Unauthorized(ProxyError::AccessTokenRequired))?
         .to_owned();
 
+    // Limit concurrent conversations per account
+    let permit = conversation::acquire(&token).await?;
+
+    // Record the conversation transcript
+    let recorder = Recorder::new(
+        req.headers
+            .get(X_REQUEST_ID)
+            .and_then(|v| v.to_str().ok())
+            .map(ToOwned::to_owned),
+        reduce_key(&token).ok(),
+        model,
+        body.get("conversation_id")
+            .and_then(Value::as_str)
+            .map(ToOwned::to_owned),
+        transcript::chatgpt_messages(body.get("messages")),
+    );
+
     // If puid is exist, then return
     if !has_puid(&req.headers)? {
         // Exstract the token from the Authorization header
@@ -133,7 +170,7 @@ async fn handle_conv_request(req: &mut RequestExt) -> Result<(), ResponseError>
         };
 
         if condition {
-            let arkose_token = ArkoseToken::new_from_context(
+            let arkose_token = ArkoseToken::new_from_pool(
                 ArkoseContext::builder()
                     .client(with_context!(arkose_client))
                     .typed(model.into())
@@ -151,7 +188,7 @@ async fn handle_conv_request(req: &mut RequestExt) -> Result<(), ResponseError>
 
     drop(json);
 
-    Ok(())
+    Ok((permit, recorder))
 }
 
 /// Handle dashboard request
@@ -175,11 +212,14 @@ async fn handle_dashboard_request(req: &mut RequestExt) -> Result<(), ResponseEr
 
     // If arkose_token is not exist, then add it
     if body.get(ARKOSE_TOKEN).is_none() {
+        let (egress, egress_addr, client) = with_context!(arkose_client_with_egress);
         let arkose_token = arkose::ArkoseToken::new_from_context(
             arkose::ArkoseContext::builder()
-                .client(with_context!(arkose_client))
+                .client(client)
                 .typed(Type::Platform)
                 .identifier(None)
+                .egress(Some(egress.to_owned()))
+                .egress_addr(egress_addr)
                 .build(),
         )
         .await?;
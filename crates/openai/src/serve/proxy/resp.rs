@@ -1,6 +1,7 @@
 use std::time::UNIX_EPOCH;
 
 use crate::constant::{CF_CLEARANCE, NINJA_VERSION, PUID};
+use crate::metrics;
 use crate::with_context;
 use crate::LIB_VERSION;
 use axum::body::Body;
@@ -11,15 +12,36 @@ use axum_extra::extract::cookie;
 use axum_extra::extract::cookie::Cookie;
 use serde_json::Value;
 
+use crate::serve::conversation;
 use crate::serve::error::ResponseError;
+use crate::serve::transcript;
 
 use super::ext::ResponseExt;
 use super::toapi;
 
 /// Response convert
 pub(crate) async fn response_convert(
-    resp: ResponseExt,
+    mut resp: ResponseExt,
 ) -> Result<impl IntoResponse, ResponseError> {
+    // Keep the account conversation permit until the body is sent
+    let permit = resp.permit.take();
+    let resp = convert(resp).await?.into_response();
+
+    // Track active SSE streams
+    let sse = resp
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| v.starts_with(mime::TEXT_EVENT_STREAM.as_ref()))
+        .map(|_| metrics::SseGuard::new());
+
+    if permit.is_none() && sse.is_none() {
+        return Ok(resp);
+    }
+    Ok(conversation::hold(resp, (permit, sse)))
+}
+
+async fn convert(mut resp: ResponseExt) -> Result<impl IntoResponse, ResponseError> {
     // If to api is some, then convert to api response
     if resp.context.is_some() {
         return Ok(toapi::response_convert(resp).await?.into_response());
@@ -95,7 +117,10 @@ pub(crate) async fn response_convert(
     } else {
         // Non-files endpoint handling
         Ok(builder
-            .body(StreamBody::new(resp.inner.bytes_stream()))
+            .body(StreamBody::new(transcript::tee(
+                resp.inner.bytes_stream(),
+                resp.transcript.take(),
+            )))
             .map_err(ResponseError::InternalServerError)?
             .into_response())
     }
@@ -10,6 +10,7 @@ use axum::{
 use eventsource_stream::Eventsource;
 use reqwest::StatusCode;
 use std::str::FromStr;
+use tracing::Instrument;
 
 use crate::arkose::ArkoseContext;
 use crate::chatgpt::model::req::Metadata;
@@ -23,8 +24,12 @@ use crate::{
     arkose::ArkoseToken,
     chatgpt::model::req::{Content, ConversationMode, Messages, PostConvoRequest},
     serve::{
+        conversation,
         error::ResponseError,
+        logging::{self, X_REQUEST_ID},
         puid::{get_or_init, reduce_key},
+        transcript::{self, Recorder},
+        usage,
     },
     with_context,
 };
@@ -67,12 +72,35 @@ pub(super) async fn send_request(req: RequestExt) -> Result<ResponseExt, Respons
     // Exstract the token from the Authorization header
     let cache_id = reduce_key(baerer)?;
 
+    // Limit concurrent conversations per account
+    let permit = conversation::acquire(baerer).await?;
+
     // Exstract the body
     let bytes = req
         .body
         .as_ref()
         .ok_or_else(|| ResponseError::BadRequest(ProxyError::BodyRequired))?;
     let body = serde_json::from_slice::<model::Req>(bytes)?;
+    logging::record_model(&body.model);
+    usage::record_model(&body.model);
+
+    // Record the conversation transcript
+    let recorder = Recorder::new(
+        req.headers
+            .get(X_REQUEST_ID)
+            .and_then(|v| v.to_str().ok())
+            .map(ToOwned::to_owned),
+        Some(cache_id.clone()),
+        &body.model,
+        None,
+        body.messages
+            .iter()
+            .map(|m| transcript::Message {
+                role: m.role.to_string(),
+                content: m.content.clone(),
+            })
+            .collect(),
+    );
 
     // Convert to ChatGPT API Message
     let mut messages = Vec::with_capacity(body.messages.len());
@@ -105,7 +133,7 @@ pub(super) async fn send_request(req: RequestExt) -> Result<ResponseExt, Respons
     // check if arkose token is required
     let arkose_token: Option<String> =
         if (with_context!(arkose_gpt3_experiment) && gpt_model.is_gpt3()) || gpt_model.is_gpt4() {
-            let arkose_token = ArkoseToken::new_from_context(
+            let arkose_token = ArkoseToken::new_from_pool(
                 ArkoseContext::builder()
                     .client(client.clone())
                     .typed(gpt_model.clone().into())
@@ -150,11 +178,18 @@ pub(super) async fn send_request(req: RequestExt) -> Result<ResponseExt, Respons
     let resp = builder
         .json(&req_body)
         .send()
+        .instrument(tracing::info_span!(
+            "upstream",
+            method = "POST",
+            url = %format!("{URL_CHATGPT_API}/backend-api/conversation"),
+        ))
         .await
         .map_err(ResponseError::InternalServerError)?;
 
     Ok(ResponseExt::builder()
         .inner(resp)
+        .permit(permit)
+        .transcript(recorder)
         .context(
             Context::builder()
                 .model(body.model)
@@ -166,8 +201,9 @@ pub(super) async fn send_request(req: RequestExt) -> Result<ResponseExt, Respons
 
 /// Convert response to ChatGPT API
 pub(super) async fn response_convert(
-    resp_ext: ResponseExt,
+    mut resp_ext: ResponseExt,
 ) -> Result<impl IntoResponse, ResponseError> {
+    let recorder = resp_ext.transcript.take();
     match resp_ext.inner.error_for_status() {
         Ok(resp) => {
             // Get config from request context
@@ -176,7 +212,7 @@ pub(super) async fn response_convert(
             ))?;
 
             // Get response body event source
-            let event_source = resp.bytes_stream().eventsource();
+            let event_source = transcript::tee(resp.bytes_stream(), recorder).eventsource();
 
             if config.stream {
                 // Create a  stream response
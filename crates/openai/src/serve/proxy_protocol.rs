@@ -0,0 +1,167 @@
+use axum_server::accept::Accept;
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tower_http::add_extension::AddExtension;
+
+/// PROXY protocol v1 prefix
+const V1_PREFIX: &[u8] = b"PROXY ";
+/// PROXY protocol v1 max header length (including CRLF)
+const V1_MAX_LENGTH: usize = 107;
+/// PROXY protocol v2 signature
+const V2_SIGNATURE: [u8; 12] = [
+    0x0D, 0x0A, 0x0D, 0x0A, 0x00, 0x0D, 0x0A, 0x51, 0x55, 0x49, 0x54, 0x0A,
+];
+/// PROXY header read timeout
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Source address announced by the PROXY protocol header,
+/// `None` for LOCAL/UNKNOWN connections (e.g. load balancer health checks)
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ProxyProtocolAddr(pub Option<SocketAddr>);
+
+/// Acceptor that consumes the PROXY protocol header before handing the stream to hyper
+#[derive(Clone, Copy, Debug, Default)]
+pub(super) struct ProxyProtocolAcceptor;
+
+impl<I, S> Accept<I, S> for ProxyProtocolAcceptor
+where
+    I: AsyncRead + Unpin + Send + 'static,
+    S: Send + 'static,
+{
+    type Stream = I;
+    type Service = AddExtension<S, ProxyProtocolAddr>;
+    type Future = Pin<Box<dyn Future<Output = io::Result<(Self::Stream, Self::Service)>> + Send>>;
+
+    fn accept(&self, mut stream: I, service: S) -> Self::Future {
+        Box::pin(async move {
+            let addr = tokio::time::timeout(READ_TIMEOUT, read_header(&mut stream))
+                .await
+                .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "PROXY header timeout"))??;
+            Ok((stream, AddExtension::new(service, ProxyProtocolAddr(addr))))
+        })
+    }
+}
+
+/// Read exactly the PROXY header from the stream, leaving the payload untouched
+async fn read_header<I: AsyncRead + Unpin>(stream: &mut I) -> io::Result<Option<SocketAddr>> {
+    let mut prefix = [0u8; 6];
+    stream.read_exact(&mut prefix).await?;
+
+    // v1: human readable, terminated by CRLF
+    if prefix == V1_PREFIX {
+        let mut line = prefix.to_vec();
+        while !line.ends_with(b"\r\n") {
+            if line.len() >= V1_MAX_LENGTH {
+                return Err(invalid("PROXY v1 header too long"));
+            }
+            line.push(stream.read_u8().await?);
+        }
+        let line = std::str::from_utf8(&line).map_err(|_| invalid("PROXY v1 header not utf8"))?;
+        return parse_v1(line);
+    }
+
+    // v2: binary, 16 bytes fixed header followed by address block
+    if prefix == V2_SIGNATURE[..6] {
+        let mut header = [0u8; 16];
+        header[..6].copy_from_slice(&prefix);
+        stream.read_exact(&mut header[6..]).await?;
+        if header[..12] != V2_SIGNATURE {
+            return Err(invalid("invalid PROXY v2 signature"));
+        }
+        let len = u16::from_be_bytes([header[14], header[15]]) as usize;
+        let mut payload = vec![0u8; len];
+        stream.read_exact(&mut payload).await?;
+        return parse_v2(&header, &payload);
+    }
+
+    Err(invalid("missing PROXY protocol header"))
+}
+
+/// Example: `PROXY TCP4 192.168.0.1 192.168.0.11 56324 443\r\n`
+fn parse_v1(line: &str) -> io::Result<Option<SocketAddr>> {
+    let parts = line.trim_end().split(' ').collect::<Vec<&str>>();
+    match parts.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", "TCP4" | "TCP6", src, _dst, sport, _dport] => {
+            let ip = src
+                .parse::<IpAddr>()
+                .map_err(|_| invalid("invalid PROXY v1 source address"))?;
+            let port = sport
+                .parse::<u16>()
+                .map_err(|_| invalid("invalid PROXY v1 source port"))?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        _ => Err(invalid("invalid PROXY v1 header")),
+    }
+}
+
+fn parse_v2(header: &[u8; 16], payload: &[u8]) -> io::Result<Option<SocketAddr>> {
+    let version = header[12] >> 4;
+    let command = header[12] & 0x0F;
+    if version != 2 {
+        return Err(invalid("unsupported PROXY protocol version"));
+    }
+
+    // LOCAL command, the connection was established by the proxy itself
+    if command == 0 {
+        return Ok(None);
+    }
+
+    match header[13] >> 4 {
+        // AF_INET
+        1 if payload.len() >= 12 => {
+            let ip = Ipv4Addr::new(payload[0], payload[1], payload[2], payload[3]);
+            let port = u16::from_be_bytes([payload[8], payload[9]]);
+            Ok(Some(SocketAddr::new(IpAddr::V4(ip), port)))
+        }
+        // AF_INET6
+        2 if payload.len() >= 36 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&payload[..16]);
+            let port = u16::from_be_bytes([payload[32], payload[33]]);
+            Ok(Some(SocketAddr::new(
+                IpAddr::V6(Ipv6Addr::from(octets)),
+                port,
+            )))
+        }
+        // AF_UNSPEC / AF_UNIX
+        0 | 3 => Ok(None),
+        _ => Err(invalid("invalid PROXY v2 address block")),
+    }
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_v1() {
+        let addr = parse_v1("PROXY TCP4 192.168.0.1 192.168.0.11 56324 443\r\n").unwrap();
+        assert_eq!(addr, Some("192.168.0.1:56324".parse().unwrap()));
+        let addr = parse_v1("PROXY TCP6 2001:db8::1 2001:db8::2 4000 443\r\n").unwrap();
+        assert_eq!(addr, Some("[2001:db8::1]:4000".parse().unwrap()));
+        assert_eq!(parse_v1("PROXY UNKNOWN\r\n").unwrap(), None);
+        assert!(parse_v1("PROXY TCP4 foo bar 1 2\r\n").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_v2() {
+        let mut data = V2_SIGNATURE.to_vec();
+        data.extend_from_slice(&[0x21, 0x11, 0x00, 0x0C]);
+        data.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2, 0x1F, 0x90, 0x01, 0xBB]);
+        data.extend_from_slice(b"GET / HTTP/1.1\r\n");
+
+        let mut stream = data.as_slice();
+        let addr = read_header(&mut stream).await.unwrap();
+        assert_eq!(addr, Some("10.0.0.1:8080".parse().unwrap()));
+        assert_eq!(stream, b"GET / HTTP/1.1\r\n");
+    }
+}
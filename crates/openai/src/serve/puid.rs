@@ -1,5 +1,5 @@
 use super::error::{ProxyError, ResponseError};
-use crate::{gpt_model::GPTModel, with_context, URL_CHATGPT_API};
+use crate::{gpt_model::GPTModel, metrics, with_context, URL_CHATGPT_API};
 use moka::sync::Cache;
 use std::str::FromStr;
 use tokio::sync::OnceCell;
@@ -23,6 +23,22 @@ async fn cache() -> &'static Cache<String, String> {
         .await
 }
 
+/// List cached puid entries, email -> puid
+pub(super) async fn list() -> Vec<(String, String)> {
+    cache()
+        .await
+        .iter()
+        .map(|(k, v)| (k.as_ref().to_owned(), v))
+        .collect()
+}
+
+/// Clear the puid cache
+pub(super) async fn clear() {
+    let puid_cache = cache().await;
+    puid_cache.invalidate_all();
+    puid_cache.run_pending_tasks();
+}
+
 pub(super) async fn get_or_init(
     token: &str,
     model: &str,
@@ -32,8 +48,10 @@ pub(super) async fn get_or_init(
     let puid_cache = cache().await;
 
     if let Some(p) = puid_cache.get(&cache_id) {
+        metrics::inc_puid_cache(true);
         return Ok(Some(p.clone()));
     }
+    metrics::inc_puid_cache(false);
 
     if GPTModel::from_str(model)?.is_gpt4() {
         let resp = with_context!(api_client)
@@ -0,0 +1,93 @@
+use super::error::{ProxyError, ResponseError};
+use super::proxy_protocol::ProxyProtocolAddr;
+use crate::with_context;
+use axum::async_trait;
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::request::Parts;
+use axum::http::{Extensions, HeaderMap};
+use cidr::IpCidr;
+use std::net::{IpAddr, SocketAddr};
+
+const X_FORWARDED_FOR: &str = "X-Forwarded-For";
+const X_REAL_IP: &str = "X-Real-IP";
+const CF_CONNECTING_IP: &str = "CF-Connecting-IP";
+
+/// Real client address extractor.
+///
+/// The peer address comes from the PROXY protocol header when the listener expects one,
+/// otherwise from the socket. Forwarded headers are only honored when the peer is a trusted proxy.
+pub(crate) struct ClientIp(pub IpAddr);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientIp
+where
+    S: Send + Sync,
+{
+    type Rejection = ResponseError;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        resolve(&parts.extensions, &parts.headers)
+            .map(ClientIp)
+            .map_err(ResponseError::InternalServerError)
+    }
+}
+
+/// Resolve the real client address from request extensions and headers
+pub(crate) fn resolve(extensions: &Extensions, headers: &HeaderMap) -> Result<IpAddr, ProxyError> {
+    let peer = extensions
+        .get::<ProxyProtocolAddr>()
+        .and_then(|addr| addr.0)
+        .or_else(|| {
+            extensions
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|info| info.0)
+        })
+        .map(|addr| addr.ip().to_canonical())
+        .ok_or(ProxyError::ClientAddrNotFound)?;
+
+    let reloadable = with_context!(reloadable);
+    let trusted = reloadable.trusted_proxies();
+
+    if !is_trusted(trusted, &peer) {
+        return Ok(peer);
+    }
+
+    // Walk the forwarded chain from right to left, the first untrusted hop is the client
+    if let Some(ip) = forwarded_for(headers, trusted) {
+        return Ok(ip);
+    }
+
+    Ok(single_header(headers, X_REAL_IP)
+        .or_else(|| single_header(headers, CF_CONNECTING_IP))
+        .unwrap_or(peer))
+}
+
+fn is_trusted(trusted: &[IpCidr], ip: &IpAddr) -> bool {
+    trusted.iter().any(|cidr| cidr.contains(ip))
+}
+
+fn forwarded_for(headers: &HeaderMap, trusted: &[IpCidr]) -> Option<IpAddr> {
+    let chain = headers
+        .get_all(X_FORWARDED_FOR)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|v| v.trim().parse::<IpAddr>().ok())
+        .map(|ip| ip.to_canonical())
+        .collect::<Vec<IpAddr>>();
+
+    chain
+        .iter()
+        .rev()
+        .find(|ip| !is_trusted(trusted, ip))
+        .or_else(|| chain.first())
+        .copied()
+}
+
+fn single_header(headers: &HeaderMap, name: &str) -> Option<IpAddr> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<IpAddr>().ok())
+        .map(|ip| ip.to_canonical())
+}
@@ -0,0 +1,286 @@
+use super::middleware::cidr::watch_dir;
+use super::middleware::tokenbucket::TokenBucketProvider;
+use crate::context::{self, args::Args};
+use crate::{debug, info, warn};
+use hotwatch::{Event, EventKind, Hotwatch};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Collect the names of the fields whose values differ
+macro_rules! changed_fields {
+    ($old:expr, $new:expr; $($field:ident),* $(,)?) => {{
+        let mut fields: Vec<&'static str> = Vec::new();
+        $(
+            if format!("{:?}", $old.$field) != format!("{:?}", $new.$field) {
+                fields.push(stringify!($field));
+            }
+        )*
+        fields
+    }};
+}
+
+/// Copy the fields from one args to another
+macro_rules! copy_fields {
+    ($dst:expr, $src:expr; $($field:ident),* $(,)?) => {{
+        $(
+            $dst.$field = $src.$field.clone();
+        )*
+    }};
+}
+
+/// Expand the macro with the fields applied without restart
+macro_rules! reloadable_fields {
+    ($mac:ident!($($arg:tt)*)) => {
+        $mac!($($arg)*;
+            auth_key,
+            visitor_email_whitelist,
+            trusted_proxies,
+            cf_site_key,
+            cf_secret_key,
+            enable_file_proxy,
+            conv_queue_timeout,
+            arkose_endpoint,
+            arkose_gpt3_experiment,
+            arkose_gpt3_experiment_solver,
+            arkose_random_fingerprint,
+            arkose_solver,
+            arkose_solver_tguess_endpoint,
+            arkose_solver_image_dir,
+            arkose_version_webhook,
+            tb_enable,
+            tb_capacity,
+            tb_fill_rate,
+        )
+    };
+}
+
+pub(super) struct ConfigWatcher {
+    /// Watched directory
+    dir: PathBuf,
+    /// File Hotwatch
+    hotwatch: Hotwatch,
+}
+
+impl Drop for ConfigWatcher {
+    fn drop(&mut self) {
+        if let Some(err) = self.hotwatch.unwatch(self.dir.as_path()).err() {
+            warn!("hotwatch stop error: {err}")
+        }
+    }
+}
+
+/// Watch the configuration file, swap the reloadable settings on change
+pub(super) fn watch(args: &Args, limit: Arc<TokenBucketProvider>) -> Option<ConfigWatcher> {
+    let loader = args.config.clone()?;
+    let dir = watch_dir(loader.path());
+
+    let mut hotwatch = match Hotwatch::new() {
+        Ok(hotwatch) => hotwatch,
+        Err(err) => {
+            warn!("Failed to initialize config file watcher: {err}");
+            return None;
+        }
+    };
+
+    // Settings the server was started with, and the last applied
+    let startup = args.clone();
+    let mut current = args.clone();
+
+    info!("Start watching config file: {}", loader.path().display());
+    let result = hotwatch.watch(&dir, move |event: Event| match event.kind {
+        EventKind::Create(_) | EventKind::Modify(_) => {
+            if !event
+                .paths
+                .iter()
+                .any(|path| path.file_name() == loader.path().file_name())
+            {
+                return;
+            }
+
+            match loader.load() {
+                Ok(args) => {
+                    apply(&startup, &current, &args, &limit);
+                    current = args;
+                }
+                Err(err) => warn!(
+                    "Failed to reload config file: {}: {err}",
+                    loader.path().display()
+                ),
+            }
+        }
+        _ => {}
+    });
+
+    if let Some(err) = result.err() {
+        warn!("Failed to watch config file: {err}");
+        return None;
+    }
+
+    Some(ConfigWatcher { dir, hotwatch })
+}
+
+/// Result of comparing a reloaded config with the running one
+struct Changes {
+    /// Reloadable fields changed since the last reload
+    reloaded: Vec<&'static str>,
+    /// Fields changed since startup that need a restart
+    restart: Vec<&'static str>,
+    /// Startup settings with the reloadable fields of the new config
+    effective: Args,
+}
+
+fn diff(startup: &Args, current: &Args, new: &Args) -> Changes {
+    let reloaded = reloadable_fields!(changed_fields!(current, new));
+
+    #[allow(unused_mut)]
+    let mut restart = changed_fields!(startup, new;
+        bind,
+        log_format,
+        concurrent_limit,
+        conv_limit,
+        transcript_dir,
+        transcript_retention,
+        cookie_store,
+        fastest_dns,
+        tcp_keepalive,
+        no_keepalive,
+        pool_idle_timeout,
+        timeout,
+        connect_timeout,
+        enable_direct,
+        proxies,
+        impersonate_uas,
+        tls_cert,
+        tls_key,
+        metrics_bind,
+        proxy_protocol,
+        allow_cidrs,
+        deny_cidrs,
+        cidr_file,
+        enable_webui,
+        enable_arkose_proxy,
+        arkose_har_dir,
+        arkose_pool_size,
+        arkose_pool_ttl,
+        arkose_pool_types,
+        arkose_public_keys,
+        tb_strategy,
+        tb_expired,
+    );
+
+    #[cfg(feature = "preauth")]
+    restart.extend(changed_fields!(startup, new; pbind, pupstream, pcert, pkey));
+
+    #[cfg(feature = "otel")]
+    restart.extend(changed_fields!(startup, new; otlp_endpoint));
+
+    // Only the reloadable fields take effect, the rest keep the startup values
+    let mut effective = startup.clone();
+    reloadable_fields!(copy_fields!(effective, new));
+
+    Changes {
+        reloaded,
+        restart,
+        effective,
+    }
+}
+
+fn apply(startup: &Args, current: &Args, new: &Args, limit: &TokenBucketProvider) {
+    let Changes {
+        reloaded,
+        restart,
+        effective,
+    } = diff(startup, current, new);
+
+    if reloaded.is_empty() && restart.is_empty() {
+        debug!("Config file unchanged");
+        return;
+    }
+
+    context::reload(&effective);
+    limit.update(new.tb_enable, new.tb_capacity, new.tb_fill_rate);
+
+    if !reloaded.is_empty() {
+        info!("Config reloaded, applied: {}", reloaded.join(", "));
+    }
+
+    if !restart.is_empty() {
+        warn!(
+            "Config changed, restart required to apply: {}",
+            restart.join(", ")
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::arkose;
+
+    #[test]
+    fn test_unchanged() {
+        let args = Args::builder().build();
+        let changes = diff(&args, &args, &args.clone());
+        assert!(changes.reloaded.is_empty());
+        assert!(changes.restart.is_empty());
+    }
+
+    #[test]
+    fn test_changed_fields() {
+        let current = Args::builder().build();
+        let mut new = current.clone();
+        new.auth_key = Some("secret".to_owned());
+        new.conv_queue_timeout = 5;
+        new.tb_capacity = 120;
+
+        let changes = diff(&current, &current, &new);
+        assert_eq!(
+            changes.reloaded,
+            vec!["auth_key", "conv_queue_timeout", "tb_capacity"]
+        );
+        assert!(changes.restart.is_empty());
+    }
+
+    #[test]
+    fn test_restart_required() {
+        let startup = Args::builder().build();
+        let mut new = startup.clone();
+        new.conv_limit = 2;
+        new.bind = Some("127.0.0.1:8000".parse().unwrap());
+        new.arkose_pool_types = vec![arkose::Type::Auth];
+
+        let changes = diff(&startup, &startup, &new);
+        assert!(changes.reloaded.is_empty());
+        assert_eq!(
+            changes.restart,
+            vec!["bind", "conv_limit", "arkose_pool_types"]
+        );
+    }
+
+    #[test]
+    fn test_restart_compared_with_startup() {
+        // A restart field changed by an earlier reload is reported again,
+        // a reloadable field is only reported when it changes
+        let startup = Args::builder().build();
+        let mut current = startup.clone();
+        current.conv_limit = 2;
+        current.auth_key = Some("secret".to_owned());
+        let new = current.clone();
+
+        let changes = diff(&startup, &current, &new);
+        assert!(changes.reloaded.is_empty());
+        assert_eq!(changes.restart, vec!["conv_limit"]);
+    }
+
+    #[test]
+    fn test_effective_args() {
+        let startup = Args::builder().build();
+        let mut new = startup.clone();
+        new.auth_key = Some("secret".to_owned());
+        new.conv_limit = 2;
+
+        let effective = diff(&startup, &startup, &new).effective;
+        assert_eq!(effective.auth_key.as_deref(), Some("secret"));
+        assert_eq!(effective.conv_limit, startup.conv_limit);
+    }
+}
@@ -4,7 +4,6 @@ mod session;
 
 use axum::body;
 use axum::body::Body;
-use axum::extract::ConnectInfo;
 use axum::extract::Path;
 use axum::extract::Query;
 use axum::headers::authorization::Bearer;
@@ -16,6 +15,7 @@ use axum::http::Response;
 use axum::http::StatusCode;
 use axum::middleware;
 use axum::response::IntoResponse;
+use axum::response::Redirect;
 use axum::routing::any;
 use axum::routing::{get, post};
 use axum::Json;
@@ -28,7 +28,6 @@ use axum_csrf::Key;
 use axum_extra::extract::CookieJar;
 use serde_json::{json, Value};
 use std::collections::HashMap;
-use std::net::SocketAddr;
 use std::sync::OnceLock;
 use tower::ServiceBuilder;
 use tower_http::ServiceBuilderExt;
@@ -47,7 +46,9 @@ use crate::serve::error::ProxyError;
 use crate::serve::error::ResponseError;
 use crate::serve::middleware::csrf;
 use crate::serve::proxy::header_convert;
+use crate::serve::realip::ClientIp;
 use crate::serve::turnstile;
+use crate::serve::usage;
 use crate::serve::whitelist;
 use crate::with_context;
 use crate::{
@@ -57,6 +58,7 @@ use crate::{
 };
 
 use super::get_static_resource;
+use super::har;
 use session::session::Session;
 use session::SessionExt;
 
@@ -72,6 +74,9 @@ const TEMP_CHAT: &str = "chat.htm";
 const TEMP_DETAIL: &str = "detail.htm";
 const TEMP_LOGIN: &str = "login.htm";
 const TEMP_SHARE: &str = "share.htm";
+const TEMP_USAGE: &str = "usage.htm";
+const USAGE_DAYS: u32 = 14;
+const USAGE_MAX_DAYS: u32 = 90;
 
 static TEMPLATE: OnceLock<tera::Tera> = OnceLock::new();
 
@@ -87,7 +92,7 @@ pub(super) fn config(router: Router, args: &Args) -> Router {
 
     // Configure arkose routing
     let router =     // If the auth key is empty, then the auth page is not required
-    if with_context!(auth_key).is_some() {
+    if with_context!(reloadable).auth_key().is_some() {
         router
     } else {
         router.route("/auth", get(auth))
@@ -109,6 +114,7 @@ pub(super) fn config(router: Router, args: &Args) -> Router {
         .route("/auth/logout", get(logout))
         .route("/auth/session", get(session))
         .route("/auth/me", get(auth_me))
+        .route("/admin/usage", get(usage))
         .route("/", get(chat))
         .route("/c", get(chat))
         .route("/c/:conversation_id", get(chat))
@@ -167,7 +173,7 @@ async fn login_index(token: CsrfToken) -> Result<impl IntoResponse, ResponseErro
 
 /// Login from username and password
 async fn login(
-    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    ClientIp(addr): ClientIp,
     token: CsrfToken,
     account: axum::Form<AuthAccount>,
 ) -> Result<impl IntoResponse, ResponseError> {
@@ -189,11 +195,10 @@ async fn login(
     };
 
     // Check if the request is in the turnstile
-    if let Some(err) =
-        turnstile::cf_turnstile_check(addr.ip(), account.cf_turnstile_response.as_deref())
-            .await
-            .map_err(|err| err_handler(err.to_string()))
-            .err()
+    if let Some(err) = turnstile::cf_turnstile_check(addr, account.cf_turnstile_response.as_deref())
+        .await
+        .map_err(|err| err_handler(err.to_string()))
+        .err()
     {
         return Ok(err.into_response());
     };
@@ -297,6 +302,35 @@ async fn login_token(
     Ok(response)
 }
 
+#[derive(serde::Deserialize)]
+struct UsageQuery {
+    days: Option<u32>,
+}
+
+/// Usage statistics page, only for the admin logged in with the auth key
+async fn usage(
+    jar: CookieJar,
+    query: Option<Query<UsageQuery>>,
+) -> Result<Response<Body>, ResponseError> {
+    if with_context!(reloadable).auth_key().is_none() {
+        return Err(ResponseError::Forbidden(ProxyError::AuthKeyRequired));
+    }
+
+    if !har::check_session(jar).await {
+        return Ok(Redirect::temporary(har::LOGIN_PATH).into_response());
+    }
+
+    let days = query
+        .and_then(|q| q.days)
+        .unwrap_or(USAGE_DAYS)
+        .clamp(1, USAGE_MAX_DAYS);
+    let summary = usage::summary(days).map_err(ResponseError::InternalServerError)?;
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("usage", &summary);
+    render_template(TEMP_USAGE, &ctx)
+}
+
 /// Logout, will remove cookie
 async fn logout() -> Result<Response<Body>, ResponseError> {
     // Clear session
@@ -612,6 +646,7 @@ fn render_template(name: &str, context: &tera::Context) -> Result<Response<Body>
             tera.add_raw_templates(vec![
                 (TEMP_AUTH, include_str!("../../../../frontend/auth.htm")),
                 (TEMP_LOGIN, include_str!("../../../../frontend/login.htm")),
+                (TEMP_USAGE, include_str!("../../../../frontend/usage.htm")),
             ])
             .expect("The static template failed to load");
             tera
@@ -629,14 +664,15 @@ fn render_template(name: &str, context: &tera::Context) -> Result<Response<Body>
 /// Settings html template data
 fn settings_template_data(ctx: &mut tera::Context) {
     let context = with_context!();
+    let reloadable = context.reloadable();
 
     // If auth key is not empty, well close the auth page
-    context.auth_key().map(|_| {
+    reloadable.auth_key().map(|_| {
         ctx.insert(AUTH_KEY, EMPTY);
     });
 
     // If the turnstile is not empty, well enable the turnstile captcha
-    context.cf_turnstile().map(|site_key| {
+    reloadable.cf_turnstile().map(|site_key| {
         ctx.insert(SITE_KEY, &site_key.site_key);
     });
 
@@ -646,7 +682,7 @@ fn settings_template_data(ctx: &mut tera::Context) {
     });
 
     // If the arkose endpoint is not empty, well enable the arkose captcha
-    context
+    reloadable
         .arkose_endpoint()
         .map(|arkose_endpoint| ctx.insert(ARKOSE_ENDPOINT, arkose_endpoint));
 }
@@ -15,7 +15,7 @@ use std::str::FromStr;
 const COOKIE_NAME: &'static str = "har_token";
 const FIELD_FILE: &'static str = "files";
 
-const LOGIN_PATH: &'static str = "/har/login";
+pub(super) const LOGIN_PATH: &'static str = "/har/login";
 const UPLOAD_PATH: &'static str = "/har/upload";
 
 const LOGIN_PAGE: &'static str = include_str!("../../../../frontend/har/login.html");
@@ -35,9 +35,25 @@ pub(super) fn config(router: Router, _: &Args) -> Router {
         .route("/har/rename", post(rename_file))
 }
 
+/// Escape text inserted into the HTML pages, messages may carry user supplied file names
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
 fn error_html(title: &str, error_message: &str, back: bool) -> Html<String> {
     let mut error = ERROR_PAGE
-        .replace("{{.error}}", error_message)
+        .replace("{{.error}}", &escape_html(error_message))
         .replace("{{.title}}", title);
     if !back {
         error = error.replace("window.history.back()", "window.location.reload()")
@@ -53,8 +69,8 @@ fn success_html(title: &str, success_message: &str) -> Html<String> {
 }
 
 /// Check session
-async fn check_session(jar: CookieJar) -> bool {
-    if with_context!(auth_key).is_none() {
+pub(super) async fn check_session(jar: CookieJar) -> bool {
+    if with_context!(reloadable).auth_key().is_none() {
         return true;
     }
     if let Some(cookie) = jar.get(COOKIE_NAME) {
@@ -99,7 +115,7 @@ struct AuthenticateKey {
 async fn post_login(
     password: Option<Form<AuthenticateKey>>,
 ) -> Result<impl IntoResponse, ResponseError> {
-    if let Some(upload_key) = with_context!(auth_key) {
+    if let Some(upload_key) = with_context!(reloadable).auth_key() {
         if password.as_ref().map(|p| p.0.password.as_ref()) == Some(upload_key) {
             return Ok(generate_success_response().await.into_response());
         }
@@ -153,14 +169,12 @@ async fn post_upload(
             .await
             .map_err(ResponseError::InternalServerError)?;
 
-        if let Some(err) = har::valid(&data).err() {
+        if let Some(err) = har::valid(&_type.0 .0, &data).err() {
             warn!("upload har file check error: {}", err);
-            return Ok(error_html(
-                FAILED_UPLOAD_TITLE,
-                "The content and format of the Har file do not meet the requirements",
-                false,
-            )
-            .into_response());
+            return Ok(
+                error_html(FAILED_UPLOAD_TITLE, &format!("{filename}: {err}"), false)
+                    .into_response(),
+            );
         }
 
         har::write_file(&_type.0 .0, &filename, data)
@@ -190,12 +204,69 @@ async fn get_files(
 
     let mut files = Vec::new();
     while let Ok(Some(entry)) = dirs.next_entry().await {
-        files.push(entry.file_name().to_string_lossy().to_string())
+        // Skip the quarantine directory
+        if entry.path().is_dir() {
+            continue;
+        }
+        let filename = entry.file_name().to_string_lossy().to_string();
+        // Parsing a large HAR file blocks, keep it off the async workers
+        let inspect = {
+            let (typed, filename) = (_type.0 .0, filename.clone());
+            tokio::task::spawn_blocking(move || har::inspect_file(&typed, &filename))
+                .await
+                .map_err(ResponseError::InternalServerError)?
+        };
+        let file = match inspect {
+            Ok(info) => HarFile {
+                filename,
+                info: Some(info),
+                error: None,
+                quarantine: None,
+            },
+            Err(err) => HarFile {
+                filename,
+                info: None,
+                error: Some(err.to_string()),
+                quarantine: None,
+            },
+        };
+        files.push(file)
+    }
+
+    // Quarantined files, renaming out of the directory restores them
+    if let Ok(mut dirs) = har::read_quarantine_dir(&_type.0 .0).await {
+        while let Ok(Some(entry)) = dirs.next_entry().await {
+            let filename = entry.file_name().to_string_lossy().to_string();
+            let quarantine = har::quarantine_event(&_type.0 .0, &filename);
+            let error = match quarantine.as_ref() {
+                Some(event) => format!(
+                    "Quarantined at {} after {} consecutive failed tokens",
+                    event.time, event.failures
+                ),
+                None => "Quarantined".to_owned(),
+            };
+            files.push(HarFile {
+                filename: format!("{}/{filename}", har::QUARANTINE_DIR),
+                info: None,
+                error: Some(error),
+                quarantine,
+            })
+        }
     }
 
     Ok(Json(files).into_response())
 }
 
+/// HAR file of the list, invalid or quarantined files carry the reason
+#[derive(serde::Serialize)]
+struct HarFile {
+    filename: String,
+    #[serde(flatten)]
+    info: Option<har::HarInfo>,
+    error: Option<String>,
+    quarantine: Option<har::QuarantineEvent>,
+}
+
 #[derive(serde::Deserialize)]
 struct Filename {
     filename: String,
@@ -298,3 +369,22 @@ impl Header for PlatformType {
         values.extend(std::iter::once(value));
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_error_html_escapes_message() {
+        let html = error_html(
+            FAILED_UPLOAD_TITLE,
+            r#"<img src=x onerror="alert('x')">.har: Invalid HAR file"#,
+            false,
+        )
+        .0;
+        assert!(!html.contains("<img"));
+        assert!(html.contains(
+            "&lt;img src=x onerror=&quot;alert(&#x27;x&#x27;)&quot;&gt;.har: Invalid HAR file"
+        ));
+    }
+}
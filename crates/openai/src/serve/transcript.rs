@@ -0,0 +1,328 @@
+use crate::uuid::uuid;
+use crate::{info, metrics, now_duration, warn};
+use bytes::Bytes;
+use futures_core::Stream;
+use serde::Serialize;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc::{self, error::TrySendError, Receiver, Sender};
+use tokio_stream::StreamExt;
+
+const FILE_PREFIX: &str = "transcript-";
+const FILE_EXTENSION: &str = "jsonl";
+/// Records waiting for the writer, newer records are dropped when it falls behind
+const QUEUE_CAPACITY: usize = 1024;
+
+static SENDER: OnceLock<Sender<Transcript>> = OnceLock::new();
+
+#[derive(Serialize, Debug)]
+pub(crate) struct Message {
+    pub role: String,
+    pub content: String,
+}
+
+#[derive(Serialize, Debug, Default)]
+struct Transcript {
+    id: String,
+    request_id: Option<String>,
+    identity: Option<String>,
+    model: String,
+    conversation_id: Option<String>,
+    /// Request time (unix millis)
+    created_at: u64,
+    /// Response finished time (unix millis)
+    finished_at: u64,
+    messages: Vec<Message>,
+    answer: String,
+}
+
+/// Init the transcript writer, records are appended to daily rotated JSONL files
+pub(super) fn init(dir: Option<PathBuf>, retention: u64) {
+    let dir = match dir {
+        Some(dir) => dir,
+        None => return,
+    };
+
+    if let Some(err) = std::fs::create_dir_all(&dir).err() {
+        warn!(
+            "Failed to create transcript directory: {}: {err}",
+            dir.display()
+        );
+        return;
+    }
+
+    let (tx, rx) = mpsc::channel(QUEUE_CAPACITY);
+    if SENDER.set(tx).is_ok() {
+        info!("Transcript recording to: {}", dir.display());
+        tokio::spawn(write_loop(dir, retention, rx));
+    }
+}
+
+/// Whether transcript recording is enabled
+pub(crate) fn enabled() -> bool {
+    SENDER.get().is_some()
+}
+
+/// Conversation recorder, collects the assistant answer from the upstream SSE stream
+/// and writes the record when dropped
+pub(crate) struct Recorder {
+    record: Transcript,
+    buf: Vec<u8>,
+    /// Upstream has responded, requests failed before are not recorded
+    responded: bool,
+}
+
+impl Recorder {
+    /// Create a recorder, `None` if transcript recording is disabled
+    pub(crate) fn new(
+        request_id: Option<String>,
+        identity: Option<String>,
+        model: &str,
+        conversation_id: Option<String>,
+        messages: Vec<Message>,
+    ) -> Option<Self> {
+        if !enabled() {
+            return None;
+        }
+
+        Some(Recorder {
+            record: Transcript {
+                id: uuid(),
+                request_id,
+                identity,
+                model: model.to_owned(),
+                conversation_id,
+                created_at: now_millis(),
+                finished_at: 0,
+                messages,
+                answer: String::new(),
+            },
+            buf: Vec::new(),
+            responded: false,
+        })
+    }
+
+    /// Feed a chunk of the upstream SSE stream
+    pub(crate) fn feed(&mut self, chunk: &[u8]) {
+        self.responded = true;
+        self.buf.extend_from_slice(chunk);
+        while let Some(pos) = self.buf.iter().position(|b| *b == b'\n') {
+            let line = self.buf.drain(..=pos).collect::<Vec<u8>>();
+            let line = String::from_utf8_lossy(&line);
+            if let Some(data) = line.trim_end().strip_prefix("data:") {
+                self.on_event(data.trim_start());
+            }
+        }
+    }
+
+    fn on_event(&mut self, data: &str) {
+        if data.is_empty() || data.eq("[DONE]") {
+            return;
+        }
+
+        let event = match serde_json::from_str::<Value>(data) {
+            Ok(event) => event,
+            Err(_) => return,
+        };
+
+        if self.record.conversation_id.is_none() {
+            self.record.conversation_id = event
+                .get("conversation_id")
+                .and_then(Value::as_str)
+                .map(ToOwned::to_owned);
+        }
+
+        let message = match event.get("message") {
+            Some(message) => message,
+            None => return,
+        };
+
+        let is_assistant = message
+            .pointer("/author/role")
+            .and_then(Value::as_str)
+            .map_or(false, |role| role.eq("assistant"));
+
+        // Every event carries the whole message assembled so far
+        if is_assistant {
+            if let Some(text) = join_parts(message.pointer("/content/parts")) {
+                self.record.answer = text;
+            }
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if !self.responded {
+            return;
+        }
+
+        if let Some(tx) = SENDER.get() {
+            let mut record = std::mem::take(&mut self.record);
+            record.finished_at = now_millis();
+            send(tx, record);
+        }
+    }
+}
+
+/// Queue the record for the writer, drop it if the queue is full
+fn send(tx: &Sender<Transcript>, record: Transcript) {
+    if let Err(TrySendError::Full(record)) = tx.try_send(record) {
+        metrics::inc_transcript_dropped();
+        warn!(
+            "Transcript queue is full, dropped transcript: {}",
+            record.id
+        );
+    }
+}
+
+/// Pass the response body stream through, feeding each chunk to the recorder
+pub(crate) fn tee<S, E>(
+    stream: S,
+    mut recorder: Option<Recorder>,
+) -> impl Stream<Item = Result<Bytes, E>>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    stream.map(move |chunk| {
+        if let (Some(recorder), Ok(bytes)) = (recorder.as_mut(), &chunk) {
+            recorder.feed(bytes);
+        }
+        chunk
+    })
+}
+
+/// Convert ChatGPT API request messages to transcript messages
+pub(crate) fn chatgpt_messages(messages: Option<&Value>) -> Vec<Message> {
+    messages
+        .and_then(Value::as_array)
+        .map(|messages| {
+            messages
+                .iter()
+                .filter_map(|message| {
+                    Some(Message {
+                        role: message.pointer("/author/role")?.as_str()?.to_owned(),
+                        content: join_parts(message.pointer("/content/parts"))?,
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Join the text parts of a message content
+fn join_parts(parts: Option<&Value>) -> Option<String> {
+    let parts = parts?.as_array()?;
+    Some(
+        parts
+            .iter()
+            .filter_map(Value::as_str)
+            .collect::<Vec<&str>>()
+            .join("\n"),
+    )
+}
+
+fn now_millis() -> u64 {
+    now_duration()
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+async fn write_loop(dir: PathBuf, retention: u64, mut rx: Receiver<Transcript>) {
+    let mut current_date = None;
+    while let Some(record) = rx.recv().await {
+        let date = time::OffsetDateTime::now_utc().date();
+
+        // Rotate daily, remove the expired files
+        if current_date.ne(&Some(date)) {
+            current_date = Some(date);
+            if retention > 0 {
+                remove_expired(&dir, retention).await;
+            }
+        }
+
+        let path = dir.join(format!("{FILE_PREFIX}{date}.{FILE_EXTENSION}"));
+        if let Some(err) = append(&path, &record).await.err() {
+            warn!("Failed to write transcript: {}: {err}", path.display());
+        }
+    }
+}
+
+async fn append(path: &Path, record: &Transcript) -> anyhow::Result<()> {
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(&line).await?;
+    Ok(())
+}
+
+async fn remove_expired(dir: &Path, retention: u64) {
+    let expired = match SystemTime::now().checked_sub(Duration::from_secs(retention * 86400)) {
+        Some(expired) => expired,
+        None => return,
+    };
+
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(err) => {
+            warn!(
+                "Failed to read transcript directory: {}: {err}",
+                dir.display()
+            );
+            return;
+        }
+    };
+
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+        let is_transcript = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map_or(false, |name| {
+                name.starts_with(FILE_PREFIX) && name.ends_with(FILE_EXTENSION)
+            });
+        if !is_transcript {
+            continue;
+        }
+
+        let modified = match entry.metadata().await.and_then(|m| m.modified()) {
+            Ok(modified) => modified,
+            Err(_) => continue,
+        };
+
+        if modified < expired {
+            info!("Remove expired transcript: {}", path.display());
+            if let Some(err) = tokio::fs::remove_file(&path).await.err() {
+                warn!("Failed to remove transcript: {}: {err}", path.display());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_send_drops_when_full() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let record = |id: &str| Transcript {
+            id: id.to_owned(),
+            ..Default::default()
+        };
+
+        send(&tx, record("first"));
+        send(&tx, record("second"));
+
+        assert_eq!(rx.try_recv().unwrap().id, "first");
+        assert!(rx.try_recv().is_err());
+    }
+}
@@ -14,8 +14,9 @@ pub(super) async fn cf_turnstile_check(
     }
 
     let ctx = with_context!();
+    let reloadable = ctx.reloadable();
 
-    if let Some(turnsile) = ctx.cf_turnstile() {
+    if let Some(turnsile) = reloadable.cf_turnstile() {
         let response = cf_response
             .filter(|r| !r.is_empty())
             .ok_or_else(|| ProxyError::CfMissingCaptcha)?;
@@ -0,0 +1,247 @@
+use super::puid::reduce_key;
+use crate::context::database;
+use crate::warn;
+use axum::headers::authorization::Bearer;
+use axum::headers::Authorization;
+use axum::http::Request;
+use axum::middleware::Next;
+use axum::response::Response;
+use axum::TypedHeader;
+use native_db::*;
+use native_model::{native_model, Model};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::sync::OnceLock;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+const UNKNOWN: &str = "unknown";
+
+static DATABASE_BUILDER: OnceLock<DatabaseBuilder> = OnceLock::new();
+static DATABASE: OnceLock<Database<'static>> = OnceLock::new();
+static SENDER: OnceLock<UnboundedSender<Usage>> = OnceLock::new();
+
+tokio::task_local! {
+    /// Model of the request being handled, set by the proxy handlers
+    static MODEL: RefCell<Option<String>>;
+}
+
+/// Request count of a user, model and status in a day
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[native_model(id = 2, version = 1)]
+#[native_db]
+struct UsageCount {
+    /// `{day}|{email}|{model}|{status}`
+    #[primary_key]
+    key: String,
+    day: String,
+    email: String,
+    model: String,
+    status: u16,
+    count: u64,
+}
+
+struct Usage {
+    email: String,
+    model: String,
+    status: u16,
+}
+
+/// Init the usage statistics store, requests are counted in the background
+pub(super) fn init() {
+    let db = match database::open(
+        &DATABASE_BUILDER,
+        |builder| builder.define::<UsageCount>().expect("define table failed"),
+        "usage.db",
+    ) {
+        Some(db) => db,
+        None => return,
+    };
+
+    if DATABASE.set(db).is_err() {
+        return;
+    }
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    if SENDER.set(tx).is_ok() {
+        std::thread::spawn(move || write_loop(rx));
+    }
+}
+
+/// Record the model of the request being handled
+pub(crate) fn record_model(model: &str) {
+    let _ = MODEL.try_with(|m| *m.borrow_mut() = Some(model.to_owned()));
+}
+
+/// Count the api requests by user email, model and response status
+pub(crate) async fn usage_middleware<B>(
+    bearer: Option<TypedHeader<Authorization<Bearer>>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let tx = match SENDER.get() {
+        Some(tx) => tx,
+        None => return next.run(request).await,
+    };
+
+    let email = bearer
+        .and_then(|TypedHeader(Authorization(bearer))| reduce_key(bearer.token()).ok())
+        .unwrap_or_else(|| UNKNOWN.to_owned());
+
+    let (resp, model) = MODEL
+        .scope(RefCell::new(None), async move {
+            let resp = next.run(request).await;
+            let model = MODEL.with(|m| m.borrow_mut().take());
+            (resp, model)
+        })
+        .await;
+
+    let _ = tx.send(Usage {
+        email,
+        model: model.unwrap_or_else(|| UNKNOWN.to_owned()),
+        status: resp.status().as_u16(),
+    });
+
+    resp
+}
+
+fn write_loop(mut rx: UnboundedReceiver<Usage>) {
+    while let Some(usage) = rx.blocking_recv() {
+        if let Some(db) = DATABASE.get() {
+            if let Some(err) = increment(db, usage).err() {
+                warn!("Failed to update usage statistics: {err}");
+            }
+        }
+    }
+}
+
+fn increment(db: &Database<'static>, usage: Usage) -> anyhow::Result<()> {
+    let day = time::OffsetDateTime::now_utc().date().to_string();
+    let key = format!("{day}|{}|{}|{}", usage.email, usage.model, usage.status);
+
+    let rw = db.rw_transaction()?;
+    let mut count: UsageCount = match rw.get().primary(key.clone())? {
+        Some(count) => count,
+        None => UsageCount {
+            key,
+            day,
+            email: usage.email,
+            model: usage.model,
+            status: usage.status,
+            count: 0,
+        },
+    };
+    count.count += 1;
+    rw.insert(count)?;
+    rw.commit()?;
+    Ok(())
+}
+
+#[cfg(feature = "template")]
+pub(super) use self::summary::*;
+
+#[cfg(feature = "template")]
+mod summary {
+    use super::{UsageCount, DATABASE};
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+
+    /// Request count of a day, `height` is the percentage of the series maximum
+    #[derive(Serialize)]
+    pub struct Bar {
+        day: String,
+        count: u64,
+        height: u64,
+    }
+
+    #[derive(Serialize)]
+    pub struct Series {
+        name: String,
+        total: u64,
+        bars: Vec<Bar>,
+    }
+
+    #[derive(Serialize)]
+    pub struct Summary {
+        enabled: bool,
+        days: Vec<String>,
+        total: Series,
+        users: Vec<Series>,
+        models: Vec<Series>,
+        statuses: Vec<Series>,
+    }
+
+    /// Aggregate the request counts of the latest days
+    pub fn summary(days: u32) -> anyhow::Result<Summary> {
+        let today = time::OffsetDateTime::now_utc().date();
+        let days = (0..days.max(1))
+            .rev()
+            .map(|i| (today - time::Duration::days(i as i64)).to_string())
+            .collect::<Vec<String>>();
+
+        let mut total = BTreeMap::new();
+        let mut users = BTreeMap::new();
+        let mut models = BTreeMap::new();
+        let mut statuses = BTreeMap::new();
+
+        if let Some(db) = DATABASE.get() {
+            let r = db.r_transaction()?;
+            for count in r.scan().primary::<UsageCount>()?.all() {
+                if !days.contains(&count.day) {
+                    continue;
+                }
+                add(&mut total, "total".to_owned(), &count);
+                add(&mut users, count.email.clone(), &count);
+                add(&mut models, count.model.clone(), &count);
+                add(&mut statuses, count.status.to_string(), &count);
+            }
+        }
+
+        Ok(Summary {
+            enabled: DATABASE.get().is_some(),
+            total: series(total, &days)
+                .pop()
+                .unwrap_or_else(|| to_series("total".to_owned(), BTreeMap::new(), &days)),
+            users: series(users, &days),
+            models: series(models, &days),
+            statuses: series(statuses, &days),
+            days,
+        })
+    }
+
+    fn add(map: &mut BTreeMap<String, BTreeMap<String, u64>>, name: String, count: &UsageCount) {
+        *map.entry(name)
+            .or_default()
+            .entry(count.day.clone())
+            .or_default() += count.count;
+    }
+
+    /// Sort the series by total count, descending
+    fn series(map: BTreeMap<String, BTreeMap<String, u64>>, days: &[String]) -> Vec<Series> {
+        let mut series = map
+            .into_iter()
+            .map(|(name, counts)| to_series(name, counts, days))
+            .collect::<Vec<Series>>();
+        series.sort_by(|a, b| b.total.cmp(&a.total));
+        series
+    }
+
+    fn to_series(name: String, counts: BTreeMap<String, u64>, days: &[String]) -> Series {
+        let max = counts.values().copied().max().unwrap_or_default().max(1);
+        let bars = days
+            .iter()
+            .map(|day| {
+                let count = counts.get(day).copied().unwrap_or_default();
+                Bar {
+                    day: day.clone(),
+                    count,
+                    height: count * 100 / max,
+                }
+            })
+            .collect::<Vec<Bar>>();
+        Series {
+            name,
+            total: counts.values().sum(),
+            bars,
+        }
+    }
+}
@@ -1,6 +1,15 @@
 use crate::parse;
 use clap::{Args, Subcommand};
-use openai::{arkose::funcaptcha::solver::Solver, proxy};
+use openai::{
+    arkose::{
+        funcaptcha::{
+            dataset::Format,
+            solver::{GenericSolverConfig, Solver},
+        },
+        registry::PublicKey,
+    },
+    proxy,
+};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
@@ -19,7 +28,7 @@ pub mod cmd {
 
 #[cfg(all(feature = "serve", feature = "terminal"))]
 pub mod cmd {
-    use super::{ServeSubcommand, Subcommand};
+    use super::{ArkoseSubcommand, ServeSubcommand, Subcommand};
     use clap::Parser;
     #[derive(Parser)]
     #[clap(author, version, about, arg_required_else_help = true)]
@@ -34,6 +43,9 @@ pub mod cmd {
         /// Start the http server
         #[clap(subcommand)]
         Serve(ServeSubcommand),
+        /// Arkose utilities
+        #[clap(subcommand)]
+        Arkose(ArkoseSubcommand),
         /// Terminal interaction
         Terminal,
     }
@@ -68,17 +80,56 @@ pub enum ServeSubcommand {
         #[clap(short, long, group = "gt")]
         out: Option<PathBuf>,
     },
+    /// Arkose utilities
+    #[cfg(not(feature = "terminal"))]
+    #[clap(subcommand)]
+    Arkose(ArkoseSubcommand),
     /// Update the application
     Update,
 }
 
+#[derive(Subcommand)]
+pub enum ArkoseSubcommand {
+    /// Funcaptcha image dataset
+    #[clap(subcommand)]
+    Dataset(DatasetSubcommand),
+}
+
+#[derive(Subcommand)]
+pub enum DatasetSubcommand {
+    /// Export the stored funcaptcha images and labels as a dataset
+    Export {
+        /// Funcaptcha image store directory (--arkose-solver-image-dir)
+        #[clap(short, long, value_parser = parse::parse_dir_path)]
+        dir: PathBuf,
+        /// Dataset output directory
+        #[clap(short, long)]
+        out: PathBuf,
+        /// Label file format (csv/jsonl)
+        #[clap(short, long, default_value = "jsonl")]
+        format: Format,
+        /// Only export images of accepted challenges
+        #[clap(long)]
+        accepted_only: bool,
+    },
+}
+
 #[derive(Args, Debug, Default, Serialize, Deserialize)]
 pub struct ServeArgs {
     /// Log level (info/debug/warn/trace/error)
     #[clap(short = 'L', long, global = true, env = "LOG", default_value = "info")]
     pub(super) level: String,
 
-    /// Configuration file path (toml format file)
+    /// Log format (text/json)
+    #[clap(long, global = true, env = "LOG_FORMAT", default_value = "text", value_parser = ["text", "json"])]
+    pub(super) log_format: String,
+
+    /// OTLP (gRPC) collector endpoint for trace export, e.g. http://localhost:4317
+    #[clap(long, env = "OTLP_ENDPOINT", value_parser = parse::parse_url)]
+    #[cfg(feature = "otel")]
+    pub(super) otlp_endpoint: Option<String>,
+
+    /// Configuration file path (toml format file), watched and hot reloaded on change
     #[clap(short = 'C', long, env = "CONFIG", value_parser = parse::parse_file_path)]
     pub(super) config: Option<PathBuf>,
 
@@ -90,6 +141,22 @@ pub struct ServeArgs {
     #[clap(long, default_value = "1024")]
     pub(super) concurrent_limit: usize,
 
+    /// Per account concurrent conversation limit, 0 means unlimited
+    #[clap(long, env = "CONV_LIMIT", default_value = "0")]
+    pub(super) conv_limit: usize,
+
+    /// Per account conversation queue timeout (seconds)
+    #[clap(long, env = "CONV_QUEUE_TIMEOUT", default_value = "30")]
+    pub(super) conv_queue_timeout: u64,
+
+    /// Conversation transcript directory, disabled if not set
+    #[clap(long, env = "TRANSCRIPT_DIR")]
+    pub(super) transcript_dir: Option<PathBuf>,
+
+    /// Conversation transcript retention (days), 0 means keep forever
+    #[clap(long, env = "TRANSCRIPT_RETENTION", default_value = "30")]
+    pub(super) transcript_retention: u64,
+
     /// Server/Client timeout (seconds)
     #[clap(long, default_value = "360")]
     pub(super) timeout: usize,
@@ -169,6 +236,32 @@ pub struct ServeArgs {
     #[clap(short = 'W', long, env = "VISITOR_EMAIL_WHITELIST", value_parser = parse::parse_email_whitelist)]
     pub(super) visitor_email_whitelist: Option<std::vec::Vec<String>>,
 
+    /// Metrics server bind address, e.g. 127.0.0.1:9090
+    /// If not set, /metrics is served on the main server and protected by the auth key
+    #[clap(long, env = "METRICS_BIND", value_parser = parse::parse_socket_addr, verbatim_doc_comment)]
+    pub(super) metrics_bind: Option<std::net::SocketAddr>,
+
+    /// Trusted reverse proxies CIDR, use ',' to separate, e.g. 127.0.0.1/32,10.0.0.0/8
+    #[clap(long, env = "TRUSTED_PROXIES", value_parser = parse::parse_cidrs)]
+    pub(super) trusted_proxies: Option<std::vec::Vec<cidr::IpCidr>>,
+
+    /// Enable PROXY protocol (v1/v2) on the server listener
+    #[clap(long, env = "PROXY_PROTOCOL")]
+    pub(super) proxy_protocol: bool,
+
+    /// Allow client CIDR, use ',' to separate, e.g. 10.0.0.0/8,192.168.0.0/16
+    #[clap(long, env = "ALLOW_CIDRS", value_parser = parse::parse_cidrs)]
+    pub(super) allow_cidrs: Option<std::vec::Vec<cidr::IpCidr>>,
+
+    /// Deny client CIDR, use ',' to separate
+    #[clap(long, env = "DENY_CIDRS", value_parser = parse::parse_cidrs)]
+    pub(super) deny_cidrs: Option<std::vec::Vec<cidr::IpCidr>>,
+
+    /// Per route group CIDR rules file, hot reloaded on change.
+    /// One rule per line: allow|deny [api|webui|har|arkose] <cidr>
+    #[clap(long, env = "CIDR_FILE", verbatim_doc_comment)]
+    pub(super) cidr_file: Option<PathBuf>,
+
     /// Arkose endpoint, e.g. https://client-api.arkoselabs.com
     #[clap(long, value_parser = parse::parse_url)]
     pub(super) arkose_endpoint: Option<String>,
@@ -181,6 +274,10 @@ pub struct ServeArgs {
     #[clap(short = 'S', long, default_value = "false")]
     pub(super) arkose_gpt3_experiment_solver: bool,
 
+    /// Generate a random browser fingerprint per identity / egress for Arkose
+    #[clap(long, default_value = "false")]
+    pub(super) arkose_random_fingerprint: bool,
+
     /// About the browser HAR directory path requested by ArkoseLabs
     #[clap(long, value_parser = parse::parse_dir_path)]
     pub(super) arkose_har_dir: Option<PathBuf>,
@@ -206,6 +303,22 @@ pub struct ServeArgs {
     #[clap(long, default_value = "1", requires = "arkose_solver_key")]
     pub(super) arkose_solver_limit: usize,
 
+    /// About the solver concurrent challenges limit by ArkoseLabs, 0 means unlimited
+    #[clap(long, default_value = "0", requires = "arkose_solver_key")]
+    pub(super) arkose_solver_concurrency: usize,
+
+    /// About the solver tasks per minute limit by ArkoseLabs, 0 means unlimited
+    #[clap(long, default_value = "0", requires = "arkose_solver_key")]
+    pub(super) arkose_solver_tasks_per_minute: u64,
+
+    /// About the solver tasks per day limit by ArkoseLabs, 0 means unlimited
+    #[clap(long, default_value = "0", requires = "arkose_solver_key")]
+    pub(super) arkose_solver_tasks_per_day: u64,
+
+    /// Generic solver request template and response paths, only set in the config file
+    #[clap(skip)]
+    pub(super) arkose_solver_generic: Option<GenericSolverConfig>,
+
     /// About the solver tguess endpoint by ArkoseLabs
     #[clap(long, value_parser = parse::parse_url)]
     pub(super) arkose_solver_tguess_endpoint: Option<String>,
@@ -214,6 +327,26 @@ pub struct ServeArgs {
     #[clap(long, value_parser = parse::parse_dir_path)]
     pub(super) arkose_solver_image_dir: Option<PathBuf>,
 
+    /// Pre-generated arkose tokens kept per type, 0 means disabled
+    #[clap(long, env = "ARKOSE_POOL_SIZE", default_value = "0")]
+    pub(super) arkose_pool_size: usize,
+
+    /// Pre-generated arkose token time to live (seconds)
+    #[clap(long, env = "ARKOSE_POOL_TTL", default_value = "120")]
+    pub(super) arkose_pool_ttl: u64,
+
+    /// Arkose types prefilled when the pool starts, separate multiple ones with "," (default: gpt4)
+    #[clap(long, env = "ARKOSE_POOL_TYPES", value_parser = parse::parse_arkose_types)]
+    pub(super) arkose_pool_types: Option<std::vec::Vec<openai::arkose::Type>>,
+
+    /// Arkose public keys registered in addition to the built-in keys, only set in the config file
+    #[clap(skip)]
+    pub(super) arkose_public_keys: Option<Vec<PublicKey>>,
+
+    /// Webhook posted when the arkose enforcement version changes
+    #[clap(long, value_parser = parse::parse_url)]
+    pub(super) arkose_version_webhook: Option<String>,
+
     /// Enable token bucket flow limitation
     #[clap(short = 'T', long)]
     #[cfg(feature = "limit")]
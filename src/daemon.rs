@@ -4,10 +4,25 @@ use crate::{
     args::{self, ServeArgs},
     utils::unix::fix_relative_path,
 };
-use clap::CommandFactory;
-use openai::{arkose::funcaptcha::solver::ArkoseSolver, context::args::Args, proxy, serve::Serve};
+use openai::{
+    arkose::{
+        self,
+        funcaptcha::{
+            dataset,
+            solver::{ArkoseSolver, SolverBudget},
+        },
+    },
+    context::args::{Args, ConfigLoader},
+    proxy,
+    serve::Serve,
+};
 use reqwest::impersonate::Impersonate;
-use std::{net::IpAddr, ops::Not, path::PathBuf, str::FromStr};
+use std::{
+    net::IpAddr,
+    ops::Not,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 use url::Url;
 
 pub(super) fn serve(mut args: ServeArgs, relative_path: bool) -> anyhow::Result<()> {
@@ -15,22 +30,11 @@ pub(super) fn serve(mut args: ServeArgs, relative_path: bool) -> anyhow::Result<
         fix_relative_path(&mut args);
     }
 
-    if let Some(config_path) = args.config {
-        let bytes = std::fs::read(config_path)?;
-        let data = String::from_utf8(bytes)?;
-        args = toml::from_str::<ServeArgs>(&data)?;
+    let config_path = args.config.clone();
+    if let Some(config_path) = config_path.as_ref() {
+        args = read_config(config_path)?;
     }
 
-    let arkose_solver = match args.arkose_solver_key.as_ref() {
-        Some(client_key) => Some(ArkoseSolver::new(
-            args.arkose_solver,
-            client_key.clone(),
-            args.arkose_solver_endpoint,
-            args.arkose_solver_limit,
-        )),
-        None => None,
-    };
-
     #[cfg(target_os = "linux")]
     if let Some(ref proxies) = args.proxies {
         proxies.iter().for_each(|p| {
@@ -49,10 +53,43 @@ pub(super) fn serve(mut args: ServeArgs, relative_path: bool) -> anyhow::Result<
     }
 
     // Set the log level
-    std::env::set_var("RUST_LOG", args.level);
+    std::env::set_var("RUST_LOG", &args.level);
+
+    // Watch the config file, reloaded settings are parsed the same way
+    let config = config_path
+        .map(|path| ConfigLoader::new(path, |path| build_args(read_config(path)?, None)));
+
+    Serve::new(build_args(args, config)?).run()
+}
+
+/// Read the toml config file
+fn read_config(path: &Path) -> anyhow::Result<ServeArgs> {
+    let bytes = std::fs::read(path)?;
+    let data = String::from_utf8(bytes)?;
+    Ok(toml::from_str::<ServeArgs>(&data)?)
+}
+
+/// Build the server args from the command line or config file args
+fn build_args(args: ServeArgs, config: Option<ConfigLoader>) -> anyhow::Result<Args> {
+    let arkose_solver = match args.arkose_solver_key.as_ref() {
+        Some(client_key) => Some(ArkoseSolver::new(
+            args.arkose_solver,
+            client_key.clone(),
+            args.arkose_solver_endpoint,
+            args.arkose_solver_limit,
+            SolverBudget {
+                concurrency: args.arkose_solver_concurrency,
+                tasks_per_minute: args.arkose_solver_tasks_per_minute,
+                tasks_per_day: args.arkose_solver_tasks_per_day,
+            },
+            args.arkose_solver_generic,
+        )?),
+        None => None,
+    };
 
     let builder = Args::builder()
         .bind(args.bind)
+        .log_format(args.log_format)
         .fastest_dns(args.fastest_dns)
         .proxies(args.proxies.unwrap_or_default())
         .enable_direct(args.enable_direct)
@@ -63,25 +100,45 @@ pub(super) fn serve(mut args: ServeArgs, relative_path: bool) -> anyhow::Result<
         .timeout(args.timeout)
         .connect_timeout(args.connect_timeout)
         .concurrent_limit(args.concurrent_limit)
+        .conv_limit(args.conv_limit)
+        .conv_queue_timeout(args.conv_queue_timeout)
+        .transcript_dir(args.transcript_dir)
+        .transcript_retention(args.transcript_retention)
         .tls_cert(args.tls_cert)
         .tls_key(args.tls_key)
         .auth_key(args.auth_key)
         .visitor_email_whitelist(args.visitor_email_whitelist)
+        .metrics_bind(args.metrics_bind)
+        .trusted_proxies(args.trusted_proxies.unwrap_or_default())
+        .proxy_protocol(args.proxy_protocol)
+        .allow_cidrs(args.allow_cidrs.unwrap_or_default())
+        .deny_cidrs(args.deny_cidrs.unwrap_or_default())
+        .cidr_file(args.cidr_file)
         .cf_site_key(args.cf_site_key)
         .cf_secret_key(args.cf_secret_key)
         .enable_webui(args.enable_webui)
         .arkose_endpoint(args.arkose_endpoint)
         .arkose_gpt3_experiment(args.arkose_gpt3_experiment)
         .arkose_gpt3_experiment_solver(args.arkose_gpt3_experiment_solver)
+        .arkose_random_fingerprint(args.arkose_random_fingerprint)
         .arkose_solver(arkose_solver)
         .arkose_solver_tguess_endpoint(args.arkose_solver_tguess_endpoint)
         .arkose_solver_image_dir(args.arkose_solver_image_dir)
+        .arkose_pool_size(args.arkose_pool_size)
+        .arkose_pool_ttl(args.arkose_pool_ttl)
+        .arkose_pool_types(
+            args.arkose_pool_types
+                .unwrap_or_else(|| vec![arkose::Type::GPT4]),
+        )
+        .arkose_public_keys(args.arkose_public_keys.unwrap_or_default())
+        .arkose_version_webhook(args.arkose_version_webhook)
         .enable_file_proxy(args.enable_file_proxy)
         .enable_arkose_proxy(args.enable_arkose_proxy)
         .pbind(args.pbind)
         .pupstream(args.pupstream)
         .pcert(args.pcert)
-        .pkey(args.pkey);
+        .pkey(args.pkey)
+        .config(config);
 
     #[cfg(feature = "limit")]
     let builder = builder
@@ -91,6 +148,9 @@ pub(super) fn serve(mut args: ServeArgs, relative_path: bool) -> anyhow::Result<
         .tb_fill_rate(args.tb_fill_rate)
         .tb_expired(args.tb_expired);
 
+    #[cfg(feature = "otel")]
+    let builder = builder.otlp_endpoint(args.otlp_endpoint);
+
     // Parse the impersonate user agents
     if let Some(impersonate_list) = args.impersonate_uas {
         let mut impersonate_uas: Vec<Impersonate> = Vec::new();
@@ -99,21 +159,13 @@ pub(super) fn serve(mut args: ServeArgs, relative_path: bool) -> anyhow::Result<
                 Ok(impersonate) => {
                     impersonate_uas.push(impersonate);
                 }
-                Err(_) => {
-                    let mut cmd = args::cmd::Opt::command();
-                    cmd.error(
-                        clap::error::ErrorKind::ArgumentConflict,
-                        &format!("Unsupport impersonate user agent: {}", ua),
-                    )
-                    .exit();
-                }
+                Err(_) => anyhow::bail!("Unsupport impersonate user agent: {}", ua),
             }
         }
 
-        let args = builder.impersonate_uas(impersonate_uas).build();
-        Serve::new(args).run()
+        Ok(builder.impersonate_uas(impersonate_uas).build())
     } else {
-        Serve::new(builder.build()).run()
+        Ok(builder.build())
     }
 }
 
@@ -275,6 +327,8 @@ pub(super) fn generate_template(out: Option<PathBuf>) -> anyhow::Result<()> {
     let args = args::ServeArgs {
         bind: Some("0.0.0.0:7999".parse()?),
         concurrent_limit: 65535,
+        conv_queue_timeout: 30,
+        transcript_retention: 30,
         timeout: 600,
         connect_timeout: 60,
         tcp_keepalive: 60,
@@ -286,7 +340,9 @@ pub(super) fn generate_template(out: Option<PathBuf>) -> anyhow::Result<()> {
         cookie_store: true,
         pool_idle_timeout: 90,
         arkose_solver_limit: 3,
+        arkose_pool_ttl: 120,
         level: "info".to_owned(),
+        log_format: "text".to_owned(),
         pcert: PathBuf::from("ca/cert.crt"),
         pkey: PathBuf::from("ca/key.pem"),
         arkose_gpt3_experiment: false,
@@ -319,3 +375,17 @@ pub(super) fn generate_template(out: Option<PathBuf>) -> anyhow::Result<()> {
     }
     Ok(())
 }
+
+pub(super) fn export_dataset(
+    dir: PathBuf,
+    out: PathBuf,
+    format: dataset::Format,
+    accepted_only: bool,
+) -> anyhow::Result<()> {
+    let summary = dataset::export(&dir, &out, format, accepted_only)?;
+    println!("Exported {} samples to {}", summary.exported, out.display());
+    if summary.skipped > 0 {
+        println!("Skipped {} images with malformed labels", summary.skipped);
+    }
+    Ok(())
+}
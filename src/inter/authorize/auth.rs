@@ -50,7 +50,7 @@ async fn sign_in() -> anyhow::Result<()> {
     })
     .await??;
 
-    let (username, password, mfa_res) = tokio::task::spawn_blocking(move || {
+    let (username, password, mfa_res, totp_res) = tokio::task::spawn_blocking(move || {
         let username = Text::new("Email ›")
             .with_render_config(render_config())
             .with_validator(required!("email is required"))
@@ -72,7 +72,16 @@ async fn sign_in() -> anyhow::Result<()> {
             .with_help_message("OpenAI account MFA Code, If it is empty, please enter directly.")
             .prompt_skippable();
 
-        (username, password, mfa_res)
+        let totp_res = Password::new("TOTP Secret [Option] ›")
+            .with_render_config(render_config())
+            .with_display_mode(PasswordDisplayMode::Masked)
+            .with_help_message(
+                "Base32 TOTP secret, generates the MFA Code when it is empty, If it is empty, please enter directly.",
+            )
+            .without_confirmation()
+            .prompt_skippable();
+
+        (username, password, mfa_res, totp_res)
     })
     .await?;
 
@@ -85,6 +94,13 @@ async fn sign_in() -> anyhow::Result<()> {
         })
         .unwrap_or(None);
 
+    let totp_secret = totp_res
+        .map_err(|_| {
+            println!("An error happened when asking for your totp secret, try again later.");
+        })
+        .unwrap_or(None)
+        .filter(|s| !s.is_empty());
+
     let store = Context::get_account_store().await;
     let client = Context::get_auth_client().await;
 
@@ -96,6 +112,7 @@ async fn sign_in() -> anyhow::Result<()> {
                 .username(username.clone())
                 .password(password.clone())
                 .mfa(mfa_code.clone())
+                .totp_secret(totp_secret.clone())
                 .option(auth_strategy.clone())
                 .build();
 
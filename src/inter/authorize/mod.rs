@@ -57,7 +57,7 @@ pub async fn login_prompt(auth_strategy: Option<AuthStrategy>) -> anyhow::Result
         .await??
     };
 
-    let (username, password, mfa_res) = tokio::task::spawn_blocking(move || {
+    let (username, password, mfa_res, totp_res) = tokio::task::spawn_blocking(move || {
         let username = Text::new("Email ›")
             .with_render_config(render_config())
             .with_validator(required!("email is required"))
@@ -79,7 +79,16 @@ pub async fn login_prompt(auth_strategy: Option<AuthStrategy>) -> anyhow::Result
             .with_help_message("OpenAI account MFA Code, If it is empty, please enter directly.")
             .prompt_skippable();
 
-        (username, password, mfa_res)
+        let totp_res = Password::new("TOTP Secret [Option] ›")
+            .with_render_config(render_config())
+            .with_display_mode(PasswordDisplayMode::Masked)
+            .with_help_message(
+                "Base32 TOTP secret, generates the MFA Code when it is empty, If it is empty, please enter directly.",
+            )
+            .without_confirmation()
+            .prompt_skippable();
+
+        (username, password, mfa_res, totp_res)
     })
     .await?;
 
@@ -92,10 +101,18 @@ pub async fn login_prompt(auth_strategy: Option<AuthStrategy>) -> anyhow::Result
         })
         .unwrap_or(None);
 
+    let totp_secret = totp_res
+        .map_err(|_| {
+            println!("An error happened when asking for your totp secret, try again later.");
+        })
+        .unwrap_or(None)
+        .filter(|s| !s.is_empty());
+
     let auth_account = AuthAccount::builder()
         .username(username)
         .password(password)
         .mfa(mfa_code)
+        .totp_secret(totp_secret)
         .option(auth_strategy)
         .build();
 
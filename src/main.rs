@@ -35,6 +35,7 @@ fn main() -> anyhow::Result<()> {
             }
             args::ServeSubcommand::UA => print_ua_help(),
             args::ServeSubcommand::GT { out } => daemon::generate_template(out)?,
+            args::ServeSubcommand::Arkose(commands) => arkose_command(commands)?,
             args::ServeSubcommand::Update => update::update()?,
         }
     }
@@ -62,6 +63,7 @@ fn main() -> anyhow::Result<()> {
                 args::ServeSubcommand::GT { out } => daemon::generate_template(out)?,
                 args::ServeSubcommand::Update => update::update()?,
             },
+            SubCommands::Arkose(commands) => arkose_command(commands)?,
             SubCommands::Terminal => {
                 let runtime = tokio::runtime::Builder::new_multi_thread()
                     .enable_all()
@@ -77,6 +79,17 @@ fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+fn arkose_command(commands: args::ArkoseSubcommand) -> anyhow::Result<()> {
+    match commands {
+        args::ArkoseSubcommand::Dataset(args::DatasetSubcommand::Export {
+            dir,
+            out,
+            format,
+            accepted_only,
+        }) => daemon::export_dataset(dir, out, format, accepted_only),
+    }
+}
+
 use reqwest::impersonate::Impersonate;
 
 struct AgentImpersonate(Impersonate);
@@ -104,6 +104,37 @@ pub fn parse_email_whitelist(s: &str) -> anyhow::Result<Vec<String>> {
     Ok(emails)
 }
 
+/// parse cidr list
+/// format: cidr1,cidr2,cidr3
+pub fn parse_cidrs(s: &str) -> anyhow::Result<Vec<cidr::IpCidr>> {
+    let split = s.split(',');
+    let mut cidrs: Vec<_> = vec![];
+
+    for ele in split {
+        let cidr = ele.trim();
+        if cidr.is_empty() {
+            continue;
+        }
+
+        match cidr.parse::<cidr::IpCidr>() {
+            Ok(cidr) => cidrs.push(cidr),
+            Err(_) => anyhow::bail!("Invalid cidr format: {}", cidr),
+        }
+    }
+
+    Ok(cidrs)
+}
+
+/// parse arkose type list
+/// format: gpt3,gpt4,auth
+pub fn parse_arkose_types(s: &str) -> anyhow::Result<Vec<openai::arkose::Type>> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(openai::arkose::Type::from_str)
+        .collect()
+}
+
 // parse impersonate user-agent
 pub fn parse_impersonate_uas(s: &str) -> anyhow::Result<Vec<String>> {
     let split = s.split(',');